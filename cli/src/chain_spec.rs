@@ -348,6 +348,7 @@ pub fn testnet_genesis(
         treasury: Default::default(),
         vesting: Default::default(),
         gilt: Default::default(),
+        permastore: Default::default(),
     }
 }
 
@@ -16,9 +16,19 @@
 // You should have received a copy of the GNU General Public License
 // along with Canyon. If not, see <http://www.gnu.org/licenses/>.
 
+use std::net::SocketAddr;
+
 use structopt::StructOpt;
 
-use sc_cli::{KeySubcommand, RunCmd, SignCmd, VanityCmd, VerifyCmd};
+use sc_cli::{
+    CliConfiguration, DatabaseParams, KeySubcommand, PruningParams, RunCmd, SharedParams, SignCmd,
+    VanityCmd, VerifyCmd,
+};
+use sp_core::crypto::Ss58Codec;
+
+use canyon_primitives::{AccountId, BlockNumber};
+
+use crate::service::StorageSelection;
 
 /// An overarching CLI command definition.
 #[derive(Debug, StructOpt)]
@@ -29,6 +39,76 @@ pub struct Cli {
     #[allow(missing_docs)]
     #[structopt(flatten)]
     pub run: RunCmd,
+
+    /// Account of the PoA miner in SS58 format, which must be the block author.
+    ///
+    /// Required for authoring blocks in the SPoRA mining mode of PoA.
+    #[structopt(long, parse(try_from_str = parse_account_id))]
+    pub poa_miner: Option<AccountId>,
+
+    /// Serve the locally stored transaction data over HTTP at the given address.
+    ///
+    /// The data is available at `/data/{chunk_root}` and `/tx/{block}/{extrinsic_index}`.
+    #[structopt(long, value_name = "ADDR")]
+    pub data_gateway: Option<SocketAddr>,
+
+    /// Maximum total size of the transaction data stored locally, e.g. `500GiB`.
+    ///
+    /// Once the weave exceeds the size, only the weave ranges selected by
+    /// `--storage-selection` are stored, the rest is pruned. It must be at least the
+    /// size of a weave range, i.e., 1GiB.
+    #[structopt(long, value_name = "SIZE", parse(try_from_str = parse_storage_max_size))]
+    pub storage_max_size: Option<u64>,
+
+    /// Strategy of selecting the weave ranges stored within `--storage-max-size`.
+    ///
+    /// `random` stores the ranges picked pseudo-randomly by the node, `near-account`
+    /// stores the ranges closest to the account of `--poa-miner`, `newest` stores the
+    /// latest ranges.
+    #[structopt(
+        long,
+        value_name = "STRATEGY",
+        default_value = "newest",
+        possible_values = &["random", "near-account", "newest"],
+    )]
+    pub storage_selection: StorageSelection,
+}
+
+fn parse_account_id(s: &str) -> Result<AccountId, String> {
+    AccountId::from_ss58check(s).map_err(|e| format!("Invalid SS58 account {}: {:?}", s, e))
+}
+
+/// Parses a byte size with an optional binary unit suffix, e.g. `1024`, `500GiB`.
+fn parse_size(s: &str) -> Result<u64, String> {
+    let units: &[(&str, u64)] = &[
+        ("TiB", 1 << 40),
+        ("GiB", 1 << 30),
+        ("MiB", 1 << 20),
+        ("KiB", 1 << 10),
+    ];
+    let (number, unit) = units
+        .iter()
+        .find_map(|&(suffix, unit)| s.strip_suffix(suffix).map(|number| (number, unit)))
+        .unwrap_or((s, 1));
+    number
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .and_then(|number| number.checked_mul(unit))
+        .ok_or_else(|| format!("Invalid size {}", s))
+}
+
+/// Parses the storage budget, which must fit at least one weave range.
+fn parse_storage_max_size(s: &str) -> Result<u64, String> {
+    let max_size = parse_size(s)?;
+    if max_size < cc_datastore::WEAVE_RANGE_SIZE {
+        return Err(format!(
+            "Storage max size {} is smaller than a weave range of {} bytes",
+            s,
+            cc_datastore::WEAVE_RANGE_SIZE
+        ));
+    }
+    Ok(max_size)
 }
 
 /// Possible subcommands of the main binary.
@@ -82,4 +162,60 @@ pub enum Subcommand {
 
     /// Revert the chain to a previous state.
     Revert(sc_cli::RevertCmd),
+
+    /// Manage the local datastore of transaction data.
+    #[structopt(name = "datastore")]
+    Datastore(DatastoreCmd),
+}
+
+/// Subcommands managing the local datastore of transaction data.
+#[derive(Debug, StructOpt)]
+pub enum DatastoreCmd {
+    /// Verify the stored transaction data against the on-chain chunk roots.
+    Verify(DatastoreVerifyCmd),
+}
+
+/// The `datastore verify` command.
+#[derive(Debug, StructOpt)]
+pub struct DatastoreVerifyCmd {
+    /// Number of the first block to verify.
+    #[structopt(long, value_name = "BLOCK", default_value = "0")]
+    pub from: BlockNumber,
+
+    /// Number of the last block to verify, the best finalized block if not given.
+    #[structopt(long, value_name = "BLOCK")]
+    pub to: Option<BlockNumber>,
+
+    /// Quarantine the corrupted transaction data, which is then fetched from peers
+    /// again once the node is started.
+    ///
+    /// Nothing is written to the datastore without it.
+    #[structopt(long)]
+    pub quarantine: bool,
+
+    #[allow(missing_docs)]
+    #[structopt(flatten)]
+    pub shared_params: SharedParams,
+
+    #[allow(missing_docs)]
+    #[structopt(flatten)]
+    pub pruning_params: PruningParams,
+
+    #[allow(missing_docs)]
+    #[structopt(flatten)]
+    pub database_params: DatabaseParams,
+}
+
+impl CliConfiguration for DatastoreVerifyCmd {
+    fn shared_params(&self) -> &SharedParams {
+        &self.shared_params
+    }
+
+    fn pruning_params(&self) -> Option<&PruningParams> {
+        Some(&self.pruning_params)
+    }
+
+    fn database_params(&self) -> Option<&DatabaseParams> {
+        Some(&self.database_params)
+    }
 }
@@ -17,13 +17,14 @@
 // along with Canyon. If not, see <http://www.gnu.org/licenses/>.
 
 use sc_cli::{ChainSpec, Result, Role, RuntimeVersion, SubstrateCli};
+use sc_client_api::HeaderBackend;
 use sc_service::PartialComponents;
 
 use canyon_executor::ExecutorDispatch;
 use canyon_runtime::{Block, RuntimeApi};
 
 use crate::service::new_partial;
-use crate::{chain_spec, service, Cli, Subcommand};
+use crate::{chain_spec, service, Cli, DatastoreCmd, Subcommand};
 
 impl SubstrateCli for Cli {
     fn impl_name() -> String {
@@ -80,10 +81,16 @@ pub fn run() -> Result<()> {
     match &cli.subcommand {
         None => {
             let runner = cli.create_runner(&cli.run)?;
+            let canyon_config = service::CanyonConfiguration {
+                poa_miner: cli.poa_miner.clone(),
+                data_gateway: cli.data_gateway,
+                storage_max_size: cli.storage_max_size,
+                storage_selection: cli.storage_selection,
+            };
             runner.run_node_until_exit(|config| async move {
                 match config.role {
                     Role::Light => service::new_light(config),
-                    _ => service::new_full(config),
+                    _ => service::new_full(config, canyon_config),
                 }
                 .map_err(sc_cli::Error::Service)
             })
@@ -162,6 +169,53 @@ pub fn run() -> Result<()> {
             let runner = cli.create_runner(cmd)?;
             runner.sync_run(|config| cmd.run(config.database))
         }
+        Some(Subcommand::Datastore(DatastoreCmd::Verify(cmd))) => {
+            let runner = cli.create_runner(cmd)?;
+            runner.sync_run(|config| {
+                let PartialComponents {
+                    client,
+                    other: (_, _, _, _, perma_storage),
+                    ..
+                } = new_partial(&config)?;
+
+                let to = cmd.to.unwrap_or_else(|| client.info().finalized_number);
+                let scrubber =
+                    cc_datastore::Scrubber::new(perma_storage.with_client(client), None);
+                let report = scrubber
+                    .scrub(cmd.from, to, cmd.quarantine)
+                    .map_err(|e| format!("Failed to verify the datastore: {}", e))?;
+
+                for corrupted in &report.corrupted {
+                    println!(
+                        "Block #{}, extrinsic {}: {} is corrupted: {}",
+                        corrupted.block_number,
+                        corrupted.extrinsic_index,
+                        corrupted.chunk_root,
+                        corrupted.corruption,
+                    );
+                }
+                println!(
+                    "Verified blocks #{}..=#{}, intact: {} ({} bytes), corrupted: {}{}, missing: {}",
+                    cmd.from,
+                    to,
+                    report.intact_data,
+                    report.intact_bytes,
+                    report.corrupted.len(),
+                    if cmd.quarantine && !report.corrupted.is_empty() {
+                        " (quarantined)"
+                    } else {
+                        ""
+                    },
+                    report.missing_data,
+                );
+
+                if report.corrupted.is_empty() {
+                    Ok(())
+                } else {
+                    Err("Corrupted transaction data found in the datastore".into())
+                }
+            })
+        }
         Some(Subcommand::Revert(cmd)) => {
             let runner = cli.create_runner(cmd)?;
             runner.async_run(|config| {
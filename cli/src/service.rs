@@ -20,6 +20,8 @@
 
 //! Service implementation. Specialized wrapper over substrate service.
 
+use std::net::SocketAddr;
+use std::str::FromStr;
 use std::sync::Arc;
 
 use futures::prelude::*;
@@ -30,10 +32,11 @@ use sc_executor::NativeElseWasmExecutor;
 use sc_network::{Event, NetworkService};
 use sc_service::{config::Configuration, error::Error as ServiceError, RpcHandlers, TaskManager};
 use sc_telemetry::{Telemetry, TelemetryWorker};
+use sp_core::hashing::blake2_256;
 use sp_runtime::traits::Block as BlockT;
 
 use canyon_executor::ExecutorDispatch;
-use canyon_primitives::Block;
+use canyon_primitives::{AccountId, Block};
 use canyon_runtime::RuntimeApi;
 
 type FullClient =
@@ -45,12 +48,51 @@ type FullGrandpaBlockImport =
 type LightClient =
     sc_service::TLightClient<Block, RuntimeApi, NativeElseWasmExecutor<ExecutorDispatch>>;
 
-type FullPoaBlockImport = cc_consensus_poa::PurePoaBlockImport<
-    Block,
-    FullGrandpaBlockImport,
-    FullClient,
-    FullSelectChain,
->;
+type FullPoaBlockImport =
+    cc_consensus_poa::PurePoaBlockImport<Block, FullGrandpaBlockImport, FullClient>;
+
+/// Canyon-specific configuration of the node.
+#[derive(Debug, Clone, Default)]
+pub struct CanyonConfiguration {
+    /// Account of the PoA miner, required in the SPoRA mining mode.
+    pub poa_miner: Option<AccountId>,
+    /// Address of the HTTP gateway serving the stored transaction data, if enabled.
+    pub data_gateway: Option<SocketAddr>,
+    /// Maximum total byte size of the transaction data stored locally, unlimited if `None`.
+    pub storage_max_size: Option<u64>,
+    /// Strategy of selecting the weave ranges stored within `storage_max_size`.
+    pub storage_selection: StorageSelection,
+}
+
+/// Strategy of selecting the weave ranges stored locally, see [`cc_datastore::RangeSelection`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageSelection {
+    /// Ranges in a pseudo-random order seeded by the local peer id.
+    Random,
+    /// Ranges closest to the account of the PoA miner.
+    NearAccount,
+    /// The latest ranges first.
+    Newest,
+}
+
+impl Default for StorageSelection {
+    fn default() -> Self {
+        Self::Newest
+    }
+}
+
+impl FromStr for StorageSelection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "random" => Ok(Self::Random),
+            "near-account" => Ok(Self::NearAccount),
+            "newest" => Ok(Self::Newest),
+            _ => Err(format!("Unknown storage selection: {}", s)),
+        }
+    }
+}
 
 #[allow(clippy::type_complexity)]
 pub fn new_partial(
@@ -72,8 +114,12 @@ pub fn new_partial(
                 grandpa::LinkHalf<Block, FullClient, FullSelectChain>,
                 sc_consensus_babe::BabeLink<Block>,
             ),
-            grandpa::SharedVoterState,
+            (
+                grandpa::SharedVoterState,
+                cc_network::SharedBackfillProgress,
+            ),
             Option<Telemetry>,
+            cc_datastore::PermanentStorage<()>,
         ),
     >,
     ServiceError,
@@ -103,6 +149,15 @@ pub fn new_partial(
         )?;
     let client = Arc::new(client);
 
+    let perma_storage = open_perma_storage(config, &backend)?;
+
+    // Make the local transaction data accessible to the runtime in the transaction pool.
+    client
+        .execution_extensions()
+        .set_extensions_factory(Box::new(cc_datastore::PermastoreExtensionsFactory::new(
+            perma_storage.clone(),
+        )));
+
     let telemetry = telemetry.map(|(worker, telemetry)| {
         task_manager.spawn_handle().spawn("telemetry", worker.run());
         telemetry
@@ -126,11 +181,8 @@ pub fn new_partial(
     )?;
     let justification_import = grandpa_block_import.clone();
 
-    let poa_block_import = cc_consensus_poa::PurePoaBlockImport::new(
-        grandpa_block_import,
-        client.clone(),
-        select_chain.clone(),
-    );
+    let poa_block_import =
+        cc_consensus_poa::PurePoaBlockImport::new(grandpa_block_import, client.clone());
 
     let (block_import, babe_link) = sc_consensus_babe::block_import(
         sc_consensus_babe::Config::get_or_compute(&*client)?,
@@ -173,7 +225,8 @@ pub fn new_partial(
         let justification_stream = grandpa_link.justification_stream();
         let shared_authority_set = grandpa_link.shared_authority_set().clone();
         let shared_voter_state = grandpa::SharedVoterState::empty();
-        let rpc_setup = shared_voter_state.clone();
+        let backfill = cc_network::SharedBackfillProgress::default();
+        let rpc_setup = (shared_voter_state.clone(), backfill.clone());
 
         let finality_proof_provider = grandpa::FinalityProofProvider::new_for_service(
             backend.clone(),
@@ -189,9 +242,7 @@ pub fn new_partial(
         let keystore = keystore_container.sync_keystore();
         let chain_spec = config.chain_spec.cloned_box();
 
-        let offchain_storage = backend
-            .offchain_storage()
-            .unwrap_or_else(|| panic!("offchain storage is some; qed"));
+        let perma_storage = perma_storage.clone();
 
         let spawn_handle = task_manager.spawn_handle();
         let rpc_extensions_builder = move |deny_unsafe, subscription_executor| {
@@ -213,10 +264,8 @@ pub fn new_partial(
                     subscription_executor,
                     finality_provider: finality_proof_provider.clone(),
                 },
-                perma_storage: cc_datastore::PermanentStorage::new(
-                    offchain_storage.clone(),
-                    client.clone(),
-                ),
+                perma_storage: perma_storage.clone().with_client(client.clone()),
+                backfill: backfill.clone(),
             };
 
             use jsonrpc_pubsub::manager::SubscriptionManager;
@@ -245,10 +294,47 @@ pub fn new_partial(
         select_chain,
         import_queue,
         transaction_pool,
-        other: (rpc_extensions_builder, import_setup, rpc_setup, telemetry),
+        other: (
+            rpc_extensions_builder,
+            import_setup,
+            rpc_setup,
+            telemetry,
+            perma_storage,
+        ),
     })
 }
 
+/// Opens the permanent storage of transaction data, of which the chunk store is
+/// placed next to the database of client.
+fn open_perma_storage(
+    config: &Configuration,
+    backend: &FullBackend,
+) -> Result<cc_datastore::PermanentStorage<()>, ServiceError> {
+    let chunk_store_path = config
+        .database
+        .path()
+        .and_then(|path| path.parent())
+        .map(|path| path.join("permastore"))
+        .ok_or_else(|| ServiceError::Other("Database path is required by permastore".into()))?;
+
+    let chunk_store = cc_datastore::ChunkStore::open(&chunk_store_path).map_err(|e| {
+        ServiceError::Other(format!(
+            "Failed to open the chunk store at {}: {}",
+            chunk_store_path.display(),
+            e
+        ))
+    })?;
+
+    let offchain_storage = backend
+        .offchain_storage()
+        .unwrap_or_else(|| panic!("offchain storage is some; qed"));
+
+    Ok(
+        cc_datastore::PermanentStorage::new(chunk_store, Arc::new(()))
+            .with_legacy_storage(offchain_storage),
+    )
+}
+
 pub struct NewFullBase {
     pub task_manager: TaskManager,
     pub client: Arc<FullClient>,
@@ -259,6 +345,7 @@ pub struct NewFullBase {
 /// Creates a full service from the configuration.
 pub fn new_full_base(
     mut config: Configuration,
+    canyon_config: CanyonConfiguration,
     with_startup_data: impl FnOnce(
         &sc_consensus_babe::BabeBlockImport<Block, FullClient, FullPoaBlockImport>,
         &sc_consensus_babe::BabeLink<Block>,
@@ -272,15 +359,26 @@ pub fn new_full_base(
         keystore_container,
         select_chain,
         transaction_pool,
-        other: (rpc_extensions_builder, import_setup, rpc_setup, mut telemetry),
+        other: (rpc_extensions_builder, import_setup, rpc_setup, mut telemetry, perma_storage),
     } = new_partial(&config)?;
 
-    let shared_voter_state = rpc_setup;
+    let (shared_voter_state, backfill) = rpc_setup;
 
     config
         .network
         .extra_sets
         .push(grandpa::grandpa_peers_set_config());
+    config
+        .network
+        .extra_sets
+        .push(cc_network::announcement_peers_set_config());
+
+    let (chunk_request_handler, chunk_request_protocol_config) =
+        cc_network::ChunkRequestHandler::new(perma_storage.clone());
+    config
+        .network
+        .request_response_protocols
+        .push(chunk_request_protocol_config);
 
     let warp_sync = Arc::new(grandpa::warp_proof::NetworkProvider::new(
         backend.clone(),
@@ -299,6 +397,71 @@ pub fn new_full_base(
             warp_sync: Some(warp_sync),
         })?;
 
+    let selection = match canyon_config.storage_selection {
+        StorageSelection::Random => cc_datastore::RangeSelection::Random(
+            blake2_256(&network.local_peer_id().to_bytes()).into(),
+        ),
+        StorageSelection::NearAccount => {
+            let poa_miner: &[u8] = canyon_config
+                .poa_miner
+                .as_ref()
+                .map(AsRef::as_ref)
+                .ok_or_else(|| {
+                    ServiceError::Other(
+                        "`--poa-miner` is required by the near-account storage selection".into(),
+                    )
+                })?;
+            cc_datastore::RangeSelection::NearAccount(blake2_256(poa_miner).into())
+        }
+        StorageSelection::Newest => cc_datastore::RangeSelection::NewestFirst,
+    };
+    let perma_storage = perma_storage.with_storage_policy(cc_datastore::StoragePolicy {
+        max_size: canyon_config.storage_max_size,
+        selection,
+    });
+
+    // The permastore workers read the datastore and call the runtime API in a blocking
+    // manner, they are kept off the async executor.
+    task_manager
+        .spawn_handle()
+        .spawn_blocking("chunk-request-handler", chunk_request_handler.run());
+    task_manager.spawn_handle().spawn_blocking(
+        "permastore-sync",
+        cc_network::DataSync::new(network.clone(), perma_storage.clone()).run(Box::pin(
+            cc_network::imported_transaction_data(client.clone()),
+        )),
+    );
+    // The blocks storing the data quarantined by the scrubber are backfilled again.
+    let (corrupted_blocks, corrupted_blocks_rx) = futures::channel::mpsc::unbounded();
+    task_manager.spawn_handle().spawn_blocking(
+        "permastore-scrubber",
+        cc_datastore::Scrubber::new(
+            perma_storage.clone().with_client(client.clone()),
+            config.prometheus_registry(),
+        )
+        .run(corrupted_blocks),
+    );
+    task_manager.spawn_handle().spawn_blocking(
+        "permastore-backfill",
+        cc_network::Backfill::new(
+            cc_network::ClientDataSource::new(client.clone()),
+            network.clone(),
+            perma_storage.clone(),
+            backfill,
+            config.prometheus_registry(),
+        )
+        .with_rescan(corrupted_blocks_rx.map(u64::from).boxed())
+        .run(),
+    );
+    task_manager.spawn_handle().spawn_blocking(
+        "permastore-pruner",
+        cc_network::Pruner::new(
+            cc_network::ClientDataSource::new(client.clone()),
+            perma_storage.clone(),
+        )
+        .run(),
+    );
+
     if config.offchain_worker.enabled {
         sc_service::build_offchain_workers(
             &config,
@@ -316,10 +479,6 @@ pub fn new_full_base(
     let enable_grandpa = !config.disable_grandpa;
     let prometheus_registry = config.prometheus_registry().cloned();
 
-    let offchain_storage = backend
-        .offchain_storage()
-        .unwrap_or_else(|| panic!("offchain storage is some; qed"));
-
     let _rpc_handlers = sc_service::spawn_tasks(sc_service::SpawnTasksParams {
         config,
         backend,
@@ -339,6 +498,17 @@ pub fn new_full_base(
 
     (with_startup_data)(&block_import, &babe_link);
 
+    if let Some(addr) = canyon_config.data_gateway {
+        let perma_storage = perma_storage.clone().with_client(client.clone());
+        task_manager
+            .spawn_handle()
+            .spawn("data-gateway", async move {
+                if let Err(e) = cc_gateway::run::<Block, _>(addr, perma_storage).await {
+                    log::error!(target: "gateway", "Data gateway error: {:?}", e);
+                }
+            });
+    }
+
     if let sc_service::config::Role::Authority { .. } = &role {
         let proposer = sc_basic_authorship::ProposerFactory::new(
             task_manager.spawn_handle(),
@@ -352,6 +522,7 @@ pub fn new_full_base(
             sp_consensus::CanAuthorWithNativeVersion::new(client.executor().clone());
 
         let client_clone = client.clone();
+        let poa_miner = canyon_config.poa_miner;
         let slot_duration = babe_link.config().slot_duration();
         let babe_config = sc_consensus_babe::BabeParams {
             keystore: keystore_container.sync_keystore(),
@@ -364,8 +535,8 @@ pub fn new_full_base(
             create_inherent_data_providers: move |parent, ()| {
                 let client_clone = client_clone.clone();
                 let client_clone2 = client_clone.clone();
-                let client_clone3 = client_clone.clone();
-                let offchain_storage_clone = offchain_storage.clone();
+                let perma_storage = perma_storage.clone().with_client(client_clone.clone());
+                let poa_miner = poa_miner.clone();
                 async move {
                     let uncles = sc_consensus_uncles::create_uncles_inherent_data_provider(
                         &*client_clone,
@@ -382,7 +553,8 @@ pub fn new_full_base(
                     let poa = cc_consensus_poa::PoaInherentDataProvider::create(
                         client_clone2,
                         parent,
-                        cc_datastore::PermanentStorage::new(offchain_storage_clone, client_clone3),
+                        perma_storage,
+                        poa_miner,
                     )?;
 
                     Ok((timestamp, slot, uncles, poa))
@@ -483,8 +655,12 @@ pub fn new_full_base(
 }
 
 /// Builds a new service for a full client.
-pub fn new_full(config: Configuration) -> Result<TaskManager, ServiceError> {
-    new_full_base(config, |_, _| ()).map(|NewFullBase { task_manager, .. }| task_manager)
+pub fn new_full(
+    config: Configuration,
+    canyon_config: CanyonConfiguration,
+) -> Result<TaskManager, ServiceError> {
+    new_full_base(config, canyon_config, |_, _| ())
+        .map(|NewFullBase { task_manager, .. }| task_manager)
 }
 
 pub fn new_light_base(
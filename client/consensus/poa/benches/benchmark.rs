@@ -1,8 +1,10 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use rand::Rng;
 
+use sp_core::H256;
+
 use cc_consensus_poa::{ChunkProof, ChunkProofBuilder};
-use cp_permastore::CHUNK_SIZE;
+use cp_permastore::{chunk_hash, CHUNK_SIZE};
 
 fn generate_chunk_proof(data: Vec<u8>, offset: u32) -> ChunkProof {
     ChunkProofBuilder::new(data, CHUNK_SIZE, offset)
@@ -10,6 +12,16 @@ fn generate_chunk_proof(data: Vec<u8>, offset: u32) -> ChunkProof {
         .expect("failed to build chunk proof")
 }
 
+fn generate_chunk_proof_from_chunk_hashes(
+    chunk_hashes: Vec<H256>,
+    target_chunk: Vec<u8>,
+    chunk_index: u32,
+) -> ChunkProof {
+    ChunkProofBuilder::from_chunk_hashes(chunk_hashes, chunk_index, target_chunk)
+        .build()
+        .expect("failed to build chunk proof")
+}
+
 fn random_data(data_size: usize) -> Vec<u8> {
     let mut rng = rand::thread_rng();
     (0..data_size).map(|_| rng.gen::<u8>()).collect()
@@ -30,6 +42,21 @@ fn chunk_proof_benchmark(c: &mut Criterion) {
     c.bench_function("chunk proof generation 1GiB", |b| {
         b.iter(|| generate_chunk_proof(black_box(data.clone()), black_box(20)))
     });
+
+    let chunk_hashes = data
+        .chunks(CHUNK_SIZE as usize)
+        .map(chunk_hash)
+        .collect::<Vec<_>>();
+    let target_chunk = data[..CHUNK_SIZE as usize].to_vec();
+    c.bench_function("chunk proof generation 1GiB from chunk hashes", |b| {
+        b.iter(|| {
+            generate_chunk_proof_from_chunk_hashes(
+                black_box(chunk_hashes.clone()),
+                black_box(target_chunk.clone()),
+                black_box(0),
+            )
+        })
+    });
 }
 
 criterion_group!(benches, chunk_proof_benchmark);
@@ -17,11 +17,9 @@
 // along with Canyon. If not, see <http://www.gnu.org/licenses/>.
 
 use sp_core::H256;
-use sp_io::hashing::blake2_256;
-use sp_trie::TrieMut;
 
-use cp_consensus_poa::{encode_index, ChunkProof};
-use cp_permastore::{Hasher, TrieLayout, VerifyError};
+use cp_consensus_poa::ChunkProof;
+use cp_permastore::{chunk_hash, VerifyError};
 
 use crate::trie::TrieError;
 
@@ -53,81 +51,115 @@ pub fn verify_chunk_proof(
     chunk_index: u32,
     proof: &[Vec<u8>],
 ) -> Result<(), VerifyError> {
-    sp_trie::verify_trie_proof::<TrieLayout, _, _, _>(
-        chunk_root,
-        proof,
-        &[(encode_index(chunk_index), Some(blake2_256(&chunk)))],
-    )
+    cp_permastore::verify_chunk_proof(chunk_root, &chunk, chunk_index, proof)
 }
 
-/// A builder for creating a [`ChunkProof`] from the entire raw transaction data.
+/// Source of the chunks from which a [`ChunkProof`] is built.
+#[derive(Debug, Clone)]
+enum ChunkSource {
+    /// Raw bytes of entire transaction data and the size of per data chunk in bytes.
+    Data { data: Vec<u8>, chunk_size: u32 },
+    /// Hashes of all the data chunks and the raw bytes of the recall chunk.
+    ChunkHashes {
+        chunk_hashes: Vec<H256>,
+        target_chunk: Vec<u8>,
+    },
+}
+
+/// A builder for creating a [`ChunkProof`].
+///
+/// The builder can be constructed from the entire raw transaction data, from a
+/// stream of data chunks or from the precomputed chunk hashes. Only the latter
+/// two are suitable for the large transaction data as the entire data is never
+/// held in memory.
 #[derive(Debug, Clone)]
 pub struct ChunkProofBuilder {
-    /// Raw bytes of entire transaction data.
-    data: Vec<u8>,
-    /// Size of per data chunk in bytes.
-    chunk_size: u32,
+    /// Source of the data chunks.
+    source: ChunkSource,
     /// Index of the recall chunk.
     target_chunk_index: u32,
 }
 
 impl ChunkProofBuilder {
-    /// Constructs an instance of [`ChunkProofBuilder`].
+    /// Constructs an instance of [`ChunkProofBuilder`] from the entire transaction data.
     pub fn new(data: Vec<u8>, chunk_size: u32, transaction_data_offset: u32) -> Self {
         debug_assert!(chunk_size > 0);
 
         let target_chunk_index = transaction_data_offset / chunk_size;
 
         Self {
-            data,
-            chunk_size,
+            source: ChunkSource::Data { data, chunk_size },
             target_chunk_index,
         }
     }
 
-    /// Creates a [`ChunkProof`].
+    /// Constructs an instance of [`ChunkProofBuilder`] from the data chunks in order.
     ///
-    /// # Panics
-    ///
-    /// Panics if the building of chunks trie failed.
-    pub fn build(&self) -> Result<ChunkProof, TrieError> {
-        let mut target_chunk = Vec::with_capacity(self.chunk_size as usize);
-
-        let mut db = sp_trie::MemoryDB::<Hasher>::default();
-        let mut chunk_root = sp_trie::empty_trie_root::<TrieLayout>();
-
-        {
-            let mut trie = sp_trie::TrieDBMut::<TrieLayout>::new(&mut db, &mut chunk_root);
-
-            let chunks = self
-                .data
-                .chunks(self.chunk_size as usize)
-                .map(|c| c.to_vec());
-
-            for (index, chunk) in chunks.enumerate() {
-                // Build the trie using chunk id.
-                trie.insert(&encode_index(index as u32), &blake2_256(&chunk))
-                    .unwrap_or_else(|e| {
-                        panic!(
-                            "Failed to insert the trie node: {:?}, chunk index: {}",
-                            e, index
-                        )
-                    });
-
-                if index == self.target_chunk_index as usize {
-                    target_chunk = chunk;
-                }
+    /// Only the hashes of chunks and the recall chunk are kept in memory.
+    pub fn from_chunks<I, E>(chunks: I, target_chunk_index: u32) -> Result<Self, E>
+    where
+        I: IntoIterator<Item = Result<Vec<u8>, E>>,
+    {
+        let mut chunk_hashes = Vec::new();
+        let mut target_chunk = Vec::new();
+
+        for (index, chunk) in chunks.into_iter().enumerate() {
+            let chunk = chunk?;
+            chunk_hashes.push(chunk_hash(&chunk));
+            if index == target_chunk_index as usize {
+                target_chunk = chunk;
             }
+        }
 
-            trie.commit();
+        Ok(Self::from_chunk_hashes(
+            chunk_hashes,
+            target_chunk_index,
+            target_chunk,
+        ))
+    }
+
+    /// Constructs an instance of [`ChunkProofBuilder`] from the precomputed hashes
+    /// of all data chunks in order and the recall chunk `target_chunk`.
+    pub fn from_chunk_hashes(
+        chunk_hashes: Vec<H256>,
+        target_chunk_index: u32,
+        target_chunk: Vec<u8>,
+    ) -> Self {
+        Self {
+            source: ChunkSource::ChunkHashes {
+                chunk_hashes,
+                target_chunk,
+            },
+            target_chunk_index,
         }
+    }
 
-        let proof = sp_trie::generate_trie_proof::<TrieLayout, _, _, _>(
-            &db,
-            chunk_root,
-            &[encode_index(self.target_chunk_index)],
-        )
-        .map_err(|e| TrieError::Trie(Box::new(e)))?;
+    /// Creates a [`ChunkProof`].
+    pub fn build(&self) -> Result<ChunkProof, TrieError> {
+        match &self.source {
+            ChunkSource::Data { data, chunk_size } => {
+                let chunks = data.chunks(*chunk_size as usize);
+                let target_chunk = chunks
+                    .clone()
+                    .nth(self.target_chunk_index as usize)
+                    .map(|chunk| chunk.to_vec())
+                    .unwrap_or_default();
+                self.build_with(chunks.map(chunk_hash), target_chunk)
+            }
+            ChunkSource::ChunkHashes {
+                chunk_hashes,
+                target_chunk,
+            } => self.build_with(chunk_hashes.iter().copied(), target_chunk.clone()),
+        }
+    }
+
+    fn build_with(
+        &self,
+        chunk_hashes: impl Iterator<Item = H256>,
+        target_chunk: Vec<u8>,
+    ) -> Result<ChunkProof, TrieError> {
+        let proof = cp_permastore::generate_chunk_proof(chunk_hashes, self.target_chunk_index)
+            .map_err(|e| TrieError::Trie(Box::new(e)))?;
 
         Ok(ChunkProof {
             chunk: target_chunk,
@@ -156,4 +188,66 @@ mod tests {
         assert!(verify_chunk_proof(&chunk_root, b"l".to_vec(), 3, &chunk_proof.proof).is_ok());
         assert!(verify_chunk_proof(&chunk_root, b"l".to_vec(), 4, &chunk_proof.proof).is_err());
     }
+
+    #[test]
+    fn chunk_proof_should_verify_against_chunk_root() {
+        use cp_permastore::CHUNK_SIZE;
+
+        let data = (0..CHUNK_SIZE * 3 + 10)
+            .map(|i| i as u8)
+            .collect::<Vec<_>>();
+        let chunk_root = cp_permastore::chunk_root(&data);
+
+        for chunk_index in 0..4 {
+            let chunk_proof =
+                ChunkProofBuilder::new(data.clone(), CHUNK_SIZE, chunk_index * CHUNK_SIZE)
+                    .build()
+                    .unwrap();
+            assert!(ChunkProofVerifier::new(chunk_proof)
+                .verify(&chunk_root)
+                .is_ok());
+        }
+    }
+
+    #[test]
+    fn chunk_proof_builders_should_agree() {
+        let data = (0..1000u32).map(|i| i as u8).collect::<Vec<_>>();
+        let chunk_size = 64;
+
+        let chunks = data
+            .chunks(chunk_size)
+            .map(|chunk| Ok::<_, ()>(chunk.to_vec()))
+            .collect::<Vec<_>>();
+        let chunk_hashes = data.chunks(chunk_size).map(chunk_hash).collect::<Vec<_>>();
+
+        for &target_chunk_index in &[0u32, 7, 15] {
+            let expected = ChunkProofBuilder::new(
+                data.clone(),
+                chunk_size as u32,
+                target_chunk_index * chunk_size as u32,
+            )
+            .build()
+            .unwrap();
+
+            let from_chunks = ChunkProofBuilder::from_chunks(chunks.clone(), target_chunk_index)
+                .unwrap()
+                .build()
+                .unwrap();
+            assert_eq!(from_chunks, expected);
+
+            let target_chunk = data
+                .chunks(chunk_size)
+                .nth(target_chunk_index as usize)
+                .unwrap()
+                .to_vec();
+            let from_chunk_hashes = ChunkProofBuilder::from_chunk_hashes(
+                chunk_hashes.clone(),
+                target_chunk_index,
+                target_chunk,
+            )
+            .build()
+            .unwrap();
+            assert_eq!(from_chunk_hashes, expected);
+        }
+    }
 }
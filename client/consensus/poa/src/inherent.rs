@@ -24,6 +24,7 @@ use sp_runtime::traits::{Block as BlockT, NumberFor};
 
 use sc_client_api::BlockBackend;
 
+use canyon_primitives::AccountId;
 use cc_datastore::TransactionDataBackend as TransactionDataBackendT;
 use cp_consensus_poa::{PoaOutcome, POA_INHERENT_IDENTIFIER};
 
@@ -35,10 +36,13 @@ pub struct PoaInherentDataProvider {
 
 impl PoaInherentDataProvider {
     /// Creates a new instance of [`PoaInherentDataProvider`].
+    ///
+    /// `miner` is required in the SPoRA mining mode.
     pub fn create<Block, Client, TransactionDataBackend>(
         client: Arc<Client>,
         parent: Block::Hash,
         transaction_data_backend: TransactionDataBackend,
+        miner: Option<AccountId>,
     ) -> Result<Self, crate::Error<Block>>
     where
         Block: BlockT<Hash = canyon_primitives::Hash> + 'static,
@@ -49,16 +53,17 @@ impl PoaInherentDataProvider {
             + Sync
             + 'static,
         Client::Api: cp_permastore::PermastoreApi<Block, NumberFor<Block>, u32, Block::Hash>
-            + cp_poa::PoaApi<Block>,
+            + cp_poa::PoaApi<Block, AccountId>,
         TransactionDataBackend: TransactionDataBackendT<Block>,
     {
-        let poa_outcome = match crate::construct_poa(client, parent, transaction_data_backend) {
-            Ok(outcome) => outcome,
-            Err(e) => {
-                log::error!(target: "poa", "Failed to construct poa: {:?}", e);
-                return Err(e);
-            }
-        };
+        let poa_outcome =
+            match crate::construct_poa(client, parent, transaction_data_backend, miner) {
+                Ok(outcome) => outcome,
+                Err(e) => {
+                    log::error!(target: "poa", "Failed to construct poa: {:?}", e);
+                    return Err(e);
+                }
+            };
         Ok(Self { poa_outcome })
     }
 }
@@ -36,10 +36,15 @@
 //! 1. Pick a random byte from the whole network storage space, aka BlockWeave.
 //!     - The block weave can be seen as an ever growing gigantic array.
 //!     - Currently, the randome byte is determined by hashing
-//!       the parent header hash for N times(see [`calculate_challenge_byte`]),
-//!       which will be replaced with another strategy in SPoRA.
+//!       the parent header hash for N times(see [`calculate_challenge_byte`]).
+//!     - In the SPoRA mining mode, the random byte is picked from the recall
+//!       range of the miner using N as the nonce, see [`spora`] for details.
 //!
 //! 2. Locate the extrinsic in which the random byte is included.
+//!     - The weave offset of each extrinsic is recorded on chain, the
+//!       extrinsic is located by a single runtime call `locate_recall_tx`.
+//!     - The forgotten data is excluded from the consensus, repeat
+//!       from Step 1 with N+1 hashing if the extrinsic has been forgotten.
 //!
 //! 3. Check if the data of extrinsic located in Step 2 exists in
 //!    the local storage.
@@ -48,6 +53,8 @@
 //!       of extrinsic and data chunks respectively.
 //!     - If not, repeat from Step 1 by choosing another random byte
 //!       with N+1 hashing.
+//!     - In the SPoRA mining mode, repeat from Step 1 with N+1 too if
+//!       the hash of the recall chunk does not meet the difficulty target.
 //!
 //! ## Usage
 //!
@@ -83,25 +90,27 @@ use std::sync::Arc;
 use codec::{Decode, Encode};
 use thiserror::Error;
 
-use sc_client_api::{backend::AuxStore, BlockBackend, BlockOf};
+use sc_client_api::BlockBackend;
 use sc_consensus::{BlockCheckParams, BlockImport, BlockImportParams, ImportResult};
-use sp_api::ProvideRuntimeApi;
-use sp_block_builder::BlockBuilder as BlockBuilderApi;
-use sp_blockchain::{well_known_cache_keys::Id as CacheKeyId, HeaderBackend, ProvideCache};
-use sp_consensus::{Error as ConsensusError, SelectChain};
+use sp_api::{ApiExt, ProvideRuntimeApi};
+use sp_blockchain::{well_known_cache_keys::Id as CacheKeyId, HeaderBackend};
+use sp_consensus::Error as ConsensusError;
 use sp_runtime::{
     generic::BlockId,
     traits::{Block as BlockT, Header as HeaderT, NumberFor},
     DigestItem,
 };
 
-use canyon_primitives::{DataIndex, Depth, ExtrinsicIndex};
+use canyon_primitives::{AccountId, DataIndex, Depth, ExtrinsicIndex};
 use cc_datastore::TransactionDataBackend as TransactionDataBackendT;
 use cp_permastore::{PermastoreApi, CHUNK_SIZE};
 use cp_poa::PoaApi;
 
 mod chunk_proof;
 mod inherent;
+pub mod spora;
+#[cfg(test)]
+mod tests;
 mod trie;
 mod tx_proof;
 
@@ -111,7 +120,8 @@ pub use self::tx_proof::{build_extrinsic_proof, verify_extrinsic_proof, TxProofV
 
 // Re-exports of the primitives of poa consensus.
 pub use cp_consensus_poa::{
-    ChunkProof, PoaConfiguration, PoaOutcome, PoaValidityError, ProofOfAccess, POA_ENGINE_ID,
+    ChunkProof, LegacyProofOfAccess, MiningMode, PoaConfiguration, PoaOutcome, PoaValidityError,
+    ProofOfAccess, SporaParams, POA_ENGINE_ID, POA_ENGINE_ID_V2,
 };
 
 /// Minimum depth of PoA.
@@ -152,12 +162,21 @@ pub enum Error<Block: BlockT> {
     /// Block not found.
     #[error("Block {0} not found")]
     BlockNotFound(BlockId<Block>),
-    /// Recall block not found.
-    #[error("Recall block not found given the recall byte {0}")]
-    RecallBlockNotFound(DataIndex),
     /// Recall extrinsic not found.
     #[error("Recall extrinsic index not found given the recall byte {0}")]
     RecallExtrinsicNotFound(DataIndex),
+    /// The data of recall extrinsic has been forgotten.
+    #[error("The data of recall extrinsic {0}#{1} has been forgotten")]
+    RecallExtrinsicForgotten(BlockId<Block>, ExtrinsicIndex),
+    /// The chunk proof does not match the recall byte.
+    #[error("Invalid chunk index, expected: {0}, got: {1}")]
+    InvalidChunkIndex(u32, u32),
+    /// Miner is not configured in the SPoRA mining mode.
+    #[error("PoA miner must be configured in the SPoRA mining mode")]
+    MinerNotConfigured,
+    /// The SPoRA solution does not meet the difficulty target.
+    #[error("SPoRA solution at depth {0} does not meet the difficulty target")]
+    InvalidSporaSolution(Depth),
     /// Maxinum depth reached.
     #[error("Reaching the maximum allowed depth {0}")]
     MaxDepthReached(Depth),
@@ -188,8 +207,6 @@ fn make_bytes(h: [u8; 32]) -> [u8; 8] {
 /// Returns the position of recall byte in the entire weave.
 ///
 /// Formula: `multihash(seed, depth) % weave_size`
-///
-/// TODO: SPoRA
 pub fn calculate_challenge_byte(
     seed: Randomness,
     weave_size: DataIndex,
@@ -202,28 +219,34 @@ pub fn calculate_challenge_byte(
     DataIndex::from_le_bytes(make_bytes(multihash(seed, depth))) % weave_size
 }
 
-/// Returns a tuple of (extrinsic_index, absolute_data_index)
-/// of extrinsic in which `recall_byte` is located.
-fn find_recall_tx(
-    recall_byte: DataIndex,
-    sized_extrinsics: &[(ExtrinsicIndex, DataIndex)],
-) -> (ExtrinsicIndex, DataIndex) {
-    log::trace!(
-        target: "poa",
-        "Locating the position of recall tx, recall_byte: {}, sized_extrinsics: {:?}",
-        recall_byte, sized_extrinsics
-    );
-    match sized_extrinsics.binary_search_by_key(&recall_byte, |&(_, weave_size)| weave_size) {
-        Ok(i) => sized_extrinsics[i],
-        Err(i) => sized_extrinsics[i],
+/// Parameters and the encoded miner account in the SPoRA mining mode.
+type SporaMining = (SporaParams, Vec<u8>);
+
+/// Returns the position of recall byte at `depth` in the entire weave,
+/// `spora_mining` is `Some` in the SPoRA mining mode.
+fn recall_byte_at(
+    seed: &[u8],
+    weave_size: DataIndex,
+    depth: Depth,
+    spora_mining: Option<&SporaMining>,
+) -> DataIndex {
+    match spora_mining {
+        Some((params, miner)) => {
+            spora::calculate_recall_byte(seed, miner, weave_size, params, depth)
+        }
+        None => calculate_challenge_byte(seed.to_vec(), weave_size, depth),
     }
 }
 
 /// All information of recall block that is required to build a [`ProofOfAccess`].
 #[derive(Debug, Clone)]
 pub struct RecallInfo<B: BlockT> {
-    /// Weave size of last block.
-    weave_base: DataIndex,
+    /// Number of recall block.
+    recall_block_number: NumberFor<B>,
+    /// Hash of recall block.
+    recall_block_hash: B::Hash,
+    /// Weave size before the recall extrinsic.
+    tx_weave_base: DataIndex,
     /// All extrinsics in recall block.
     extrinsics: Vec<B::Extrinsic>,
     /// Extrinsics root of recall block.
@@ -244,56 +267,172 @@ impl<B: BlockT<Hash = canyon_primitives::Hash>> RecallInfo<B> {
     }
 }
 
-/// Returns all the information about the recall block for the PoA consensus.
-fn find_recall_info<Block, Client>(
+/// Returns the hash of block `number` on the chain ending at block `at`.
+///
+/// The canonical hash is used once the chain of `at` joins the canonical chain,
+/// so that only the blocks of a side branch need to be walked.
+fn ancestor_hash_at<Block, Client>(
+    client: &Arc<Client>,
+    at: Block::Hash,
+    number: NumberFor<Block>,
+) -> Result<Block::Hash, Error<Block>>
+where
+    Block: BlockT,
+    Client: HeaderBackend<Block>,
+{
+    let mut hash = at;
+    loop {
+        let header = client
+            .header(BlockId::Hash(hash))?
+            .ok_or(Error::BlockNotFound(BlockId::Hash(hash)))?;
+
+        let current_number = *header.number();
+
+        if current_number < number {
+            return Err(Error::BlockNotFound(BlockId::Number(number)));
+        }
+
+        if current_number == number {
+            return Ok(hash);
+        }
+
+        if client.hash(current_number)? == Some(hash) {
+            return client
+                .hash(number)?
+                .ok_or(Error::BlockNotFound(BlockId::Number(number)));
+        }
+
+        hash = *header.parent_hash();
+    }
+}
+
+/// Returns `true` if the runtime at block `at` provides the methods added in
+/// version 2 of [`PermastoreApi`], i.e., `locate_recall_tx`, `weave_offsets`
+/// and `is_forgotten`.
+fn has_permastore_api_v2<Block, Client>(
+    client: &Arc<Client>,
+    at: &BlockId<Block>,
+) -> Result<bool, Error<Block>>
+where
+    Block: BlockT,
+    Client: ProvideRuntimeApi<Block>,
+    Client::Api: PermastoreApi<Block, NumberFor<Block>, u32, Block::Hash>,
+{
+    Ok(client
+        .runtime_api()
+        .has_api_with::<dyn PermastoreApi<Block, NumberFor<Block>, u32, Block::Hash>, _>(
+            at,
+            |version| version >= 2,
+        )?)
+}
+
+/// Returns `(recall_block_number, recall_extrinsic_index, tx_weave_base)` of the
+/// transaction in which the recall byte is included, read from the state of block `at`.
+///
+/// The runtime without `PermastoreApi::locate_recall_tx` is handled by scanning the
+/// sized extrinsics of the recall block.
+fn locate_recall_tx<Block, Client>(
+    at: Block::Hash,
     recall_byte: DataIndex,
-    recall_block_number: NumberFor<Block>,
     client: &Arc<Client>,
-) -> Result<RecallInfo<Block>, Error<Block>>
+) -> Result<(NumberFor<Block>, ExtrinsicIndex, DataIndex), Error<Block>>
 where
     Block: BlockT,
-    Client: BlockBackend<Block> + ProvideRuntimeApi<Block> + Send + Sync,
+    Client: BlockBackend<Block> + HeaderBackend<Block> + ProvideRuntimeApi<Block> + Send + Sync,
     Client::Api: PermastoreApi<Block, NumberFor<Block>, u32, Block::Hash>,
 {
-    let recall_block_id = BlockId::number(recall_block_number);
+    let at_id = BlockId::Hash(at);
 
+    if has_permastore_api_v2(client, &at_id)? {
+        return client
+            .runtime_api()
+            .locate_recall_tx(&at_id, recall_byte)?
+            .ok_or(Error::RecallExtrinsicNotFound(recall_byte));
+    }
+
+    let recall_block_number = client
+        .runtime_api()
+        .find_recall_block(&at_id, recall_byte)?
+        .ok_or(Error::RecallExtrinsicNotFound(recall_byte))?;
+
+    let recall_block_id = BlockId::Hash(ancestor_hash_at(client, at, recall_block_number)?);
     let (header, extrinsics) = fetch_block(client, recall_block_id)?.deconstruct();
 
-    let weave_base = client
+    let mut tx_weave_base = client
         .runtime_api()
         .weave_size(&BlockId::Hash(*header.parent_hash()))?;
 
-    let mut sized_extrinsics = Vec::with_capacity(extrinsics.len());
-
-    let mut acc = 0u64;
-    for (index, _extrinsic) in extrinsics.iter().enumerate() {
-        let tx_size = client.runtime_api().data_size(
-            &recall_block_id,
-            recall_block_number,
-            index as ExtrinsicIndex,
-        )? as u64;
-        if tx_size > 0 {
-            sized_extrinsics.push((index as ExtrinsicIndex, weave_base + acc + tx_size));
-            acc += tx_size;
+    for index in 0..extrinsics.len() as ExtrinsicIndex {
+        let tx_size = client
+            .runtime_api()
+            .data_size(&at_id, recall_block_number, index)? as DataIndex;
+        if tx_size > 0 && recall_byte < tx_weave_base + tx_size {
+            return Ok((recall_block_number, index, tx_weave_base));
         }
+        tx_weave_base += tx_size;
     }
 
-    log::trace!(
+    Err(Error::RecallExtrinsicNotFound(recall_byte))
+}
+
+/// Returns `true` if the data of extrinsic `extrinsic_index` in block `block_number`
+/// has been forgotten, read from the state of block `at`.
+///
+/// The data can not be forgotten in the runtime without `PermastoreApi::is_forgotten`.
+fn is_forgotten<Block, Client>(
+    client: &Arc<Client>,
+    at: &BlockId<Block>,
+    block_number: NumberFor<Block>,
+    extrinsic_index: ExtrinsicIndex,
+) -> Result<bool, Error<Block>>
+where
+    Block: BlockT,
+    Client: ProvideRuntimeApi<Block>,
+    Client::Api: PermastoreApi<Block, NumberFor<Block>, u32, Block::Hash>,
+{
+    if !has_permastore_api_v2(client, at)? {
+        return Ok(false);
+    }
+
+    Ok(client
+        .runtime_api()
+        .is_forgotten(at, block_number, extrinsic_index)?)
+}
+
+/// Returns all the information about the recall block for the PoA consensus.
+///
+/// The recall block is resolved on the chain ending at block `at`, which may not
+/// be the canonical chain.
+fn find_recall_info<Block, Client>(
+    at: Block::Hash,
+    recall_byte: DataIndex,
+    client: &Arc<Client>,
+) -> Result<RecallInfo<Block>, Error<Block>>
+where
+    Block: BlockT,
+    Client: BlockBackend<Block> + HeaderBackend<Block> + ProvideRuntimeApi<Block> + Send + Sync,
+    Client::Api: PermastoreApi<Block, NumberFor<Block>, u32, Block::Hash>,
+{
+    let (recall_block_number, recall_extrinsic_index, tx_weave_base) =
+        locate_recall_tx(at, recall_byte, client)?;
+
+    log::debug!(
         target: "poa",
-        "The sized extrinsics found in recall block: {:?}",
-        sized_extrinsics,
+        "Recall extrinsic {}#{} was found given the recall byte: {}, tx weave base: {}",
+        recall_block_number,
+        recall_extrinsic_index,
+        recall_byte,
+        tx_weave_base,
     );
 
-    // No data store transactions in this block.
-    if sized_extrinsics.is_empty() {
-        return Err(Error::<Block>::RecallExtrinsicNotFound(recall_byte));
-    }
+    let recall_block_hash = ancestor_hash_at(client, at, recall_block_number)?;
 
-    let (recall_extrinsic_index, _recall_block_data_ceil) =
-        find_recall_tx(recall_byte, &sized_extrinsics);
+    let (header, extrinsics) = fetch_block(client, BlockId::Hash(recall_block_hash))?.deconstruct();
 
     Ok(RecallInfo {
-        weave_base,
+        recall_block_number,
+        recall_block_hash,
+        tx_weave_base,
         extrinsics,
         extrinsics_root: *header.extrinsics_root(),
         recall_extrinsic_index,
@@ -312,26 +451,11 @@ where
     Ok(client.block(&id)?.ok_or(Error::BlockNotFound(id))?.block)
 }
 
-/// Returns the block number of recall block.
-fn find_recall_block<Block: BlockT, RA>(
-    at: BlockId<Block>,
-    recall_byte: DataIndex,
-    runtime_api: &Arc<RA>,
-) -> Result<NumberFor<Block>, Error<Block>>
-where
-    RA: ProvideRuntimeApi<Block> + Send + Sync,
-    RA::Api: PermastoreApi<Block, NumberFor<Block>, u32, Block::Hash>,
-{
-    runtime_api
-        .runtime_api()
-        .find_recall_block(&at, recall_byte)?
-        .ok_or(Error::RecallBlockNotFound(recall_byte))
-}
-
 /// A builder for creating [`PoaOutcome`].
 pub struct PoaBuilder<Block, Client, TransactionDataBackend> {
     client: Arc<Client>,
     transaction_data_backend: TransactionDataBackend,
+    miner: Option<AccountId>,
     phatom: PhantomData<Block>,
 }
 
@@ -344,7 +468,8 @@ where
         + Send
         + Sync
         + 'static,
-    Client::Api: PermastoreApi<Block, NumberFor<Block>, u32, Block::Hash> + PoaApi<Block>,
+    Client::Api:
+        PermastoreApi<Block, NumberFor<Block>, u32, Block::Hash> + PoaApi<Block, AccountId>,
     TransactionDataBackend: TransactionDataBackendT<Block>,
 {
     /// Creates a new instance of [`PoaBuilder`].
@@ -352,20 +477,43 @@ where
         Self {
             client,
             transaction_data_backend,
+            miner: None,
             phatom: PhantomData::<Block>,
         }
     }
 
-    /// Returns the number of recall block.
-    fn find_recall_block(
+    /// Sets the miner account, which is required in the SPoRA mining mode.
+    pub fn with_miner(mut self, miner: Option<AccountId>) -> Self {
+        self.miner = miner;
+        self
+    }
+
+    /// Returns a [`ChunkProofBuilder`] of the recall chunk `chunk_index`, which is built
+    /// from the chunk hashes without reading the entire transaction data.
+    ///
+    /// Returns `None` if the chunk hashes of transaction data are not found locally.
+    fn recall_chunk_proof_builder(
         &self,
         at: BlockId<Block>,
-        recall_byte: DataIndex,
-    ) -> Result<NumberFor<Block>, Error<Block>> {
-        self.client
-            .runtime_api()
-            .find_recall_block(&at, recall_byte)?
-            .ok_or(Error::RecallBlockNotFound(recall_byte))
+        id: BlockId<Block>,
+        extrinsic_index: ExtrinsicIndex,
+        chunk_index: u32,
+        chunk: Vec<u8>,
+    ) -> Result<Option<ChunkProofBuilder>, cc_datastore::Error<Block>> {
+        let chunk_hashes = match self.transaction_data_backend.transaction_chunk_hashes(
+            Some(at),
+            id,
+            extrinsic_index,
+        )? {
+            Some(chunk_hashes) => chunk_hashes,
+            None => return Ok(None),
+        };
+
+        Ok(Some(ChunkProofBuilder::from_chunk_hashes(
+            chunk_hashes,
+            chunk_index,
+            chunk,
+        )))
     }
 
     /// Creates the inherent data [`PoaOutcome`].
@@ -384,57 +532,118 @@ where
             max_depth,
             max_tx_path,
             max_chunk_path,
+            mining_mode,
         } = self.client.runtime_api().poa_config(&parent_id)?;
 
+        let spora_mining = match mining_mode {
+            MiningMode::Classic => None,
+            MiningMode::Spora(params) => {
+                let miner = self.miner.as_ref().ok_or(Error::MinerNotConfigured)?;
+                Some((params, miner.encode()))
+            }
+        };
+
+        let seed = parent.encode();
+
         for depth in MIN_DEPTH..=max_depth {
-            let recall_byte = calculate_challenge_byte(parent.encode(), weave_size, depth);
+            let recall_byte = recall_byte_at(&seed, weave_size, depth, spora_mining.as_ref());
             log::debug!(
                 target: "poa",
                 "Attempting to generate poa at depth: {}, recall byte found: {}",
                 depth, recall_byte,
             );
-            let recall_block_number = self.find_recall_block(parent_id, recall_byte)?;
-
-            log::debug!(
-                target: "poa", "Recall block number: {} was found given the recall byte: {}",
-                recall_block_number,
-                recall_byte,
-            );
 
             let RecallInfo {
-                weave_base,
+                recall_block_number,
+                recall_block_hash,
+                tx_weave_base,
                 extrinsics,
                 extrinsics_root,
                 recall_extrinsic_index,
-            } = find_recall_info(recall_byte, recall_block_number, &self.client)?;
+            } = find_recall_info(parent, recall_byte, &self.client)?;
 
             // Continue if the recall tx has been forgotten as the forgot
             // txs can not participate in the consensus.
-            //
-            // FIXME: handle the data oblivion
-            // if todo!("recall_tx has been forgotten via runtime api") {
-            // continue;
-            // }
-
-            let recall_data = self.transaction_data_backend.transaction_data(
-                BlockId::Number(recall_block_number),
-                recall_extrinsic_index as u32,
+            if is_forgotten(
+                &self.client,
+                &parent_id,
+                recall_block_number,
+                recall_extrinsic_index,
+            )? {
+                log::debug!(
+                    target: "poa",
+                    "Recall extrinsic {}#{} has been forgotten, continuing next depth: {}",
+                    recall_block_number,
+                    recall_extrinsic_index,
+                    depth + 1
+                );
+                continue;
+            }
+
+            if recall_byte < tx_weave_base {
+                panic!(
+                    "Underflow happened! recall_byte: {}, tx_weave_base: {}",
+                    recall_byte, tx_weave_base
+                );
+            }
+
+            let chunk_index = recall_chunk_index(recall_byte, tx_weave_base);
+            let recall_block_id = BlockId::Hash(recall_block_hash);
+
+            // The recall block may be on a side branch, the transaction data has to be
+            // resolved against the state of the parent.
+            let recall_chunk = match self.transaction_data_backend.transaction_chunk(
+                Some(parent_id),
+                recall_block_id,
+                recall_extrinsic_index,
+                chunk_index,
+            ) {
+                Ok(Some(chunk)) => chunk,
+                Ok(None) => {
+                    log::warn!(
+                        target: "poa",
+                        "Transaction data not found given block {} and extrinsic index {}, continuing next depth: {}",
+                        recall_block_number,
+                        recall_extrinsic_index,
+                        depth + 1
+                    );
+                    continue;
+                }
+                Err(e) => {
+                    log::error!(
+                        target: "poa",
+                        "Error occurred when retrieving the transaction data: {:?}",
+                        e,
+                    );
+                    continue;
+                }
+            };
+
+            // Check the difficulty against the raw recall chunk first, the proofs are only
+            // built for a solution.
+            if let Some((params, miner)) = &spora_mining {
+                let hash = spora::solution_hash(&seed, miner, depth, &recall_chunk);
+                if !spora::meets_difficulty(hash, params) {
+                    log::trace!(
+                        target: "poa",
+                        "SPoRA solution at depth {} does not meet the difficulty target",
+                        depth,
+                    );
+                    continue;
+                }
+            }
+
+            let chunk_proof_builder = self.recall_chunk_proof_builder(
+                parent_id,
+                recall_block_id,
+                recall_extrinsic_index,
+                chunk_index,
+                recall_chunk,
             );
 
-            match recall_data {
-                Ok(Some(tx_data)) => {
-                    let transaction_data_offset = match recall_byte.checked_sub(weave_base) {
-                        Some(offset) => offset,
-                        None => panic!(
-                            "Underflow happened! recall_byte: {}, recall_block_weave_base: {}",
-                            recall_byte, weave_base
-                        ),
-                    };
-
-                    if let Ok(chunk_proof) =
-                        ChunkProofBuilder::new(tx_data, CHUNK_SIZE, transaction_data_offset as u32)
-                            .build()
-                    {
+            match chunk_proof_builder {
+                Ok(Some(chunk_proof_builder)) => {
+                    if let Ok(chunk_proof) = chunk_proof_builder.build() {
                         if chunk_proof.size() > max_chunk_path as usize {
                             log::debug!(
                                 target: "poa",
@@ -461,6 +670,10 @@ where
                                 continue;
                             }
                             let poa = ProofOfAccess::new(depth, tx_proof, chunk_proof);
+                            let poa = match spora_mining {
+                                Some((_, miner)) => poa.with_miner(miner),
+                                None => poa,
+                            };
                             log::trace!(target: "poa", "Generate the poa proof successfully: {:?}", poa);
                             return Ok(PoaOutcome::Justification(poa));
                         }
@@ -496,6 +709,7 @@ pub fn construct_poa<Block, Client, TransactionDataBackend>(
     client: Arc<Client>,
     parent: Block::Hash,
     transaction_data_backend: TransactionDataBackend,
+    miner: Option<AccountId>,
 ) -> Result<PoaOutcome, Error<Block>>
 where
     Block: BlockT<Hash = canyon_primitives::Hash> + 'static,
@@ -505,15 +719,19 @@ where
         + Send
         + Sync
         + 'static,
-    Client::Api: PermastoreApi<Block, NumberFor<Block>, u32, Block::Hash> + PoaApi<Block>,
+    Client::Api:
+        PermastoreApi<Block, NumberFor<Block>, u32, Block::Hash> + PoaApi<Block, AccountId>,
     TransactionDataBackend: TransactionDataBackendT<Block>,
 {
-    PoaBuilder::new(client, transaction_data_backend).build(parent)
+    PoaBuilder::new(client, transaction_data_backend)
+        .with_miner(miner)
+        .build(parent)
 }
 
 /// Extracts PoA digest from a header that should contain one.
 ///
-/// The header should have one and only one [`DigestItem::PreRuntime(POA_ENGINE_ID, pre_runtime)`].
+/// The header should have one and only one [`DigestItem::Seal`] using either
+/// [`POA_ENGINE_ID_V2`] or the legacy [`POA_ENGINE_ID`].
 fn fetch_poa<B: BlockT>(header: B::Header, hash: B::Hash) -> Result<ProofOfAccess, Error<B>> {
     use DigestItem::Seal;
 
@@ -521,77 +739,182 @@ fn fetch_poa<B: BlockT>(header: B::Header, hash: B::Hash) -> Result<ProofOfAcces
         .digest()
         .logs()
         .iter()
-        .filter(|digest_item| matches!(digest_item, Seal(id, _seal) if id == &POA_ENGINE_ID))
+        .filter(|digest_item| {
+            matches!(digest_item, Seal(id, _seal) if id == &POA_ENGINE_ID_V2 || id == &POA_ENGINE_ID)
+        })
         .collect::<Vec<_>>();
 
     match poa_seal.len() {
         0 => Err(Error::<B>::NoDigest(hash)),
         1 => match poa_seal[0] {
-            Seal(_id, seal) => Decode::decode(&mut seal.as_slice()).map_err(Error::<B>::Codec),
-            _ => unreachable!("Only items using POA_ENGINE_ID has been filtered; qed"),
+            Seal(id, seal) if id == &POA_ENGINE_ID_V2 => {
+                Decode::decode(&mut seal.as_slice()).map_err(Error::<B>::Codec)
+            }
+            Seal(_id, seal) => LegacyProofOfAccess::decode(&mut seal.as_slice())
+                .map(Into::into)
+                .map_err(Error::<B>::Codec),
+            _ => unreachable!("Only items using POA_ENGINE_ID(_V2) has been filtered; qed"),
         },
         _ => Err(Error::<B>::MultipleDigests(hash)),
     }
 }
 
+/// Returns the index of chunk in which `recall_byte` is located in the recall transaction data.
+fn recall_chunk_index(recall_byte: DataIndex, tx_weave_base: DataIndex) -> u32 {
+    ((recall_byte - tx_weave_base) / CHUNK_SIZE as DataIndex) as u32
+}
+
+/// Verifies the [`ProofOfAccess`] of a block against the state of its parent `parent_hash`.
+fn verify_proof_of_access<Block, Client>(
+    client: &Arc<Client>,
+    parent_hash: Block::Hash,
+    poa: ProofOfAccess,
+) -> Result<(), Error<Block>>
+where
+    Block: BlockT<Hash = canyon_primitives::Hash>,
+    Client: BlockBackend<Block> + HeaderBackend<Block> + ProvideRuntimeApi<Block> + Send + Sync,
+    Client::Api:
+        PermastoreApi<Block, NumberFor<Block>, u32, Block::Hash> + PoaApi<Block, AccountId>,
+{
+    let poa_config = client
+        .runtime_api()
+        .poa_config(&BlockId::Hash(parent_hash))
+        .map_err(Error::<Block>::ApiError)?;
+
+    poa.check_validity(&poa_config)
+        .map_err(Error::<Block>::InvalidPoa)?;
+
+    let weave_size = client
+        .runtime_api()
+        .weave_size(&BlockId::Hash(parent_hash))
+        .map_err(Error::<Block>::ApiError)?;
+
+    let ProofOfAccess {
+        depth,
+        tx_path,
+        chunk_proof,
+        miner,
+    } = poa;
+
+    let spora_mining = match poa_config.mining_mode {
+        MiningMode::Classic => None,
+        MiningMode::Spora(params) => Some((
+            params,
+            miner.ok_or(Error::<Block>::InvalidPoa(PoaValidityError::MissingMiner))?,
+        )),
+    };
+
+    let seed = parent_hash.encode();
+
+    if let Some((params, miner)) = &spora_mining {
+        let hash = spora::solution_hash(&seed, miner, depth, &chunk_proof.chunk);
+        if !spora::meets_difficulty(hash, params) {
+            return Err(Error::<Block>::InvalidSporaSolution(depth));
+        }
+    }
+
+    let recall_byte = recall_byte_at(&seed, weave_size, depth, spora_mining.as_ref());
+    let recall_info = find_recall_info(parent_hash, recall_byte, client)?;
+    let recall_block_number = recall_info.recall_block_number;
+
+    // The forgotten data can not be the recall data, which is also skipped in
+    // [`PoaBuilder::build`].
+    if is_forgotten(
+        client,
+        &BlockId::Hash(parent_hash),
+        recall_block_number,
+        recall_info.recall_extrinsic_index,
+    )? {
+        return Err(Error::<Block>::RecallExtrinsicForgotten(
+            BlockId::Number(recall_block_number),
+            recall_info.recall_extrinsic_index,
+        ));
+    }
+
+    recall_info
+        .as_tx_proof_verifier()
+        .verify(&tx_path)
+        .map_err(Error::<Block>::VerifyFailed)?;
+
+    // The chunk proof must be bound to the recall byte, otherwise any chunk
+    // of the recall transaction data can be used to pass the verification.
+    let expected_chunk_index = recall_chunk_index(recall_byte, recall_info.tx_weave_base);
+    if chunk_proof.chunk_index != expected_chunk_index {
+        return Err(Error::<Block>::InvalidChunkIndex(
+            expected_chunk_index,
+            chunk_proof.chunk_index,
+        ));
+    }
+
+    let chunk_root = client
+        .runtime_api()
+        .chunk_root(
+            &BlockId::Hash(parent_hash),
+            recall_block_number,
+            recall_info.recall_extrinsic_index,
+        )
+        .map_err(Error::<Block>::ApiError)?
+        .ok_or(Error::<Block>::ChunkRootNotFound(
+            BlockId::Number(recall_block_number),
+            recall_info.recall_extrinsic_index,
+        ))?;
+
+    chunk_proof::ChunkProofVerifier::new(chunk_proof)
+        .verify(&chunk_root)
+        .map_err(Error::<Block>::VerifyFailed)?;
+
+    Ok(())
+}
+
 /// A pure block importer for PoA.
 ///
 /// This importer has to be used with other mature block importer
 /// together, e.g., grandpa block import, for it only verifies the
 /// validity of PoA sealed digest item in the header and nothing else.
-pub struct PurePoaBlockImport<B, I, C, S> {
+///
+/// All the PoA requirements of a block are evaluated against the state of
+/// its parent, which is not necessarily the best block.
+pub struct PurePoaBlockImport<B, I, C> {
     inner: I,
-    select_chain: S,
     client: Arc<C>,
     phatom: PhantomData<B>,
 }
 
-impl<B: Clone, I: Clone, C, S: Clone> Clone for PurePoaBlockImport<B, I, C, S> {
+impl<B: Clone, I: Clone, C> Clone for PurePoaBlockImport<B, I, C> {
     fn clone(&self) -> Self {
         Self {
             inner: self.inner.clone(),
-            select_chain: self.select_chain.clone(),
             client: self.client.clone(),
             phatom: self.phatom,
         }
     }
 }
 
-impl<B, I, C, S> PurePoaBlockImport<B, I, C, S>
+impl<B, I, C> PurePoaBlockImport<B, I, C>
 where
     B: BlockT,
     I: BlockImport<B, Transaction = sp_api::TransactionFor<C, B>> + Send + Sync,
     I::Error: Into<ConsensusError>,
-    C: ProvideRuntimeApi<B> + Send + Sync + HeaderBackend<B> + AuxStore + ProvideCache<B> + BlockOf,
-    C::Api: BlockBuilderApi<B>,
+    C: ProvideRuntimeApi<B> + Send + Sync + HeaderBackend<B>,
 {
     /// Creates a new block import suitable to be used in PoA.
-    pub fn new(inner: I, client: Arc<C>, select_chain: S) -> Self {
+    pub fn new(inner: I, client: Arc<C>) -> Self {
         Self {
             inner,
             client,
-            select_chain,
             phatom: PhantomData::<B>,
         }
     }
 }
 
 #[async_trait::async_trait]
-impl<B, I, C, S> BlockImport<B> for PurePoaBlockImport<B, I, C, S>
+impl<B, I, C> BlockImport<B> for PurePoaBlockImport<B, I, C>
 where
     B: BlockT<Hash = canyon_primitives::Hash>,
     I: BlockImport<B, Transaction = sp_api::TransactionFor<C, B>> + Send + Sync,
     I::Error: Into<ConsensusError>,
-    S: SelectChain<B>,
-    C: ProvideRuntimeApi<B>
-        + Send
-        + Sync
-        + BlockBackend<B>
-        + HeaderBackend<B>
-        + AuxStore
-        + ProvideCache<B>
-        + BlockOf,
-    C::Api: BlockBuilderApi<B> + PermastoreApi<B, NumberFor<B>, u32, B::Hash> + PoaApi<B>,
+    C: ProvideRuntimeApi<B> + Send + Sync + BlockBackend<B> + HeaderBackend<B>,
+    C::Api: PermastoreApi<B, NumberFor<B>, u32, B::Hash> + PoaApi<B, AccountId>,
 {
     type Error = ConsensusError;
     type Transaction = sp_api::TransactionFor<C, B>;
@@ -608,73 +931,17 @@ where
         block: BlockImportParams<B, Self::Transaction>,
         new_cache: HashMap<CacheKeyId, Vec<u8>>,
     ) -> Result<ImportResult, Self::Error> {
-        let best_header = self
-            .select_chain
-            .best_chain()
-            .await
-            .map_err(|e| format!("Fetch best chain failed via select chain: {:?}", e))?;
-
-        let best_hash = best_header.hash();
+        let parent_hash = *block.header.parent_hash();
 
         if self
             .client
             .runtime_api()
-            .require_proof_of_access(&BlockId::Hash(best_hash))
+            .require_proof_of_access(&BlockId::Hash(parent_hash))
             .map_err(Error::<B>::ApiError)?
         {
-            let header = block.post_header();
-            let poa = fetch_poa::<B>(header, best_hash)?;
-
-            let parent_hash = *block.header.parent_hash();
-            let poa_config = self
-                .client
-                .runtime_api()
-                .poa_config(&BlockId::Hash(parent_hash))
-                .map_err(Error::<B>::ApiError)?;
-
-            poa.check_validity(&poa_config)
-                .map_err(Error::<B>::InvalidPoa)?;
-
-            let weave_size = self
-                .client
-                .runtime_api()
-                .weave_size(&BlockId::Hash(parent_hash))
-                .map_err(Error::<B>::ApiError)?;
-
-            let ProofOfAccess {
-                depth,
-                tx_path,
-                chunk_proof,
-            } = poa;
-
-            let recall_byte = calculate_challenge_byte(parent_hash.encode(), weave_size, depth);
-            let recall_block_number =
-                find_recall_block(BlockId::Hash(parent_hash), recall_byte, &self.client)?;
-
-            let recall_info = find_recall_info(recall_byte, recall_block_number, &self.client)?;
-
-            recall_info
-                .as_tx_proof_verifier()
-                .verify(&tx_path)
-                .map_err(Error::<B>::VerifyFailed)?;
-
-            let chunk_root = self
-                .client
-                .runtime_api()
-                .chunk_root(
-                    &BlockId::Hash(parent_hash),
-                    recall_block_number,
-                    recall_info.recall_extrinsic_index,
-                )
-                .map_err(Error::<B>::ApiError)?
-                .ok_or(Error::<B>::ChunkRootNotFound(
-                    BlockId::Number(recall_block_number),
-                    recall_info.recall_extrinsic_index,
-                ))?;
-
-            chunk_proof::ChunkProofVerifier::new(chunk_proof)
-                .verify(&chunk_root)
-                .map_err(Error::<B>::VerifyFailed)?;
+            let poa = fetch_poa::<B>(block.post_header(), block.post_hash())?;
+
+            verify_proof_of_access(&self.client, parent_hash, poa)?;
         }
 
         self.inner
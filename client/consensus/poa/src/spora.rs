@@ -0,0 +1,120 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// This file is part of Canyon.
+//
+// Copyright (c) 2021 Canyon Labs.
+//
+// Canyon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// Canyon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Canyon. If not, see <http://www.gnu.org/licenses/>.
+
+//! Succinct Proofs of Random Access (SPoRA).
+//!
+//! In SPoRA, a miner is assigned a recall range in the weave derived from the
+//! parent hash and its own account, each depth is used as a nonce to pick a
+//! recall byte in that range. The proof is valid only if the hash of the nonce
+//! and the recall chunk meets the difficulty target, which requires the miner
+//! to try a number of chunks, thus favoring the miners having fast local access
+//! to the data over the ones fetching the data over the network.
+
+use codec::Encode;
+
+use canyon_primitives::{DataIndex, Depth};
+use cp_consensus_poa::SporaParams;
+
+fn as_data_index(hash: [u8; 32]) -> DataIndex {
+    let mut res = [0u8; 8];
+    res.copy_from_slice(&hash[..8]);
+    DataIndex::from_le_bytes(res)
+}
+
+/// Returns the start position of the recall range of `miner`.
+///
+/// Formula: `hash(seed ++ miner) % weave_size`
+pub fn recall_range_start(seed: &[u8], miner: &[u8], weave_size: DataIndex) -> DataIndex {
+    assert!(
+        weave_size > 0,
+        "weave size can not be 0 when calculating the recall range"
+    );
+    as_data_index(sp_io::hashing::blake2_256(&[seed, miner].concat())) % weave_size
+}
+
+/// Returns the position of recall byte in the entire weave given `nonce`.
+///
+/// The recall byte is located in the recall range of `miner`, wrapping
+/// around the end of the weave.
+pub fn calculate_recall_byte(
+    seed: &[u8],
+    miner: &[u8],
+    weave_size: DataIndex,
+    params: &SporaParams,
+    nonce: Depth,
+) -> DataIndex {
+    let start = recall_range_start(seed, miner, weave_size);
+    let range_size = params.recall_range_size.min(weave_size);
+    let offset = as_data_index(sp_io::hashing::blake2_256(
+        &[seed, miner, &nonce.encode()].concat(),
+    )) % range_size;
+    // Never overflows as both `start` and `offset` are less than `weave_size`.
+    (start + offset) % weave_size
+}
+
+/// Returns the hash of a SPoRA solution.
+pub fn solution_hash(seed: &[u8], miner: &[u8], nonce: Depth, chunk: &[u8]) -> [u8; 32] {
+    sp_io::hashing::blake2_256(&[seed, miner, &nonce.encode(), chunk].concat())
+}
+
+/// Returns `true` if the solution hash meets the difficulty target.
+///
+/// The target is `DataIndex::MAX / difficulty`, so it takes `difficulty`
+/// attempts to find a solution on average.
+pub fn meets_difficulty(hash: [u8; 32], params: &SporaParams) -> bool {
+    as_data_index(hash) <= DataIndex::MAX / params.difficulty.max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recall_byte_should_be_in_recall_range() {
+        let seed = [1u8; 32];
+        let miner = [2u8; 32];
+        let weave_size = 1_000_000;
+        let params = SporaParams {
+            recall_range_size: 1000,
+            difficulty: 1,
+        };
+
+        let start = recall_range_start(&seed, &miner, weave_size);
+        for nonce in 1..100 {
+            let recall_byte = calculate_recall_byte(&seed, &miner, weave_size, &params, nonce);
+            let offset = (recall_byte + weave_size - start) % weave_size;
+            assert!(offset < params.recall_range_size);
+        }
+
+        // The recall range is derived from the miner.
+        assert_ne!(start, recall_range_start(&seed, &[3u8; 32], weave_size));
+    }
+
+    #[test]
+    fn meets_difficulty_should_work() {
+        let mut params = SporaParams {
+            recall_range_size: 1000,
+            difficulty: 1,
+        };
+        assert!(meets_difficulty([0xff; 32], &params));
+
+        params.difficulty = 2;
+        assert!(!meets_difficulty([0xff; 32], &params));
+        assert!(meets_difficulty([0x7f; 32], &params));
+    }
+}
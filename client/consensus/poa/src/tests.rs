@@ -0,0 +1,584 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// This file is part of Canyon.
+//
+// Copyright (c) 2021 Canyon Labs.
+//
+// Canyon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// Canyon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Canyon. If not, see <http://www.gnu.org/licenses/>.
+
+use super::*;
+
+use futures::executor::block_on;
+use parking_lot::Mutex;
+
+use sc_block_builder::BlockBuilderProvider;
+use sc_consensus::ForkChoiceStrategy;
+use sp_api::{ApiError, ApiRef};
+use sp_consensus::BlockOrigin;
+use sp_core::{NativeOrEncoded, H256};
+use sp_keyring::AccountKeyring::{Alice, Bob};
+use sp_runtime::{generic::SignedBlock, Justifications, Permill};
+use substrate_test_runtime_client::{
+    runtime::{Block, Extrinsic, Header, Transfer},
+    Backend, BlockBuilderExt, Client, ClientBlockImportExt,
+};
+
+/// Byte size of the recall transaction data.
+const DATA_SIZE: usize = 4 * CHUNK_SIZE as usize + 100;
+
+/// Number of chunks of the recall transaction data.
+const CHUNKS_COUNT: u32 = 5;
+
+/// Index of the recall transaction in the recall block.
+const RECALL_EXTRINSIC_INDEX: ExtrinsicIndex = 1;
+
+/// The on-chain state exposed by the runtime APIs of [`TestClient`].
+#[derive(Debug, Clone, Default)]
+struct RuntimeState {
+    weave_size: DataIndex,
+    /// `(block_number, extrinsic_index, tx_weave_base)` of the recall transaction.
+    recall_tx: Option<(u64, ExtrinsicIndex, DataIndex)>,
+    chunk_root: Option<H256>,
+    forgotten: bool,
+}
+
+/// A test client of which the blocks are provided by the substrate test client
+/// and the PoA related runtime APIs are mocked per block.
+#[derive(Clone)]
+struct TestClient {
+    inner: Arc<Client<Backend>>,
+    states: Arc<Mutex<HashMap<H256, RuntimeState>>>,
+}
+
+impl TestClient {
+    /// Sets the runtime state at block `at`, the state of other blocks is empty.
+    fn set_state(&self, at: H256, state: RuntimeState) {
+        self.states.lock().insert(at, state);
+    }
+}
+
+struct RuntimeApi {
+    states: HashMap<H256, RuntimeState>,
+}
+
+impl RuntimeApi {
+    fn state_at(&self, at: &BlockId<Block>) -> RuntimeState {
+        match at {
+            BlockId::Hash(hash) => self.states.get(hash).cloned().unwrap_or_default(),
+            BlockId::Number(_) => panic!("Runtime API must be called at a block hash"),
+        }
+    }
+}
+
+impl ProvideRuntimeApi<Block> for TestClient {
+    type Api = RuntimeApi;
+
+    fn runtime_api<'a>(&'a self) -> ApiRef<'a, Self::Api> {
+        RuntimeApi {
+            states: self.states.lock().clone(),
+        }
+        .into()
+    }
+}
+
+sp_api::mock_impl_runtime_apis! {
+    impl PermastoreApi<Block, u64, u32, H256> for RuntimeApi {
+        #[advanced]
+        fn chunk_root(
+            &self,
+            at: &BlockId<Block>,
+            _block_number: u64,
+            _extrinsic_index: u32,
+        ) -> Result<NativeOrEncoded<Option<H256>>, ApiError> {
+            Ok(self.state_at(at).chunk_root.into())
+        }
+
+        fn find_recall_block(&self, _recall_byte: u64) -> Option<u64> {
+            unimplemented!()
+        }
+
+        #[advanced]
+        fn locate_recall_tx(
+            &self,
+            at: &BlockId<Block>,
+            _recall_byte: u64,
+        ) -> Result<NativeOrEncoded<Option<(u64, u32, u64)>>, ApiError> {
+            Ok(self.state_at(at).recall_tx.into())
+        }
+
+        fn data_size(&self, _block_number: u64, _extrinsic_index: u32) -> u32 {
+            DATA_SIZE as u32
+        }
+
+        fn weave_offsets(&self, _block_number: u64) -> Vec<(u32, u64)> {
+            unimplemented!()
+        }
+
+        #[advanced]
+        fn is_forgotten(
+            &self,
+            at: &BlockId<Block>,
+            _block_number: u64,
+            _extrinsic_index: u32,
+        ) -> Result<NativeOrEncoded<bool>, ApiError> {
+            Ok(self.state_at(at).forgotten.into())
+        }
+
+        #[advanced]
+        fn require_proof_of_access(
+            &self,
+            at: &BlockId<Block>,
+        ) -> Result<NativeOrEncoded<bool>, ApiError> {
+            Ok((self.state_at(at).weave_size > 0).into())
+        }
+
+        fn block_size(&self) -> u64 {
+            0
+        }
+
+        #[advanced]
+        fn weave_size(&self, at: &BlockId<Block>) -> Result<NativeOrEncoded<u64>, ApiError> {
+            Ok(self.state_at(at).weave_size.into())
+        }
+    }
+
+    impl PoaApi<Block, AccountId> for RuntimeApi {
+        fn poa_config() -> PoaConfiguration {
+            Default::default()
+        }
+
+        fn storage_capacity(_who: AccountId) -> Option<Permill> {
+            None
+        }
+
+        fn all_capacities() -> Vec<(AccountId, Permill)> {
+            Vec::new()
+        }
+    }
+}
+
+impl HeaderBackend<Block> for TestClient {
+    fn header(&self, id: BlockId<Block>) -> sp_blockchain::Result<Option<Header>> {
+        HeaderBackend::header(&*self.inner, id)
+    }
+
+    fn info(&self) -> sp_blockchain::Info<Block> {
+        HeaderBackend::info(&*self.inner)
+    }
+
+    fn status(&self, id: BlockId<Block>) -> sp_blockchain::Result<sp_blockchain::BlockStatus> {
+        HeaderBackend::status(&*self.inner, id)
+    }
+
+    fn number(&self, hash: H256) -> sp_blockchain::Result<Option<u64>> {
+        HeaderBackend::number(&*self.inner, hash)
+    }
+
+    fn hash(&self, number: u64) -> sp_blockchain::Result<Option<H256>> {
+        HeaderBackend::hash(&*self.inner, number)
+    }
+}
+
+impl BlockBackend<Block> for TestClient {
+    fn block_body(&self, id: &BlockId<Block>) -> sp_blockchain::Result<Option<Vec<Extrinsic>>> {
+        self.inner.block_body(id)
+    }
+
+    fn block_indexed_body(
+        &self,
+        id: &BlockId<Block>,
+    ) -> sp_blockchain::Result<Option<Vec<Vec<u8>>>> {
+        self.inner.block_indexed_body(id)
+    }
+
+    fn block(&self, id: &BlockId<Block>) -> sp_blockchain::Result<Option<SignedBlock<Block>>> {
+        self.inner.block(id)
+    }
+
+    fn block_status(&self, id: &BlockId<Block>) -> sp_consensus::Result<sp_consensus::BlockStatus> {
+        self.inner.block_status(id)
+    }
+
+    fn justifications(&self, id: &BlockId<Block>) -> sp_blockchain::Result<Option<Justifications>> {
+        self.inner.justifications(id)
+    }
+
+    fn block_hash(&self, number: u64) -> sp_blockchain::Result<Option<H256>> {
+        self.inner.block_hash(number)
+    }
+
+    fn indexed_transaction(&self, hash: &H256) -> sp_blockchain::Result<Option<Vec<u8>>> {
+        self.inner.indexed_transaction(hash)
+    }
+}
+
+#[async_trait::async_trait]
+impl BlockImport<Block> for TestClient {
+    type Error = ConsensusError;
+    type Transaction = sp_api::TransactionFor<TestClient, Block>;
+
+    async fn check_block(
+        &mut self,
+        block: BlockCheckParams<Block>,
+    ) -> Result<ImportResult, Self::Error> {
+        self.inner.check_block(block).await
+    }
+
+    async fn import_block(
+        &mut self,
+        block: BlockImportParams<Block, Self::Transaction>,
+        new_cache: HashMap<CacheKeyId, Vec<u8>>,
+    ) -> Result<ImportResult, Self::Error> {
+        self.inner
+            .import_block(block.clear_storage_changes_and_mutate(), new_cache)
+            .await
+    }
+}
+
+/// Builds a block on top of `parent` including `transfers` and imports it.
+fn build_and_import_block(
+    client: &mut Arc<Client<Backend>>,
+    parent: H256,
+    transfers: Vec<Transfer>,
+) -> Block {
+    let mut block_builder = client
+        .new_block_at(&BlockId::Hash(parent), Default::default(), false)
+        .unwrap();
+    for transfer in transfers {
+        block_builder.push_transfer(transfer).unwrap();
+    }
+    let block = block_builder.build().unwrap().block;
+    block_on(client.import(BlockOrigin::Own, block.clone())).unwrap();
+    block
+}
+
+/// Returns two transfers between Alice and Bob.
+fn transfers(amount: u64) -> Vec<Transfer> {
+    vec![
+        Transfer {
+            from: Alice.into(),
+            to: Bob.into(),
+            amount,
+            nonce: 0,
+        },
+        Transfer {
+            from: Bob.into(),
+            to: Alice.into(),
+            amount,
+            nonce: 0,
+        },
+    ]
+}
+
+/// Test chain with a side branch:
+///
+/// ```text
+/// genesis <- a1 <- a2 (best)
+///         \
+///          <- b1
+/// ```
+///
+/// The second extrinsic in a1 or b1 is treated as the recall transaction with
+/// data `data`, which is also the whole weave.
+struct TestSetup {
+    client: TestClient,
+    a1: Block,
+    a2: Block,
+    b1: Block,
+    data: Vec<u8>,
+    chunk_root: H256,
+}
+
+impl TestSetup {
+    fn new() -> Self {
+        let mut inner = Arc::new(substrate_test_runtime_client::new());
+
+        let genesis_hash = inner.chain_info().genesis_hash;
+
+        let a1 = build_and_import_block(&mut inner, genesis_hash, transfers(1));
+        let a2 = build_and_import_block(&mut inner, a1.hash(), Vec::new());
+        let b1 = build_and_import_block(&mut inner, genesis_hash, transfers(2));
+
+        assert_eq!(inner.chain_info().best_hash, a2.hash());
+
+        let data = (0..DATA_SIZE)
+            .map(|_| rand::random::<u8>())
+            .collect::<Vec<_>>();
+
+        // The chunk root is computed in the same way as the data is uploaded.
+        let chunk_root = cp_permastore::chunk_root(&data);
+
+        Self {
+            client: TestClient {
+                inner,
+                states: Default::default(),
+            },
+            a1,
+            a2,
+            b1,
+            data,
+            chunk_root,
+        }
+    }
+
+    /// Returns the state in which the recall transaction is included in block #1.
+    fn weave_state(&self) -> RuntimeState {
+        RuntimeState {
+            weave_size: DATA_SIZE as DataIndex,
+            recall_tx: Some((1, RECALL_EXTRINSIC_INDEX, 0)),
+            chunk_root: Some(self.chunk_root),
+            forgotten: false,
+        }
+    }
+
+    fn recall_byte(parent_hash: H256, depth: Depth) -> DataIndex {
+        calculate_challenge_byte(parent_hash.encode(), DATA_SIZE as DataIndex, depth)
+    }
+
+    /// Creates a [`ProofOfAccess`] of which the tx proof is built against
+    /// `recall_block` and the chunk proof is built at `offset` of the recall
+    /// transaction data.
+    fn create_poa(&self, recall_block: &Block, depth: Depth, offset: u32) -> ProofOfAccess {
+        let (header, extrinsics) = recall_block.clone().deconstruct();
+        let tx_path = build_extrinsic_proof::<Block>(
+            RECALL_EXTRINSIC_INDEX,
+            *header.extrinsics_root(),
+            extrinsics,
+        )
+        .unwrap();
+        let chunk_proof = ChunkProofBuilder::new(self.data.clone(), CHUNK_SIZE, offset)
+            .build()
+            .unwrap();
+        ProofOfAccess::new(depth, tx_path, chunk_proof)
+    }
+
+    /// Creates a valid [`ProofOfAccess`] for the block built on top of `parent_hash`.
+    fn create_honest_poa(&self, parent_hash: H256, recall_block: &Block) -> ProofOfAccess {
+        let depth = 1;
+        let recall_byte = Self::recall_byte(parent_hash, depth);
+        self.create_poa(recall_block, depth, recall_byte as u32)
+    }
+
+    fn verify(&self, parent_hash: H256, poa: ProofOfAccess) -> Result<(), Error<Block>> {
+        verify_proof_of_access(&Arc::new(self.client.clone()), parent_hash, poa)
+    }
+
+    /// Imports an empty block on top of `parent_hash` via [`PurePoaBlockImport`],
+    /// the block is sealed with `poa` if any.
+    fn import(
+        &self,
+        parent_hash: H256,
+        poa: Option<ProofOfAccess>,
+    ) -> Result<ImportResult, ConsensusError> {
+        let block = self
+            .client
+            .inner
+            .new_block_at(&BlockId::Hash(parent_hash), Default::default(), false)
+            .unwrap()
+            .build()
+            .unwrap()
+            .block;
+
+        let (header, extrinsics) = block.deconstruct();
+        let mut params = BlockImportParams::new(BlockOrigin::Own, header);
+        params.body = Some(extrinsics);
+        params.fork_choice = Some(ForkChoiceStrategy::LongestChain);
+        if let Some(poa) = poa {
+            params
+                .post_digests
+                .push(DigestItem::Seal(POA_ENGINE_ID_V2, poa.encode()));
+        }
+
+        let mut block_import =
+            PurePoaBlockImport::new(self.client.clone(), Arc::new(self.client.clone()));
+
+        block_on(block_import.import_block(params, Default::default()))
+    }
+}
+
+#[test]
+fn verify_proof_of_access_should_work() {
+    let setup = TestSetup::new();
+
+    let parent_hash = setup.a1.hash();
+    let mut state = setup.weave_state();
+    setup.client.set_state(parent_hash, state.clone());
+
+    let poa = setup.create_honest_poa(parent_hash, &setup.a1);
+
+    assert!(setup.verify(parent_hash, poa.clone()).is_ok());
+
+    // The recall transaction has been forgotten.
+    state.forgotten = true;
+    setup.client.set_state(parent_hash, state);
+    assert!(matches!(
+        setup.verify(parent_hash, poa),
+        Err(Error::RecallExtrinsicForgotten(_, RECALL_EXTRINSIC_INDEX))
+    ));
+}
+
+#[test]
+fn forged_chunk_index_should_be_rejected() {
+    let setup = TestSetup::new();
+
+    let parent_hash = setup.a1.hash();
+    setup.client.set_state(parent_hash, setup.weave_state());
+
+    let depth = 1;
+    let expected_chunk_index =
+        (TestSetup::recall_byte(parent_hash, depth) / CHUNK_SIZE as DataIndex) as u32;
+    let forged_chunk_index = (expected_chunk_index + 1) % CHUNKS_COUNT;
+
+    let forged_poa = setup.create_poa(&setup.a1, depth, forged_chunk_index * CHUNK_SIZE);
+
+    // The forged chunk proof itself is valid against the chunk root.
+    assert!(ChunkProofVerifier::new(forged_poa.chunk_proof.clone())
+        .verify(&setup.chunk_root)
+        .is_ok());
+
+    match setup.verify(parent_hash, forged_poa) {
+        Err(Error::InvalidChunkIndex(expected, got)) => {
+            assert_eq!(expected, expected_chunk_index);
+            assert_eq!(got, forged_chunk_index);
+        }
+        res => panic!("Expected InvalidChunkIndex error, got: {:?}", res),
+    }
+}
+
+#[test]
+fn import_on_side_branch_should_require_poa_of_parent() {
+    let setup = TestSetup::new();
+
+    // The best chain has no data, while the side branch does.
+    let parent_hash = setup.b1.hash();
+    setup.client.set_state(parent_hash, setup.weave_state());
+
+    assert!(setup.import(parent_hash, None).is_err());
+
+    // The recall block #1 must be resolved on the side branch instead of
+    // the canonical chain.
+    let canonical_poa = setup.create_honest_poa(parent_hash, &setup.a1);
+    assert!(setup.import(parent_hash, Some(canonical_poa)).is_err());
+
+    let poa = setup.create_honest_poa(parent_hash, &setup.b1);
+    assert!(matches!(
+        setup.import(parent_hash, Some(poa)),
+        Ok(ImportResult::Imported(_))
+    ));
+}
+
+#[test]
+fn import_on_side_branch_should_skip_poa_if_parent_has_no_data() {
+    let setup = TestSetup::new();
+
+    // The best chain has data, while the side branch does not.
+    setup.client.set_state(setup.a2.hash(), setup.weave_state());
+
+    assert!(setup.import(setup.a2.hash(), None).is_err());
+
+    assert!(matches!(
+        setup.import(setup.b1.hash(), None),
+        Ok(ImportResult::Imported(_))
+    ));
+}
+
+#[test]
+fn recall_block_should_be_resolved_on_the_chain_of_parent() {
+    let setup = TestSetup::new();
+
+    assert_eq!(
+        ancestor_hash_at::<Block, _>(&setup.client.inner, setup.a2.hash(), 1).unwrap(),
+        setup.a1.hash()
+    );
+    assert_eq!(
+        ancestor_hash_at::<Block, _>(&setup.client.inner, setup.b1.hash(), 1).unwrap(),
+        setup.b1.hash()
+    );
+    assert_eq!(
+        ancestor_hash_at::<Block, _>(&setup.client.inner, setup.b1.hash(), 0).unwrap(),
+        setup.client.inner.chain_info().genesis_hash
+    );
+    assert!(ancestor_hash_at::<Block, _>(&setup.client.inner, setup.b1.hash(), 2).is_err());
+}
+
+#[test]
+fn chunk_root_computed_at_upload_should_verify_poa_at_import() {
+    use cc_datastore::PermanentStorage;
+    use cp_permastore::PermaStorage;
+
+    let setup = TestSetup::new();
+
+    let parent_hash = setup.a1.hash();
+    // The chunk root is resolved at the parent in the datastore, the state of the
+    // best block is empty.
+    setup.client.set_state(parent_hash, setup.weave_state());
+
+    let client = Arc::new(setup.client.clone());
+
+    let mut perma_storage = PermanentStorage::new_test(client.clone());
+    perma_storage.submit(&setup.chunk_root.encode(), &setup.data);
+
+    let poa = match PoaBuilder::new(client, perma_storage)
+        .build(parent_hash)
+        .unwrap()
+    {
+        PoaOutcome::Justification(poa) => poa,
+        outcome => panic!("Expected a valid poa, got: {:?}", outcome),
+    };
+
+    assert!(setup.verify(parent_hash, poa).is_ok());
+}
+
+#[test]
+fn fetch_poa_should_decode_both_seal_versions() {
+    let chunk_proof = ChunkProof::new(vec![vec![1, 2, 3]], vec![4, 5, 6], 7);
+
+    let header_with_seal = |seal| {
+        let mut header = Header::new(
+            1,
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+        );
+        header.digest_mut().push(seal);
+        header
+    };
+
+    let legacy_poa = LegacyProofOfAccess {
+        depth: 1,
+        tx_path: vec![vec![8, 9]],
+        chunk_proof: chunk_proof.clone(),
+    };
+    let header = header_with_seal(DigestItem::Seal(POA_ENGINE_ID, legacy_poa.encode()));
+    let hash = header.hash();
+    assert_eq!(
+        fetch_poa::<Block>(header, hash).unwrap(),
+        ProofOfAccess::new(1, vec![vec![8, 9]], chunk_proof.clone())
+    );
+
+    let poa = ProofOfAccess::new(2, vec![vec![8, 9]], chunk_proof).with_miner(vec![10]);
+    let header = header_with_seal(DigestItem::Seal(POA_ENGINE_ID_V2, poa.encode()));
+    let hash = header.hash();
+    assert_eq!(fetch_poa::<Block>(header, hash).unwrap(), poa);
+
+    let mut header = header_with_seal(DigestItem::Seal(POA_ENGINE_ID_V2, poa.encode()));
+    header
+        .digest_mut()
+        .push(DigestItem::Seal(POA_ENGINE_ID, legacy_poa.encode()));
+    let hash = header.hash();
+    assert!(matches!(
+        fetch_poa::<Block>(header, hash),
+        Err(Error::MultipleDigests(_))
+    ));
+}
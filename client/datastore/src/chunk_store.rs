@@ -0,0 +1,747 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// This file is part of Canyon.
+//
+// Copyright (c) 2021 Canyon Labs.
+//
+// Canyon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// Canyon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Canyon. If not, see <http://www.gnu.org/licenses/>.
+
+//! Chunk-addressed store of the transaction data.
+
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use codec::{Decode, Encode};
+use kvdb::{DBTransaction, KeyValueDB};
+use parking_lot::Mutex;
+
+use sp_core::H256;
+
+use cp_permastore::{chunk_hash, CHUNK_SIZE};
+
+/// Column of the data chunks, keyed by `chunk_root ++ chunk_index`.
+const COL_CHUNK: u32 = 0;
+/// Column of [`DataInfo`], keyed by `chunk_root`.
+const COL_DATA_INFO: u32 = 1;
+/// Column of the hashes of all data chunks in order, keyed by `chunk_root`.
+const COL_CHUNK_HASHES: u32 = 2;
+/// Column of [`UploadProgress`] of the transaction data being uploaded chunk by chunk,
+/// keyed by `chunk_root`.
+const COL_UPLOAD: u32 = 3;
+/// Column of [`DataInfo`] of the transaction data quarantined as corrupted, keyed by
+/// `chunk_root`.
+const COL_QUARANTINE: u32 = 4;
+/// Column of the metadata of the workers maintaining the chunk store, e.g., the progress
+/// of the backfill.
+const COL_META: u32 = 5;
+/// Number of columns in the chunk store.
+const NUM_COLUMNS: u32 = 6;
+
+/// Returns the number of chunks of the transaction data of `data_size` bytes.
+fn chunk_count(data_size: u64) -> u32 {
+    ((data_size + CHUNK_SIZE as u64 - 1) / CHUNK_SIZE as u64) as u32
+}
+
+/// Information about the transaction data stored in [`ChunkStore`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub struct DataInfo {
+    /// Byte size of the entire transaction data.
+    pub data_size: u64,
+}
+
+impl DataInfo {
+    /// Returns the number of chunks of the transaction data.
+    pub fn chunk_count(&self) -> u32 {
+        chunk_count(self.data_size)
+    }
+}
+
+/// Progress of the transaction data being uploaded chunk by chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub struct UploadProgress {
+    /// Byte size of the entire transaction data.
+    pub data_size: u64,
+    /// Number of distinct chunks received so far.
+    pub received_chunks: u32,
+    /// Unix timestamp in seconds at which the last distinct chunk was received.
+    pub updated_at: u64,
+}
+
+impl UploadProgress {
+    /// Returns the number of chunks of the transaction data.
+    pub fn chunk_count(&self) -> u32 {
+        chunk_count(self.data_size)
+    }
+
+    /// Returns `true` if all the chunks of the transaction data have been received.
+    pub fn is_complete(&self) -> bool {
+        self.received_chunks >= self.chunk_count()
+    }
+}
+
+/// Returns the current unix timestamp in seconds, used as the `now` of [`UploadProgress`].
+pub fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Returns the database key of chunk `chunk_index` of the transaction data `chunk_root`.
+///
+/// The chunk index is encoded in big endian, so that the chunks of the same
+/// transaction data are laid out in order under the prefix `chunk_root`.
+fn chunk_key(chunk_root: &[u8], chunk_index: u32) -> Vec<u8> {
+    let mut key = Vec::with_capacity(chunk_root.len() + 4);
+    key.extend_from_slice(chunk_root);
+    key.extend_from_slice(&chunk_index.to_be_bytes());
+    key
+}
+
+/// Returns the error of chunk `chunk_index` missing in the stored data.
+fn missing_chunk(chunk_index: u32) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("Chunk {} of stored data is missing", chunk_index),
+    )
+}
+
+/// Reads from `reader` until `buf` is full or the end of `reader` is reached.
+///
+/// Returns the number of bytes read.
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// On-disk store in which the transaction data is stored chunk by chunk.
+///
+/// Each chunk of [`CHUNK_SIZE`] is keyed by `(chunk_root, chunk_index)`, so that a
+/// single chunk can be accessed without loading the entire transaction data. The
+/// [`DataInfo`] of transaction data is written after all of its chunks, the data
+/// is considered as stored only if its [`DataInfo`] exists.
+///
+/// The hashes of chunks are persisted alongside the chunks, so that the chunk proof
+/// can be generated without reading the entire transaction data.
+#[derive(Clone)]
+pub struct ChunkStore {
+    db: Arc<dyn KeyValueDB>,
+    /// Total byte size of the transaction data stored entirely.
+    stored_size: Arc<AtomicU64>,
+    /// Serializes the read-modify-write of [`UploadProgress`], the same data can be
+    /// uploaded by an RPC client and fetched from several peers at the same time.
+    upload_lock: Arc<Mutex<()>>,
+}
+
+impl ChunkStore {
+    /// Opens the chunk store at `path`, which is created if it does not exist.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let path = path.to_str().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid chunk store path: {}", path.display()),
+            )
+        })?;
+        let config = kvdb_rocksdb::DatabaseConfig::with_columns(NUM_COLUMNS);
+        let db = kvdb_rocksdb::Database::open(&config, path)?;
+
+        let mut stored_size = 0u64;
+        for (_, data_info) in db.iter(COL_DATA_INFO) {
+            let data_info = DataInfo::decode(&mut &data_info[..])
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            stored_size += data_info.data_size;
+        }
+
+        Ok(Self {
+            db: Arc::new(db),
+            stored_size: Arc::new(AtomicU64::new(stored_size)),
+            upload_lock: Default::default(),
+        })
+    }
+
+    /// Creates an in-memory chunk store for tests.
+    #[cfg(any(feature = "test-helpers", test))]
+    pub fn new_test() -> Self {
+        Self {
+            db: Arc::new(kvdb_memorydb::create(NUM_COLUMNS)),
+            stored_size: Default::default(),
+            upload_lock: Default::default(),
+        }
+    }
+
+    /// Returns the total byte size of the transaction data stored entirely.
+    ///
+    /// The transaction data in the legacy offchain storage is only counted once it's
+    /// moved to the chunk store, e.g., by the pruner of the network.
+    pub fn stored_size(&self) -> u64 {
+        self.stored_size.load(Ordering::SeqCst)
+    }
+
+    /// Accounts the transaction data of `old` replaced by the one of `new_size` bytes.
+    fn update_stored_size(&self, old: Option<DataInfo>, new_size: u64) {
+        self.stored_size.fetch_add(new_size, Ordering::SeqCst);
+        if let Some(old) = old {
+            self.stored_size.fetch_sub(old.data_size, Ordering::SeqCst);
+        }
+    }
+
+    /// Returns the [`DataInfo`] of transaction data `chunk_root` if it has been
+    /// stored entirely.
+    pub fn data_info(&self, chunk_root: &[u8]) -> io::Result<Option<DataInfo>> {
+        self.db
+            .get(COL_DATA_INFO, chunk_root)?
+            .map(|info| {
+                DataInfo::decode(&mut info.as_slice())
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            })
+            .transpose()
+    }
+
+    /// Returns the [`UploadProgress`] of transaction data `chunk_root` if it's being
+    /// uploaded chunk by chunk.
+    pub fn upload_progress(&self, chunk_root: &[u8]) -> io::Result<Option<UploadProgress>> {
+        self.db
+            .get(COL_UPLOAD, chunk_root)?
+            .map(|progress| {
+                UploadProgress::decode(&mut progress.as_slice())
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            })
+            .transpose()
+    }
+
+    /// Returns the chunk `chunk_index` of transaction data `chunk_root`.
+    pub fn chunk(&self, chunk_root: &[u8], chunk_index: u32) -> io::Result<Option<Vec<u8>>> {
+        self.db.get(COL_CHUNK, &chunk_key(chunk_root, chunk_index))
+    }
+
+    /// Writes the chunk `chunk_index` of transaction data `chunk_root`.
+    ///
+    /// The transaction data is not visible until [`Self::put_data_info`] is called.
+    pub fn put_chunk(&self, chunk_root: &[u8], chunk_index: u32, chunk: &[u8]) -> io::Result<()> {
+        if chunk.len() > CHUNK_SIZE as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Chunk too large, maximum: {}, got: {}",
+                    CHUNK_SIZE,
+                    chunk.len()
+                ),
+            ));
+        }
+        let mut transaction = DBTransaction::new();
+        transaction.put(COL_CHUNK, &chunk_key(chunk_root, chunk_index), chunk);
+        self.db.write(transaction)
+    }
+
+    /// Marks the transaction data `chunk_root` as stored entirely.
+    pub fn put_data_info(&self, chunk_root: &[u8], data_info: DataInfo) -> io::Result<()> {
+        let old = self.data_info(chunk_root)?;
+        let mut transaction = DBTransaction::new();
+        transaction.put(COL_DATA_INFO, chunk_root, &data_info.encode());
+        self.db.write(transaction)?;
+        self.update_stored_size(old, data_info.data_size);
+        Ok(())
+    }
+
+    /// Writes the transaction data `chunk_root` read from `reader` chunk by chunk.
+    ///
+    /// Returns the byte size of the written data.
+    pub fn write_data<R: Read>(&self, chunk_root: &[u8], mut reader: R) -> io::Result<u64> {
+        let mut buf = vec![0u8; CHUNK_SIZE as usize];
+        let mut data_size = 0u64;
+        let mut chunk_hashes = Vec::new();
+        loop {
+            let len = read_full(&mut reader, &mut buf)?;
+            if len == 0 {
+                break;
+            }
+            let chunk = &buf[..len];
+            self.put_chunk(chunk_root, chunk_hashes.len() as u32, chunk)?;
+            chunk_hashes.push(chunk_hash(chunk));
+            data_size += len as u64;
+            if len < buf.len() {
+                break;
+            }
+        }
+
+        let old = self.data_info(chunk_root)?;
+        let mut transaction = DBTransaction::new();
+        transaction.put(COL_CHUNK_HASHES, chunk_root, &chunk_hashes.encode());
+        transaction.put(COL_DATA_INFO, chunk_root, &DataInfo { data_size }.encode());
+        // The entire data supersedes the pending upload of the same data, if any.
+        transaction.delete(COL_UPLOAD, chunk_root);
+        transaction.delete(COL_QUARANTINE, chunk_root);
+        self.db.write(transaction)?;
+        self.update_stored_size(old, data_size);
+
+        Ok(data_size)
+    }
+
+    /// Writes the chunk `chunk_index` of transaction data `chunk_root` of `data_size`
+    /// bytes being uploaded chunk by chunk.
+    ///
+    /// The chunk is written along with the updated [`UploadProgress`] atomically, the
+    /// progress is unchanged if the chunk has been received before. The transaction
+    /// data is not visible until [`Self::complete_upload`] is called.
+    ///
+    /// `now` is the current unix timestamp in seconds, which is recorded in the progress
+    /// for expiring the abandoned uploads.
+    pub fn put_uploaded_chunk(
+        &self,
+        chunk_root: &[u8],
+        data_size: u64,
+        chunk_index: u32,
+        chunk: &[u8],
+        now: u64,
+    ) -> io::Result<UploadProgress> {
+        let _lock = self.upload_lock.lock();
+
+        // The upload may have been completed by another uploader in the meanwhile.
+        if self.data_info(chunk_root)?.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "Transaction data has been stored entirely",
+            ));
+        }
+
+        let mut progress = match self.upload_progress(chunk_root)? {
+            Some(progress) if progress.data_size != data_size => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "Data size mismatch, expected: {}, got: {}",
+                        progress.data_size, data_size
+                    ),
+                ));
+            }
+            Some(progress) => progress,
+            None => UploadProgress {
+                data_size,
+                received_chunks: 0,
+                updated_at: now,
+            },
+        };
+
+        if chunk_index >= progress.chunk_count() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Chunk index out of range, chunk count: {}, got: {}",
+                    progress.chunk_count(),
+                    chunk_index
+                ),
+            ));
+        }
+
+        if self.chunk(chunk_root, chunk_index)?.is_some() {
+            return Ok(progress);
+        }
+
+        progress.received_chunks += 1;
+        progress.updated_at = now;
+
+        let mut transaction = DBTransaction::new();
+        transaction.put(COL_CHUNK, &chunk_key(chunk_root, chunk_index), chunk);
+        transaction.put(COL_UPLOAD, chunk_root, &progress.encode());
+        self.db.write(transaction)?;
+
+        Ok(progress)
+    }
+
+    /// Restarts the upload of transaction data `chunk_root` as `data_size` bytes, which
+    /// replaces the existing [`UploadProgress`] declaring a different data size.
+    ///
+    /// The chunks received before are verified against `chunk_root` individually and
+    /// hence kept, only the ones within `data_size` are counted as received.
+    pub fn restart_upload(
+        &self,
+        chunk_root: &[u8],
+        data_size: u64,
+        now: u64,
+    ) -> io::Result<UploadProgress> {
+        let _lock = self.upload_lock.lock();
+
+        let mut progress = UploadProgress {
+            data_size,
+            received_chunks: 0,
+            updated_at: now,
+        };
+        for chunk_index in 0..progress.chunk_count() {
+            if self
+                .db
+                .has_key(COL_CHUNK, &chunk_key(chunk_root, chunk_index))?
+            {
+                progress.received_chunks += 1;
+            }
+        }
+
+        let mut transaction = DBTransaction::new();
+        transaction.put(COL_UPLOAD, chunk_root, &progress.encode());
+        self.db.write(transaction)?;
+
+        Ok(progress)
+    }
+
+    /// Returns all the transaction data being uploaded chunk by chunk along with their
+    /// [`UploadProgress`].
+    pub fn pending_uploads(&self) -> io::Result<Vec<(Vec<u8>, UploadProgress)>> {
+        self.db
+            .iter(COL_UPLOAD)
+            .map(|(chunk_root, progress)| {
+                UploadProgress::decode(&mut &progress[..])
+                    .map(|progress| (chunk_root.into_vec(), progress))
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            })
+            .collect()
+    }
+
+    /// Removes the pending uploads of which no new chunk has been received since
+    /// `expired_before`, including the chunks received so far.
+    ///
+    /// Returns the number of removed uploads.
+    pub fn remove_expired_uploads(&self, expired_before: u64) -> io::Result<usize> {
+        let _lock = self.upload_lock.lock();
+
+        let mut transaction = DBTransaction::new();
+        let mut removed = 0;
+        for (chunk_root, progress) in self.pending_uploads()? {
+            if progress.updated_at >= expired_before {
+                continue;
+            }
+            transaction.delete(COL_UPLOAD, &chunk_root);
+            // The chunks are shared with the data stored entirely in the meanwhile.
+            if self.data_info(&chunk_root)?.is_none() {
+                transaction.delete_prefix(COL_CHUNK, &chunk_root);
+            }
+            removed += 1;
+        }
+        if removed > 0 {
+            self.db.write(transaction)?;
+        }
+        Ok(removed)
+    }
+
+    /// Returns the hashes of all chunks of transaction data `chunk_root` which has been
+    /// received entirely but not completed yet.
+    ///
+    /// Returns `None` if some chunks have not been received.
+    pub fn uploaded_chunk_hashes(&self, chunk_root: &[u8]) -> io::Result<Option<Vec<H256>>> {
+        let progress = match self.upload_progress(chunk_root)? {
+            Some(progress) if progress.is_complete() => progress,
+            _ => return Ok(None),
+        };
+        self.read_chunk_hashes(chunk_root, progress.chunk_count())
+            .map(Some)
+    }
+
+    /// Marks the transaction data `chunk_root` uploaded chunk by chunk as stored entirely.
+    ///
+    /// `chunk_hashes` are expected to be the hashes of received chunks in order, which
+    /// have been validated against `chunk_root` by the caller.
+    ///
+    /// Completing the upload which has been completed in the meanwhile is a no-op.
+    pub fn complete_upload(
+        &self,
+        chunk_root: &[u8],
+        chunk_hashes: &[H256],
+    ) -> io::Result<DataInfo> {
+        let _lock = self.upload_lock.lock();
+
+        let old = self.data_info(chunk_root)?;
+        let progress = match (self.upload_progress(chunk_root)?, old) {
+            (Some(progress), _) if progress.is_complete() => progress,
+            (None, Some(data_info)) => return Ok(data_info),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Transaction data has not been received entirely",
+                ))
+            }
+        };
+
+        let data_info = DataInfo {
+            data_size: progress.data_size,
+        };
+
+        let mut transaction = DBTransaction::new();
+        transaction.put(COL_CHUNK_HASHES, chunk_root, &chunk_hashes.encode());
+        transaction.put(COL_DATA_INFO, chunk_root, &data_info.encode());
+        transaction.delete(COL_UPLOAD, chunk_root);
+        transaction.delete(COL_QUARANTINE, chunk_root);
+        self.db.write(transaction)?;
+        self.update_stored_size(old, data_info.data_size);
+
+        Ok(data_info)
+    }
+
+    /// Reads the first `chunk_count` chunks of transaction data `chunk_root` one by one
+    /// and returns their hashes.
+    fn read_chunk_hashes(&self, chunk_root: &[u8], chunk_count: u32) -> io::Result<Vec<H256>> {
+        (0..chunk_count)
+            .map(|chunk_index| {
+                self.chunk(chunk_root, chunk_index)?
+                    .map(|chunk| chunk_hash(&chunk))
+                    .ok_or_else(|| missing_chunk(chunk_index))
+            })
+            .collect()
+    }
+
+    /// Returns an iterator over the chunks of transaction data `chunk_root`, the
+    /// chunks are read from the database lazily.
+    ///
+    /// Returns `None` if the transaction data has not been stored entirely.
+    pub fn chunks<'a>(
+        &'a self,
+        chunk_root: &'a [u8],
+    ) -> io::Result<Option<impl Iterator<Item = io::Result<Vec<u8>>> + 'a>> {
+        Ok(self.data_info(chunk_root)?.map(move |data_info| {
+            (0..data_info.chunk_count()).map(move |chunk_index| {
+                self.chunk(chunk_root, chunk_index)?
+                    .ok_or_else(|| missing_chunk(chunk_index))
+            })
+        }))
+    }
+
+    /// Returns the persisted hashes of all chunks of transaction data `chunk_root` in order.
+    ///
+    /// Unlike [`Self::chunk_hashes`], nothing is returned if they have not been persisted.
+    pub fn persisted_chunk_hashes(&self, chunk_root: &[u8]) -> io::Result<Option<Vec<H256>>> {
+        self.db
+            .get(COL_CHUNK_HASHES, chunk_root)?
+            .map(|encoded| {
+                Vec::<H256>::decode(&mut encoded.as_slice())
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            })
+            .transpose()
+    }
+
+    /// Returns the hashes of all chunks of transaction data `chunk_root` in order.
+    ///
+    /// The chunk hashes of transaction data stored without them, e.g., the data
+    /// stored before the chunk hashes are persisted, are computed by reading the
+    /// chunks one by one and persisted on the first access.
+    pub fn chunk_hashes(&self, chunk_root: &[u8]) -> io::Result<Option<Vec<H256>>> {
+        let data_info = match self.data_info(chunk_root)? {
+            Some(data_info) => data_info,
+            None => return Ok(None),
+        };
+
+        if let Some(chunk_hashes) = self.persisted_chunk_hashes(chunk_root)? {
+            return Ok(Some(chunk_hashes));
+        }
+
+        let chunk_hashes = self.read_chunk_hashes(chunk_root, data_info.chunk_count())?;
+
+        let mut transaction = DBTransaction::new();
+        transaction.put(COL_CHUNK_HASHES, chunk_root, &chunk_hashes.encode());
+        self.db.write(transaction)?;
+
+        log::debug!(
+            target: "datastore",
+            "Persisted the chunk hashes of stored data {:?}",
+            chunk_root,
+        );
+
+        Ok(Some(chunk_hashes))
+    }
+
+    /// Returns the entire transaction data `chunk_root`.
+    pub fn read_data(&self, chunk_root: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        let chunks = match self.chunks(chunk_root)? {
+            Some(chunks) => chunks,
+            None => return Ok(None),
+        };
+        let mut data = Vec::new();
+        for chunk in chunks {
+            data.extend_from_slice(&chunk?);
+        }
+        Ok(Some(data))
+    }
+
+    /// Returns a reader of at most `len` bytes of transaction data `chunk_root` starting
+    /// from `offset`, only the chunks covering the range are read and lazily.
+    ///
+    /// The range is truncated at the end of the transaction data.
+    pub fn range_reader(
+        &self,
+        chunk_root: &[u8],
+        offset: u64,
+        len: u64,
+    ) -> io::Result<Option<RangeReader>> {
+        Ok(self.data_info(chunk_root)?.map(|data_info| RangeReader {
+            chunk_store: self.clone(),
+            chunk_root: chunk_root.to_vec(),
+            next: offset,
+            end: offset.saturating_add(len).min(data_info.data_size),
+        }))
+    }
+
+    /// Returns at most `len` bytes of transaction data `chunk_root` starting from `offset`,
+    /// only the chunks covering the range are read.
+    ///
+    /// The range is truncated at the end of the transaction data.
+    pub fn read_range(
+        &self,
+        chunk_root: &[u8],
+        offset: u64,
+        len: u64,
+    ) -> io::Result<Option<Vec<u8>>> {
+        let range_reader = match self.range_reader(chunk_root, offset, len)? {
+            Some(range_reader) => range_reader,
+            None => return Ok(None),
+        };
+
+        let mut data = Vec::with_capacity(range_reader.remaining() as usize);
+        for piece in range_reader {
+            data.extend_from_slice(&piece?);
+        }
+
+        Ok(Some(data))
+    }
+
+    /// Removes the transaction data `chunk_root`, including the chunks received so far
+    /// if it's being uploaded.
+    pub fn remove(&self, chunk_root: &[u8]) -> io::Result<()> {
+        let _lock = self.upload_lock.lock();
+
+        let old = self.data_info(chunk_root)?;
+        let mut transaction = DBTransaction::new();
+        transaction.delete(COL_UPLOAD, chunk_root);
+        transaction.delete(COL_DATA_INFO, chunk_root);
+        transaction.delete(COL_CHUNK_HASHES, chunk_root);
+        transaction.delete_prefix(COL_CHUNK, chunk_root);
+        self.db.write(transaction)?;
+        self.update_stored_size(old, 0);
+        Ok(())
+    }
+
+    /// Removes the corrupted transaction data `chunk_root` and records it as quarantined,
+    /// so that it's no longer served until being stored again.
+    pub fn quarantine(&self, chunk_root: &[u8]) -> io::Result<()> {
+        let data_info = match self.data_info(chunk_root)? {
+            Some(data_info) => data_info,
+            None => return Ok(()),
+        };
+
+        let mut transaction = DBTransaction::new();
+        transaction.put(COL_QUARANTINE, chunk_root, &data_info.encode());
+        transaction.delete(COL_DATA_INFO, chunk_root);
+        transaction.delete(COL_CHUNK_HASHES, chunk_root);
+        transaction.delete_prefix(COL_CHUNK, chunk_root);
+        self.db.write(transaction)?;
+        self.update_stored_size(Some(data_info), 0);
+        Ok(())
+    }
+
+    /// Returns the metadata `key`.
+    pub fn meta(&self, key: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        self.db.get(COL_META, key)
+    }
+
+    /// Writes the metadata `key`.
+    pub fn put_meta(&self, key: &[u8], value: &[u8]) -> io::Result<()> {
+        let mut transaction = DBTransaction::new();
+        transaction.put(COL_META, key, value);
+        self.db.write(transaction)
+    }
+
+    /// Returns the chunk roots and [`DataInfo`] of the quarantined transaction data which
+    /// has not been stored again.
+    pub fn quarantined(&self) -> io::Result<Vec<(Vec<u8>, DataInfo)>> {
+        self.db
+            .iter(COL_QUARANTINE)
+            .map(|(chunk_root, data_info)| {
+                DataInfo::decode(&mut &data_info[..])
+                    .map(|data_info| (chunk_root.into_vec(), data_info))
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            })
+            .collect()
+    }
+}
+
+/// Iterator over a byte range of the transaction data stored in [`ChunkStore`].
+///
+/// Each item is the part of a chunk covered by the range, the chunks are read on demand.
+pub struct RangeReader {
+    chunk_store: ChunkStore,
+    chunk_root: Vec<u8>,
+    /// Offset of the next byte to read.
+    next: u64,
+    /// Offset of the end of range, exclusive.
+    end: u64,
+}
+
+impl RangeReader {
+    /// Returns the number of bytes remaining in the range.
+    pub fn remaining(&self) -> u64 {
+        self.end.saturating_sub(self.next)
+    }
+
+    fn read_piece(&self) -> io::Result<Vec<u8>> {
+        let chunk_size = CHUNK_SIZE as u64;
+        let chunk_index = (self.next / chunk_size) as u32;
+        let chunk_start = chunk_index as u64 * chunk_size;
+
+        let chunk = self
+            .chunk_store
+            .chunk(&self.chunk_root, chunk_index)?
+            .ok_or_else(|| missing_chunk(chunk_index))?;
+
+        let from = (self.next - chunk_start) as usize;
+        let to = (self.end.min(chunk_start + chunk_size) - chunk_start) as usize;
+        chunk
+            .get(from..to)
+            .map(|piece| piece.to_vec())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Chunk {} of stored data is truncated", chunk_index),
+                )
+            })
+    }
+}
+
+impl Iterator for RangeReader {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= self.end {
+            return None;
+        }
+
+        match self.read_piece() {
+            Ok(piece) => {
+                self.next += piece.len() as u64;
+                Some(Ok(piece))
+            }
+            Err(e) => {
+                // Stop reading once the stored data is found broken.
+                self.next = self.end;
+                Some(Err(e))
+            }
+        }
+    }
+}
@@ -21,31 +21,71 @@
 //! This crate provides the feature of persistent storage for the transaction data
 //! expected to exist indefinitely.
 //!
-//! Currently, it is implemented on the top of offchain storage, which is a persistent
-//! local storage of each node.
+//! The transaction data is stored chunk by chunk in a dedicated on-disk [`ChunkStore`],
+//! the data stored in the offchain storage of node previously is moved into the
+//! [`ChunkStore`] lazily once it's accessed.
+//!
+//! The stored transaction data is re-verified against the on-chain chunk roots by
+//! [`Scrubber`] periodically, the corrupted data is quarantined.
 
+mod chunk_store;
+mod policy;
+mod scrubber;
 #[cfg(test)]
 mod tests;
 
+use std::io;
 use std::sync::Arc;
 
 use codec::Encode;
 
+use sc_client_api::execution_extensions::ExtensionsFactory;
 use sc_client_db::offchain::LocalStorage;
 use sp_api::ProvideRuntimeApi;
 use sp_blockchain::HeaderBackend;
+use sp_core::{
+    offchain::{Capabilities, Capability},
+    H256,
+};
+use sp_externalities::Extensions;
 use sp_runtime::{
     generic::BlockId,
     offchain::OffchainStorage,
     traits::{Block as BlockT, NumberFor},
 };
 
-use cp_permastore::{PermaStorage, PermastoreApi};
+use cp_permastore::{PermaStorage, PermastoreApi, PermastoreExt};
 
-/// Permanent storage backed by offchain storage.
+pub use self::chunk_store::{unix_timestamp, ChunkStore, DataInfo, RangeReader, UploadProgress};
+pub use self::policy::{RangeSelection, SelectedRanges, StoragePolicy, WEAVE_RANGE_SIZE};
+pub use self::scrubber::{
+    verify_data, CorruptedData, Corruption, DataIntegrity, ScrubReport, Scrubber,
+};
+
+/// Returns the transaction data of `CHUNK_SIZE * full_chunks + extra_bytes` bytes
+/// for tests.
+#[cfg(any(feature = "test-helpers", test))]
+pub fn test_data(full_chunks: u32, extra_bytes: u32) -> Vec<u8> {
+    seeded_test_data(full_chunks, extra_bytes, 0)
+}
+
+/// Same as [`test_data`], but the bytes are shifted by `seed` so that the data of
+/// distinct seeds has distinct chunk roots.
+#[cfg(any(feature = "test-helpers", test))]
+pub fn seeded_test_data(full_chunks: u32, extra_bytes: u32, seed: u32) -> Vec<u8> {
+    (0..cp_permastore::CHUNK_SIZE * full_chunks + extra_bytes)
+        .map(|i| ((i + seed) % 251) as u8)
+        .collect()
+}
+
+/// Permanent storage backed by [`ChunkStore`].
 #[derive(Clone)]
 pub struct PermanentStorage<C> {
-    offchain_storage: LocalStorage,
+    chunk_store: ChunkStore,
+    /// Offchain storage in which the entire transaction data was stored as a single
+    /// value before [`ChunkStore`] is introduced.
+    legacy_storage: Option<LocalStorage>,
+    policy: StoragePolicy,
     client: Arc<C>,
 }
 
@@ -54,18 +94,70 @@ impl<C> PermanentStorage<C> {
     #[cfg(any(feature = "test-helpers", test))]
     pub fn new_test(client: Arc<C>) -> Self {
         Self {
-            offchain_storage: LocalStorage::new_test(),
+            chunk_store: ChunkStore::new_test(),
+            legacy_storage: None,
+            policy: StoragePolicy::default(),
             client,
         }
     }
 
-    /// Creates a new instance of [`PermaStorage`] backed by offchain storage.
-    pub fn new(offchain_storage: LocalStorage, client: Arc<C>) -> Self {
+    /// Creates a new instance of [`PermaStorage`] backed by `chunk_store`.
+    pub fn new(chunk_store: ChunkStore, client: Arc<C>) -> Self {
         Self {
-            offchain_storage,
+            chunk_store,
+            legacy_storage: None,
+            policy: StoragePolicy::default(),
+            client,
+        }
+    }
+
+    /// Sets the offchain storage in which the transaction data was stored previously.
+    ///
+    /// The data found in the legacy storage is moved to the chunk store on access.
+    pub fn with_legacy_storage(mut self, legacy_storage: LocalStorage) -> Self {
+        self.legacy_storage = Some(legacy_storage);
+        self
+    }
+
+    /// Sets the policy of storing the transaction data locally.
+    pub fn with_storage_policy(mut self, policy: StoragePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Returns a [`PermanentStorage`] sharing the same underlying storage with `client`.
+    pub fn with_client<D>(self, client: Arc<D>) -> PermanentStorage<D> {
+        PermanentStorage {
+            chunk_store: self.chunk_store,
+            legacy_storage: self.legacy_storage,
+            policy: self.policy,
             client,
         }
     }
+
+    /// Moves the transaction data `key` from the legacy storage to the chunk store.
+    ///
+    /// Returns the transaction data if it's found in the legacy storage.
+    fn migrate_legacy_data(&self, key: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        let mut legacy_storage = match &self.legacy_storage {
+            Some(legacy_storage) => legacy_storage.clone(),
+            None => return Ok(None),
+        };
+
+        match legacy_storage.get(sp_offchain::STORAGE_PREFIX, key) {
+            Some(data) => {
+                self.chunk_store.write_data(key, data.as_slice())?;
+                legacy_storage.remove(sp_offchain::STORAGE_PREFIX, key);
+                log::debug!(
+                    target: "datastore",
+                    "Moved the transaction data {:?} from the offchain storage to the chunk store",
+                    key,
+                );
+                Ok(Some(data))
+            }
+            None => Ok(None),
+        }
+    }
 }
 
 impl<C> cp_permastore::PermaStorage for PermanentStorage<C>
@@ -82,8 +174,13 @@ where
     /// NOTE: the maximum size of served value is 10MiB,
     /// this limit should be enforced by the higher level API.
     fn submit(&mut self, key: &[u8], value: &[u8]) {
-        self.offchain_storage
-            .set(sp_offchain::STORAGE_PREFIX, key, value)
+        if let Err(e) = self.chunk_store.write_data(key, value) {
+            log::error!(
+                target: "datastore",
+                "Failed to write the transaction data {:?}: {:?}",
+                key, e,
+            );
+        }
     }
 
     /// Returns the entire transaction data given `key`.
@@ -92,7 +189,18 @@ where
     ///
     /// * `key`: chunk_root of the transaction data.
     fn retrieve(&self, key: &[u8]) -> Option<Vec<u8>> {
-        self.offchain_storage.get(sp_offchain::STORAGE_PREFIX, key)
+        let data = match self.chunk_store.read_data(key) {
+            Ok(None) => self.migrate_legacy_data(key),
+            res => res,
+        };
+        data.unwrap_or_else(|e| {
+            log::error!(
+                target: "datastore",
+                "Failed to read the transaction data {:?}: {:?}",
+                key, e,
+            );
+            None
+        })
     }
 
     /// Removes the storage value under given key.
@@ -101,8 +209,102 @@ where
     ///
     /// * `key`: encoded chunk root of transaction data.
     fn remove(&mut self, key: &[u8]) {
-        self.offchain_storage
-            .remove(sp_offchain::STORAGE_PREFIX, key)
+        if let Err(e) = self.chunk_store.remove(key) {
+            log::error!(
+                target: "datastore",
+                "Failed to remove the transaction data {:?}: {:?}",
+                key, e,
+            );
+        }
+        if let Some(legacy_storage) = self.legacy_storage.as_mut() {
+            legacy_storage.remove(sp_offchain::STORAGE_PREFIX, key);
+        }
+    }
+
+    /// Checks if the transaction data exists given `key` without reading the data.
+    fn exists(&self, key: &[u8]) -> bool {
+        matches!(self.chunk_store.data_info(key), Ok(Some(_)))
+            || self
+                .legacy_storage
+                .as_ref()
+                .map(|legacy_storage| {
+                    legacy_storage
+                        .get(sp_offchain::STORAGE_PREFIX, key)
+                        .is_some()
+                })
+                .unwrap_or(false)
+    }
+}
+
+/// Permanent storage of which the transaction data can be accessed chunk by chunk.
+pub trait ChunkStorage: PermaStorage {
+    /// Returns the underlying [`ChunkStore`].
+    fn chunk_store(&self) -> &ChunkStore;
+
+    /// Returns the [`DataInfo`] of transaction data `key` if it has been stored entirely.
+    ///
+    /// The chunks of transaction data are accessible from [`Self::chunk_store`]
+    /// once it returns `Some(_)`.
+    fn data_info(&self, key: &[u8]) -> io::Result<Option<DataInfo>> {
+        self.chunk_store().data_info(key)
+    }
+
+    /// Returns the policy of storing the transaction data locally.
+    fn storage_policy(&self) -> StoragePolicy {
+        StoragePolicy::default()
+    }
+}
+
+impl<C> ChunkStorage for PermanentStorage<C>
+where
+    C: Send + Sync,
+{
+    fn chunk_store(&self) -> &ChunkStore {
+        &self.chunk_store
+    }
+
+    /// Moves the transaction data from the legacy storage to the chunk store if any.
+    fn data_info(&self, key: &[u8]) -> io::Result<Option<DataInfo>> {
+        match self.chunk_store.data_info(key)? {
+            Some(data_info) => Ok(Some(data_info)),
+            None if self.migrate_legacy_data(key)?.is_some() => self.chunk_store.data_info(key),
+            None => Ok(None),
+        }
+    }
+
+    fn storage_policy(&self) -> StoragePolicy {
+        self.policy
+    }
+}
+
+/// Extensions factory that registers [`PermastoreExt`] for the offchain calls.
+///
+/// The runtime is then able to check whether the transaction data exists locally
+/// in the transaction pool validation. [`PermastoreExt`] is never registered for
+/// the block import and block authoring, which have no offchain capabilities,
+/// thus the block execution is independent of the local storage of node.
+pub struct PermastoreExtensionsFactory {
+    storage: PermanentStorage<()>,
+}
+
+impl PermastoreExtensionsFactory {
+    /// Creates a new instance of [`PermastoreExtensionsFactory`].
+    ///
+    /// The client is not required for checking the existence of data, which
+    /// also avoids the reference cycle between the client and its execution
+    /// extensions.
+    pub fn new(storage: PermanentStorage<()>) -> Self {
+        Self { storage }
+    }
+}
+
+impl ExtensionsFactory for PermastoreExtensionsFactory {
+    fn extensions_for(&self, capabilities: Capabilities) -> Extensions {
+        let mut extensions = Extensions::new();
+        if capabilities.has(Capability::OffchainDbRead) {
+            extensions.register(PermastoreExt::new(self.storage.clone()));
+        }
+        extensions
     }
 }
 
@@ -121,6 +323,9 @@ pub enum Error<Block: BlockT> {
     /// Runtime api error.
     #[error(transparent)]
     ApiError(#[from] sp_api::ApiError),
+    /// Chunk store error.
+    #[error(transparent)]
+    Io(#[from] io::Error),
 }
 
 /// Backend for storing a map of (block_number, extrinsic_index) to chunk_root.
@@ -139,40 +344,89 @@ pub trait ChunkRootBackend<Block: BlockT> {
 /// Permanent transaction data backend.
 ///
 /// High level API for accessing the transaction data.
+///
+/// The transaction data of extrinsic `extrinsic_index` in block `id` is resolved
+/// against the on-chain state of block `at`, the best block if it's `None`. The
+/// state of the chain on which block `id` is located must be used when `id` is not
+/// on the best chain, e.g., when importing a block on a side branch.
 pub trait TransactionDataBackend<Block: BlockT>: PermaStorage + ChunkRootBackend<Block> {
     /// Get transaction data. Returns `None` if data is not found.
     fn transaction_data(
         &self,
+        at: Option<BlockId<Block>>,
+        id: BlockId<Block>,
+        extrinsic_index: u32,
+    ) -> Result<Option<Vec<u8>>, Error<Block>>;
+
+    /// Get the chunk `chunk_index` of transaction data without reading the entire data.
+    /// Returns `None` if the chunk is not found.
+    fn transaction_chunk(
+        &self,
+        at: Option<BlockId<Block>>,
         id: BlockId<Block>,
         extrinsic_index: u32,
+        chunk_index: u32,
     ) -> Result<Option<Vec<u8>>, Error<Block>>;
+
+    /// Get the hashes of all chunks of transaction data in order without holding the
+    /// entire data in memory. Returns `None` if data is not found.
+    fn transaction_chunk_hashes(
+        &self,
+        at: Option<BlockId<Block>>,
+        id: BlockId<Block>,
+        extrinsic_index: u32,
+    ) -> Result<Option<Vec<H256>>, Error<Block>>;
+
+    /// Get the chunk root and the byte size of transaction data recorded on chain,
+    /// regardless of whether the data is stored locally. Returns `None` if the
+    /// extrinsic does not store any data.
+    fn transaction_data_info(
+        &self,
+        at: Option<BlockId<Block>>,
+        id: BlockId<Block>,
+        extrinsic_index: u32,
+    ) -> Result<Option<(Block::Hash, u32)>, Error<Block>>;
 }
 
-impl<Block, C> TransactionDataBackend<Block> for PermanentStorage<C>
-where
-    Block: BlockT,
-    C: HeaderBackend<Block> + ProvideRuntimeApi<Block> + Send + Sync,
-    C::Api: cp_permastore::PermastoreApi<Block, NumberFor<Block>, u32, Block::Hash>,
-{
-    fn transaction_data(
+impl<C> PermanentStorage<C> {
+    /// Returns the block number given `block_id`.
+    fn block_number<Block>(
+        &self,
+        block_id: BlockId<Block>,
+    ) -> Result<NumberFor<Block>, Error<Block>>
+    where
+        Block: BlockT,
+        C: HeaderBackend<Block>,
+    {
+        self.client
+            .block_number_from_id(&block_id)
+            .map_err(Box::new)?
+            .ok_or(Error::BlockNumberNotFound(block_id))
+    }
+
+    /// Returns the encoded chunk root of transaction data given `block_id` and `extrinsic_index`,
+    /// the chunk root is read from the state of block `at`.
+    fn chunk_root_key<Block>(
         &self,
+        at: Option<BlockId<Block>>,
         block_id: BlockId<Block>,
         extrinsic_index: u32,
-    ) -> Result<Option<Vec<u8>>, Error<Block>> {
+    ) -> Result<Vec<u8>, Error<Block>>
+    where
+        Block: BlockT,
+        C: HeaderBackend<Block> + ProvideRuntimeApi<Block> + Send + Sync,
+        C::Api: cp_permastore::PermastoreApi<Block, NumberFor<Block>, u32, Block::Hash>,
+    {
         log::debug!(
             target: "datastore",
             "Fetching chunk root at block_id: {}, extrinsic_index: {}",
             block_id, extrinsic_index,
         );
 
-        let block_number = self
-            .client
-            .block_number_from_id(&block_id)
-            .map_err(Box::new)?
-            .ok_or(Error::BlockNumberNotFound(block_id))?;
+        let block_number = self.block_number(block_id)?;
 
         let chunk_root = self
-            .chunk_root(None, block_number, extrinsic_index)?
+            .chunk_root(at, block_number, extrinsic_index)?
             .ok_or(Error::ChunkRootIsNone(block_id, extrinsic_index))?;
 
         let key = chunk_root.encode();
@@ -182,7 +436,81 @@ where
             chunk_root, key,
         );
 
-        Ok(self.retrieve(&key))
+        Ok(key)
+    }
+}
+
+impl<Block, C> TransactionDataBackend<Block> for PermanentStorage<C>
+where
+    Block: BlockT,
+    C: HeaderBackend<Block> + ProvideRuntimeApi<Block> + Send + Sync,
+    C::Api: cp_permastore::PermastoreApi<Block, NumberFor<Block>, u32, Block::Hash>,
+{
+    fn transaction_data(
+        &self,
+        at: Option<BlockId<Block>>,
+        block_id: BlockId<Block>,
+        extrinsic_index: u32,
+    ) -> Result<Option<Vec<u8>>, Error<Block>> {
+        let key = self.chunk_root_key(at, block_id, extrinsic_index)?;
+
+        match self.chunk_store.read_data(&key)? {
+            Some(data) => Ok(Some(data)),
+            None => Ok(self.migrate_legacy_data(&key)?),
+        }
+    }
+
+    fn transaction_chunk(
+        &self,
+        at: Option<BlockId<Block>>,
+        block_id: BlockId<Block>,
+        extrinsic_index: u32,
+        chunk_index: u32,
+    ) -> Result<Option<Vec<u8>>, Error<Block>> {
+        let key = self.chunk_root_key(at, block_id, extrinsic_index)?;
+
+        if self.data_info(&key)?.is_none() {
+            return Ok(None);
+        }
+
+        Ok(self.chunk_store.chunk(&key, chunk_index)?)
+    }
+
+    fn transaction_chunk_hashes(
+        &self,
+        at: Option<BlockId<Block>>,
+        block_id: BlockId<Block>,
+        extrinsic_index: u32,
+    ) -> Result<Option<Vec<H256>>, Error<Block>> {
+        let key = self.chunk_root_key(at, block_id, extrinsic_index)?;
+
+        if self.data_info(&key)?.is_none() {
+            return Ok(None);
+        }
+
+        Ok(self.chunk_store.chunk_hashes(&key)?)
+    }
+
+    fn transaction_data_info(
+        &self,
+        at: Option<BlockId<Block>>,
+        block_id: BlockId<Block>,
+        extrinsic_index: u32,
+    ) -> Result<Option<(Block::Hash, u32)>, Error<Block>> {
+        let block_number = self.block_number(block_id)?;
+
+        let chunk_root = match self.chunk_root(at, block_number, extrinsic_index)? {
+            Some(chunk_root) => chunk_root,
+            None => return Ok(None),
+        };
+
+        let at = at.unwrap_or_else(|| BlockId::hash(self.client.info().best_hash));
+        let data_size = self
+            .client
+            .runtime_api()
+            .data_size(&at, block_number, extrinsic_index)?;
+
+        Ok(Some((chunk_root, data_size)))
     }
 }
 
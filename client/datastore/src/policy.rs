@@ -0,0 +1,160 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// This file is part of Canyon.
+//
+// Copyright (c) 2021 Canyon Labs.
+//
+// Canyon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// Canyon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Canyon. If not, see <http://www.gnu.org/licenses/>.
+
+//! Policy of storing a part of the weave locally.
+//!
+//! Not every node is able to store the entire weave. With a limited disk budget, the
+//! weave is split into the ranges of [`WEAVE_RANGE_SIZE`] bytes, and the ranges are
+//! selected in the order given by [`RangeSelection`] until the budget is exhausted.
+//! Only the transaction data of which the weave base falls in the selected ranges
+//! is stored. Storing less means failing to produce the PoA at a lower depth more
+//! often, since the recall byte is picked from the entire weave.
+//!
+//! The transaction data starting in a selected range may extend beyond it, hence the
+//! byte size of the stored data is also checked against the budget, see
+//! [`StoragePolicy::has_room`].
+
+use std::collections::BTreeSet;
+
+use sp_core::{hashing::blake2_256, H256};
+
+/// Byte size of a weave range, the unit of the weave stored locally. 1GiB
+pub const WEAVE_RANGE_SIZE: u64 = 1024 * 1024 * 1024;
+
+/// Strategy of selecting the weave ranges stored within the disk budget.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeSelection {
+    /// Ranges in a pseudo-random order determined by the seed, which is expected
+    /// to be unique for the node and stable across restarts.
+    Random(H256),
+    /// Ranges of which the hash is closest to the hash of the operator's account,
+    /// so that the nodes of different operators tend to store different ranges.
+    NearAccount(H256),
+    /// The latest ranges first.
+    NewestFirst,
+}
+
+impl RangeSelection {
+    /// Returns the ordering key of range `range_index`, the range with a lower key
+    /// is selected first.
+    fn key(&self, range_index: u64) -> [u8; 32] {
+        match self {
+            Self::Random(seed) => {
+                let mut input = seed.as_bytes().to_vec();
+                input.extend_from_slice(&range_index.to_le_bytes());
+                blake2_256(&input)
+            }
+            Self::NearAccount(account_hash) => {
+                let mut distance = blake2_256(&range_index.to_le_bytes());
+                distance
+                    .iter_mut()
+                    .zip(account_hash.as_bytes())
+                    .for_each(|(byte, account_byte)| *byte ^= account_byte);
+                distance
+            }
+            Self::NewestFirst => {
+                let mut key = [0u8; 32];
+                key[..8].copy_from_slice(&(u64::MAX - range_index).to_be_bytes());
+                key
+            }
+        }
+    }
+}
+
+/// Policy of storing the transaction data locally.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StoragePolicy {
+    /// Maximum total byte size of the transaction data stored locally, the entire
+    /// weave is stored if `None`.
+    pub max_size: Option<u64>,
+    /// Strategy of selecting the weave ranges within `max_size`.
+    pub selection: RangeSelection,
+}
+
+impl Default for StoragePolicy {
+    fn default() -> Self {
+        Self {
+            max_size: None,
+            selection: RangeSelection::NewestFirst,
+        }
+    }
+}
+
+impl StoragePolicy {
+    /// Returns `true` if the entire weave is stored regardless of its size.
+    pub fn is_unlimited(&self) -> bool {
+        self.max_size.is_none()
+    }
+
+    /// Returns `true` if the transaction data of `data_size` bytes can be stored
+    /// within the budget given `stored_size` bytes are already stored.
+    pub fn has_room(&self, stored_size: u64, data_size: u64) -> bool {
+        self.max_size.map_or(true, |max_size| {
+            stored_size.saturating_add(data_size) <= max_size
+        })
+    }
+
+    /// Returns the weave ranges to store given the current weave size.
+    pub fn selected_ranges(&self, weave_size: u64) -> SelectedRanges {
+        let max_size = match self.max_size {
+            Some(max_size) if max_size < weave_size => max_size,
+            _ => return SelectedRanges::All,
+        };
+
+        let range_count = (weave_size + WEAVE_RANGE_SIZE - 1) / WEAVE_RANGE_SIZE;
+        let range_size =
+            |range_index: u64| (weave_size - range_index * WEAVE_RANGE_SIZE).min(WEAVE_RANGE_SIZE);
+
+        let mut ranges = (0..range_count)
+            .map(|range_index| (self.selection.key(range_index), range_index))
+            .collect::<Vec<_>>();
+        ranges.sort_unstable();
+
+        let mut budget = max_size;
+        let mut selected = BTreeSet::new();
+        for (_, range_index) in ranges {
+            // Only the last range is partial, which may still fit in the rest budget.
+            if range_size(range_index) <= budget {
+                budget -= range_size(range_index);
+                selected.insert(range_index);
+            }
+        }
+
+        SelectedRanges::Only(selected)
+    }
+}
+
+/// Weave ranges selected by [`StoragePolicy`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SelectedRanges {
+    /// The entire weave.
+    All,
+    /// Only the ranges of given indices.
+    Only(BTreeSet<u64>),
+}
+
+impl SelectedRanges {
+    /// Returns `true` if the transaction data starting at `weave_base` is expected
+    /// to be stored.
+    pub fn contains(&self, weave_base: u64) -> bool {
+        match self {
+            Self::All => true,
+            Self::Only(ranges) => ranges.contains(&(weave_base / WEAVE_RANGE_SIZE)),
+        }
+    }
+}
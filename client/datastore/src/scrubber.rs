@@ -0,0 +1,423 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// This file is part of Canyon.
+//
+// Copyright (c) 2021 Canyon Labs.
+//
+// Canyon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// Canyon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Canyon. If not, see <http://www.gnu.org/licenses/>.
+
+//! Scrubber re-verifying the stored transaction data against the on-chain chunk roots.
+//!
+//! The corrupted or truncated transaction data is otherwise discovered only when the
+//! proofs generated from it fail on the other nodes. [`Scrubber`] walks through the
+//! transaction data stored in the finalized blocks, recomputes the chunk root from the
+//! stored chunks and compares it with the chunk root recorded on chain. The corrupted
+//! data is quarantined, the block storing it is reported so that the data can be
+//! fetched from peers again.
+//!
+//! The transaction data not moved from the legacy offchain storage yet is verified as
+//! well, it's moved to the [`ChunkStore`] first when the corrupted data is quarantined.
+
+use std::io;
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use codec::Encode;
+use futures::channel::mpsc;
+use futures_timer::Delay;
+use prometheus_endpoint::{register, Counter, Gauge, PrometheusError, Registry, U64};
+
+use sc_client_api::BlockBackend;
+use sp_api::ProvideRuntimeApi;
+use sp_blockchain::HeaderBackend;
+use sp_core::H256;
+use sp_runtime::{
+    generic::BlockId,
+    offchain::OffchainStorage,
+    traits::{Block as BlockT, NumberFor, One, Zero},
+};
+
+use cp_permastore::{
+    chunk_hash, chunk_root as chunk_root_of_data, chunk_root_of_hashes, PermastoreApi, CHUNK_SIZE,
+};
+
+use crate::{ChunkRootBackend, ChunkStore, Error, PermanentStorage};
+
+/// Interval between two passes of the scrubber.
+const SCRUB_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Corruption found in the stored transaction data.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum Corruption {
+    /// A chunk is missing.
+    #[error("Chunk {0} is missing")]
+    MissingChunk(u32),
+    /// A chunk is of the wrong size, e.g., truncated.
+    #[error("Chunk {0} has an invalid size")]
+    InvalidChunkSize(u32),
+    /// The persisted chunk hashes do not match the chunks.
+    #[error("Persisted chunk hashes mismatch the chunks")]
+    ChunkHashesMismatch,
+    /// The chunk root recomputed from the chunks does not match the on-chain one.
+    #[error("Chunk root mismatch, expected: {expected}, got: {got}")]
+    ChunkRootMismatch {
+        /// Chunk root recorded on chain.
+        expected: H256,
+        /// Chunk root recomputed from the stored chunks.
+        got: H256,
+    },
+}
+
+/// Result of verifying the stored transaction data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DataIntegrity {
+    /// The transaction data is not stored.
+    NotStored,
+    /// The transaction data of given byte size is intact.
+    Intact(u64),
+    /// The transaction data is corrupted.
+    Corrupted(Corruption),
+}
+
+/// Verifies the transaction data stored in `chunk_store` under `chunk_root`, by
+/// recomputing the chunk root from the stored chunks.
+///
+/// Nothing is written to `chunk_store`, the chunk hashes persisted alongside the data
+/// are compared as is, which are missing for the data stored before they are persisted.
+pub fn verify_data(chunk_store: &ChunkStore, chunk_root: &H256) -> io::Result<DataIntegrity> {
+    let key = chunk_root.encode();
+
+    let data_info = match chunk_store.data_info(&key)? {
+        Some(data_info) => data_info,
+        None => return Ok(DataIntegrity::NotStored),
+    };
+
+    let chunk_count = data_info.chunk_count();
+    let mut chunk_hashes = Vec::with_capacity(chunk_count as usize);
+    for chunk_index in 0..chunk_count {
+        let chunk = match chunk_store.chunk(&key, chunk_index)? {
+            Some(chunk) => chunk,
+            None => {
+                return Ok(DataIntegrity::Corrupted(Corruption::MissingChunk(
+                    chunk_index,
+                )))
+            }
+        };
+
+        let chunk_size = if chunk_index + 1 == chunk_count {
+            data_info.data_size - chunk_index as u64 * CHUNK_SIZE as u64
+        } else {
+            CHUNK_SIZE as u64
+        };
+        if chunk.len() as u64 != chunk_size {
+            return Ok(DataIntegrity::Corrupted(Corruption::InvalidChunkSize(
+                chunk_index,
+            )));
+        }
+
+        chunk_hashes.push(chunk_hash(&chunk));
+    }
+
+    match chunk_store.persisted_chunk_hashes(&key)? {
+        Some(persisted) if persisted != chunk_hashes => {
+            return Ok(DataIntegrity::Corrupted(Corruption::ChunkHashesMismatch));
+        }
+        _ => {}
+    }
+
+    let got = chunk_root_of_hashes(chunk_hashes);
+    if got != *chunk_root {
+        return Ok(DataIntegrity::Corrupted(Corruption::ChunkRootMismatch {
+            expected: *chunk_root,
+            got,
+        }));
+    }
+
+    Ok(DataIntegrity::Intact(data_info.data_size))
+}
+
+impl<C> PermanentStorage<C> {
+    /// Verifies the transaction data `chunk_root`, which is stored either in the chunk
+    /// store or in the legacy storage.
+    ///
+    /// The legacy data is moved to the chunk store first if `migrate` is `true`, so that
+    /// it can be quarantined as usual, otherwise it's verified in place and nothing is
+    /// written.
+    pub(crate) fn verify_stored_data(
+        &self,
+        chunk_root: &H256,
+        migrate: bool,
+    ) -> io::Result<DataIntegrity> {
+        match verify_data(&self.chunk_store, chunk_root)? {
+            DataIntegrity::NotStored => {}
+            integrity => return Ok(integrity),
+        }
+
+        let key = chunk_root.encode();
+        if migrate {
+            return match self.migrate_legacy_data(&key)? {
+                Some(_) => verify_data(&self.chunk_store, chunk_root),
+                None => Ok(DataIntegrity::NotStored),
+            };
+        }
+
+        let data = match self
+            .legacy_storage
+            .as_ref()
+            .and_then(|legacy_storage| legacy_storage.get(sp_offchain::STORAGE_PREFIX, &key))
+        {
+            Some(data) => data,
+            None => return Ok(DataIntegrity::NotStored),
+        };
+
+        let got = chunk_root_of_data(&data);
+        if got != *chunk_root {
+            return Ok(DataIntegrity::Corrupted(Corruption::ChunkRootMismatch {
+                expected: *chunk_root,
+                got,
+            }));
+        }
+
+        Ok(DataIntegrity::Intact(data.len() as u64))
+    }
+}
+
+/// Corrupted transaction data found by [`Scrubber`].
+#[derive(Debug, Clone)]
+pub struct CorruptedData<Block: BlockT> {
+    /// Number of the block storing the transaction data.
+    pub block_number: NumberFor<Block>,
+    /// Index of the extrinsic storing the transaction data.
+    pub extrinsic_index: u32,
+    /// Chunk root of the transaction data recorded on chain.
+    pub chunk_root: H256,
+    /// Corruption found in the transaction data.
+    pub corruption: Corruption,
+}
+
+/// Outcome of scrubbing a range of blocks.
+#[derive(Debug, Clone)]
+pub struct ScrubReport<Block: BlockT> {
+    /// Number of the transaction data verified as intact.
+    pub intact_data: u64,
+    /// Total byte size of the transaction data verified as intact.
+    pub intact_bytes: u64,
+    /// Number of the transaction data stored on chain but not stored locally.
+    pub missing_data: u64,
+    /// Corrupted transaction data.
+    pub corrupted: Vec<CorruptedData<Block>>,
+}
+
+impl<Block: BlockT> Default for ScrubReport<Block> {
+    fn default() -> Self {
+        Self {
+            intact_data: 0,
+            intact_bytes: 0,
+            missing_data: 0,
+            corrupted: Vec::new(),
+        }
+    }
+}
+
+/// Prometheus metrics of [`Scrubber`].
+struct Metrics {
+    intact_data: Counter<U64>,
+    corrupted_data: Counter<U64>,
+    quarantined_data: Gauge<U64>,
+}
+
+impl Metrics {
+    fn register(registry: &Registry) -> Result<Self, PrometheusError> {
+        Ok(Self {
+            intact_data: register(
+                Counter::new(
+                    "permastore_scrubber_intact_data_total",
+                    "Number of the transaction data verified as intact by the scrubber",
+                )?,
+                registry,
+            )?,
+            corrupted_data: register(
+                Counter::new(
+                    "permastore_scrubber_corrupted_data_total",
+                    "Number of the corrupted transaction data found by the scrubber",
+                )?,
+                registry,
+            )?,
+            quarantined_data: register(
+                Gauge::new(
+                    "permastore_scrubber_quarantined_data",
+                    "Number of the quarantined transaction data not stored again yet",
+                )?,
+                registry,
+            )?,
+        })
+    }
+}
+
+/// Worker verifying the transaction data stored in the finalized blocks periodically.
+///
+/// The chunk root of each transaction data is read from the on-chain index through
+/// [`ChunkRootBackend`], the data missing locally is skipped.
+pub struct Scrubber<Block, C> {
+    storage: PermanentStorage<C>,
+    metrics: Option<Metrics>,
+    phantom: PhantomData<Block>,
+}
+
+impl<Block, C> Scrubber<Block, C>
+where
+    Block: BlockT<Hash = H256>,
+    C: HeaderBackend<Block> + BlockBackend<Block> + ProvideRuntimeApi<Block> + Send + Sync,
+    C::Api: PermastoreApi<Block, NumberFor<Block>, u32, Block::Hash>,
+{
+    /// Creates a new instance of [`Scrubber`], of which the metrics are registered in
+    /// `registry` if any.
+    pub fn new(storage: PermanentStorage<C>, registry: Option<&Registry>) -> Self {
+        let metrics = registry.and_then(|registry| {
+            Metrics::register(registry)
+                .map_err(|e| {
+                    log::error!(
+                        target: "datastore",
+                        "Failed to register the scrubber metrics: {}",
+                        e,
+                    )
+                })
+                .ok()
+        });
+
+        Self {
+            storage,
+            metrics,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Runs the scrubber forever, the number of block storing the corrupted transaction
+    /// data is sent to `corrupted_blocks` once the data is quarantined.
+    ///
+    /// Each pass verifies all the finalized blocks, the scrubber is expected to be spawned
+    /// as a blocking task.
+    pub async fn run(self, corrupted_blocks: mpsc::UnboundedSender<NumberFor<Block>>) {
+        loop {
+            // The node is likely busy with syncing on startup, start with a delay.
+            Delay::new(SCRUB_INTERVAL).await;
+
+            let finalized_number = self.storage.client.info().finalized_number;
+            match self.scrub(Zero::zero(), finalized_number, true) {
+                Ok(report) => {
+                    log::info!(
+                        target: "datastore",
+                        "Scrubbed the transaction data up to block #{}, intact: {}, \
+                        corrupted: {}, missing: {}",
+                        finalized_number,
+                        report.intact_data,
+                        report.corrupted.len(),
+                        report.missing_data,
+                    );
+                    for corrupted in report.corrupted {
+                        let _ = corrupted_blocks.unbounded_send(corrupted.block_number);
+                    }
+                }
+                Err(e) => {
+                    log::error!(
+                        target: "datastore",
+                        "Failed to scrub the transaction data: {}",
+                        e,
+                    );
+                }
+            }
+        }
+    }
+
+    /// Verifies the transaction data stored in the blocks from `from` to `to` inclusively,
+    /// the corrupted data is quarantined if `quarantine` is `true`.
+    pub fn scrub(
+        &self,
+        from: NumberFor<Block>,
+        to: NumberFor<Block>,
+        quarantine: bool,
+    ) -> Result<ScrubReport<Block>, Error<Block>> {
+        let mut report = ScrubReport::default();
+
+        let mut block_number = from;
+        while block_number <= to {
+            self.scrub_block(block_number, quarantine, &mut report)?;
+            block_number += One::one();
+        }
+
+        if let Some(metrics) = &self.metrics {
+            metrics
+                .quarantined_data
+                .set(self.storage.chunk_store.quarantined()?.len() as u64);
+        }
+
+        Ok(report)
+    }
+
+    fn scrub_block(
+        &self,
+        block_number: NumberFor<Block>,
+        quarantine: bool,
+        report: &mut ScrubReport<Block>,
+    ) -> Result<(), Error<Block>> {
+        let block_id = BlockId::Number(block_number);
+        let extrinsics_count = self
+            .storage
+            .client
+            .block_body(&block_id)
+            .map_err(Box::new)?
+            .map_or(0, |extrinsics| extrinsics.len());
+
+        for extrinsic_index in 0..extrinsics_count as u32 {
+            let chunk_root = match self
+                .storage
+                .chunk_root(None, block_number, extrinsic_index)?
+            {
+                Some(chunk_root) => chunk_root,
+                None => continue,
+            };
+
+            match self.storage.verify_stored_data(&chunk_root, quarantine)? {
+                DataIntegrity::NotStored => report.missing_data += 1,
+                DataIntegrity::Intact(data_size) => {
+                    report.intact_data += 1;
+                    report.intact_bytes += data_size;
+                    if let Some(metrics) = &self.metrics {
+                        metrics.intact_data.inc();
+                    }
+                }
+                DataIntegrity::Corrupted(corruption) => {
+                    log::warn!(
+                        target: "datastore",
+                        "Corrupted transaction data {} at block #{}, extrinsic index: {}: {}",
+                        chunk_root, block_number, extrinsic_index, corruption,
+                    );
+                    if let Some(metrics) = &self.metrics {
+                        metrics.corrupted_data.inc();
+                    }
+                    if quarantine {
+                        self.storage.chunk_store.quarantine(&chunk_root.encode())?;
+                    }
+                    report.corrupted.push(CorruptedData {
+                        block_number,
+                        extrinsic_index,
+                        chunk_root,
+                        corruption,
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -18,13 +18,22 @@
 
 use std::sync::Arc;
 
+use codec::Encode;
+
+use sc_client_db::offchain::LocalStorage;
+use sp_core::H256;
 use sp_keystore::testing::KeyStore;
+use sp_runtime::offchain::OffchainStorage;
 use substrate_test_runtime_client::DefaultTestClientBuilderExt;
 use substrate_test_runtime_client::TestClientBuilderExt;
 
-use cp_permastore::PermaStorage;
+use cp_permastore::{chunk_hash, chunk_root, PermaStorage, CHUNK_SIZE};
 
-use crate::PermanentStorage;
+use crate::{
+    test_data, verify_data, ChunkStorage, ChunkStore, Corruption, DataInfo, DataIntegrity,
+    PermanentStorage, RangeSelection, SelectedRanges, StoragePolicy, UploadProgress,
+    WEAVE_RANGE_SIZE,
+};
 
 #[test]
 fn basic_operations_should_work() {
@@ -43,3 +52,626 @@ fn basic_operations_should_work() {
     assert!(!perma_storage.exists(b"key"));
     assert_eq!(perma_storage.retrieve(b"key"), None);
 }
+
+#[test]
+fn chunk_store_should_work() {
+    let chunk_store = ChunkStore::new_test();
+
+    let data = (0..CHUNK_SIZE * 2 + 100)
+        .map(|i| i as u8)
+        .collect::<Vec<_>>();
+    let data_info = DataInfo {
+        data_size: data.len() as u64,
+    };
+
+    assert_eq!(
+        chunk_store
+            .write_data(b"chunk_root", data.as_slice())
+            .unwrap(),
+        data_info.data_size
+    );
+    assert_eq!(
+        chunk_store.data_info(b"chunk_root").unwrap(),
+        Some(data_info)
+    );
+    assert_eq!(data_info.chunk_count(), 3);
+
+    for (chunk_index, chunk) in data.chunks(CHUNK_SIZE as usize).enumerate() {
+        assert_eq!(
+            chunk_store
+                .chunk(b"chunk_root", chunk_index as u32)
+                .unwrap(),
+            Some(chunk.to_vec())
+        );
+    }
+    assert_eq!(chunk_store.chunk(b"chunk_root", 3).unwrap(), None);
+    assert_eq!(
+        chunk_store.chunk_hashes(b"chunk_root").unwrap(),
+        Some(data.chunks(CHUNK_SIZE as usize).map(chunk_hash).collect())
+    );
+    assert_eq!(chunk_store.read_data(b"chunk_root").unwrap(), Some(data));
+
+    chunk_store.remove(b"chunk_root").unwrap();
+    assert_eq!(chunk_store.data_info(b"chunk_root").unwrap(), None);
+    assert_eq!(chunk_store.chunk(b"chunk_root", 0).unwrap(), None);
+    assert_eq!(chunk_store.read_data(b"chunk_root").unwrap(), None);
+}
+
+#[test]
+fn incomplete_data_should_not_be_visible() {
+    let chunk_store = ChunkStore::new_test();
+
+    chunk_store.put_chunk(b"chunk_root", 0, b"chunk").unwrap();
+    assert_eq!(chunk_store.read_data(b"chunk_root").unwrap(), None);
+
+    assert_eq!(chunk_store.chunk_hashes(b"chunk_root").unwrap(), None);
+
+    chunk_store
+        .put_data_info(b"chunk_root", DataInfo { data_size: 5 })
+        .unwrap();
+    assert_eq!(
+        chunk_store.read_data(b"chunk_root").unwrap(),
+        Some(b"chunk".to_vec())
+    );
+
+    // The chunk hashes are computed lazily as they were not written along with the data.
+    let chunk_hashes = Some(vec![chunk_hash(b"chunk")]);
+    assert_eq!(
+        chunk_store.chunk_hashes(b"chunk_root").unwrap(),
+        chunk_hashes
+    );
+
+    // The chunk hashes can not be computed if any chunk is missing.
+    chunk_store.remove(b"chunk_root").unwrap();
+    chunk_store
+        .put_data_info(b"chunk_root", DataInfo { data_size: 5 })
+        .unwrap();
+    assert!(chunk_store.chunk_hashes(b"chunk_root").is_err());
+
+    assert!(chunk_store
+        .put_chunk(b"chunk_root", 1, &vec![0u8; CHUNK_SIZE as usize + 1])
+        .is_err());
+}
+
+#[test]
+fn read_range_should_only_read_covering_chunks() {
+    let chunk_store = ChunkStore::new_test();
+
+    let data = test_data(3, 10);
+    chunk_store
+        .write_data(b"chunk_root", data.as_slice())
+        .unwrap();
+
+    let chunk_size = CHUNK_SIZE as u64;
+    for &(offset, len) in &[
+        (0, 10),
+        (chunk_size - 1, 2),
+        (chunk_size, chunk_size),
+        (10, chunk_size * 3),
+        (chunk_size * 3 + 5, 100),
+    ] {
+        let end = (offset + len).min(data.len() as u64);
+        assert_eq!(
+            chunk_store.read_range(b"chunk_root", offset, len).unwrap(),
+            Some(data[offset as usize..end as usize].to_vec())
+        );
+    }
+    assert_eq!(
+        chunk_store
+            .read_range(b"chunk_root", data.len() as u64 + 1, 10)
+            .unwrap(),
+        Some(Vec::new())
+    );
+
+    // Chunks out of the range are never read.
+    chunk_store.put_chunk(b"chunk_root", 0, b"").unwrap();
+    assert_eq!(
+        chunk_store
+            .read_range(b"chunk_root", chunk_size, 10)
+            .unwrap(),
+        Some(data[chunk_size as usize..chunk_size as usize + 10].to_vec())
+    );
+    assert!(chunk_store.read_range(b"chunk_root", 0, 10).is_err());
+
+    assert_eq!(chunk_store.read_range(b"unknown", 0, 10).unwrap(), None);
+}
+
+#[test]
+fn uploaded_chunks_should_be_tracked() {
+    let chunk_store = ChunkStore::new_test();
+
+    let data_size = CHUNK_SIZE as u64 + 5;
+    let chunks = [vec![1u8; CHUNK_SIZE as usize], vec![2u8; 5]];
+
+    let progress = chunk_store
+        .put_uploaded_chunk(b"chunk_root", data_size, 1, &chunks[1], 10)
+        .unwrap();
+    assert_eq!(
+        progress,
+        UploadProgress {
+            data_size,
+            received_chunks: 1,
+            updated_at: 10,
+        }
+    );
+    assert!(!progress.is_complete());
+    assert_eq!(
+        chunk_store.uploaded_chunk_hashes(b"chunk_root").unwrap(),
+        None
+    );
+
+    // Receiving the same chunk again does not make any progress.
+    assert_eq!(
+        chunk_store
+            .put_uploaded_chunk(b"chunk_root", data_size, 1, &chunks[1], 20)
+            .unwrap(),
+        progress
+    );
+    // Inconsistent data size and out of range chunk index are rejected.
+    assert!(chunk_store
+        .put_uploaded_chunk(b"chunk_root", data_size + 1, 0, &chunks[0], 20)
+        .is_err());
+    assert!(chunk_store
+        .put_uploaded_chunk(b"chunk_root", data_size, 2, &chunks[1], 20)
+        .is_err());
+    assert!(chunk_store.complete_upload(b"chunk_root", &[]).is_err());
+
+    let progress = chunk_store
+        .put_uploaded_chunk(b"chunk_root", data_size, 0, &chunks[0], 20)
+        .unwrap();
+    assert!(progress.is_complete());
+    assert_eq!(progress.updated_at, 20);
+    // Not visible until the upload is completed.
+    assert_eq!(chunk_store.data_info(b"chunk_root").unwrap(), None);
+
+    let chunk_hashes = chunk_store
+        .uploaded_chunk_hashes(b"chunk_root")
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        chunk_hashes,
+        vec![chunk_hash(&chunks[0]), chunk_hash(&chunks[1])]
+    );
+
+    assert_eq!(
+        chunk_store
+            .complete_upload(b"chunk_root", &chunk_hashes)
+            .unwrap(),
+        DataInfo { data_size }
+    );
+    assert_eq!(chunk_store.upload_progress(b"chunk_root").unwrap(), None);
+    assert_eq!(
+        chunk_store.read_data(b"chunk_root").unwrap(),
+        Some(chunks.concat())
+    );
+    assert_eq!(
+        chunk_store.chunk_hashes(b"chunk_root").unwrap(),
+        Some(chunk_hashes.clone())
+    );
+    assert_eq!(chunk_store.stored_size(), data_size);
+
+    // A concurrent uploader of the same data neither restarts the upload nor
+    // counts the stored data twice.
+    assert_eq!(
+        chunk_store
+            .put_uploaded_chunk(b"chunk_root", data_size, 0, &chunks[0], 30)
+            .unwrap_err()
+            .kind(),
+        std::io::ErrorKind::AlreadyExists
+    );
+    assert_eq!(chunk_store.upload_progress(b"chunk_root").unwrap(), None);
+    assert_eq!(
+        chunk_store
+            .complete_upload(b"chunk_root", &chunk_hashes)
+            .unwrap(),
+        DataInfo { data_size }
+    );
+    assert_eq!(chunk_store.stored_size(), data_size);
+}
+
+#[test]
+fn concurrent_uploads_should_not_lose_progress() {
+    let chunk_store = ChunkStore::new_test();
+
+    let data = test_data(7, 10);
+    let data_size = data.len() as u64;
+
+    let uploaders = (0..4)
+        .map(|uploader| {
+            let chunk_store = chunk_store.clone();
+            let data = data.clone();
+            std::thread::spawn(move || {
+                let mut chunks = data
+                    .chunks(CHUNK_SIZE as usize)
+                    .enumerate()
+                    .collect::<Vec<_>>();
+                chunks.rotate_left(uploader * 2);
+                for (chunk_index, chunk) in chunks {
+                    chunk_store
+                        .put_uploaded_chunk(b"chunk_root", data_size, chunk_index as u32, chunk, 10)
+                        .unwrap();
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for uploader in uploaders {
+        uploader.join().unwrap();
+    }
+
+    let progress = chunk_store.upload_progress(b"chunk_root").unwrap().unwrap();
+    assert_eq!(progress.received_chunks, 8);
+    assert!(progress.is_complete());
+}
+
+#[test]
+fn expired_uploads_should_be_removed() {
+    let chunk_store = ChunkStore::new_test();
+
+    let data_size = CHUNK_SIZE as u64 + 5;
+    let chunks = [vec![1u8; CHUNK_SIZE as usize], vec![2u8; 5]];
+
+    chunk_store
+        .put_uploaded_chunk(b"abandoned", data_size, 0, &chunks[0], 10)
+        .unwrap();
+    chunk_store
+        .put_uploaded_chunk(b"uploading", data_size, 0, &chunks[0], 10)
+        .unwrap();
+    // Receiving a new chunk keeps the upload alive.
+    chunk_store
+        .put_uploaded_chunk(b"uploading", data_size, 1, &chunks[1], 30)
+        .unwrap();
+    assert_eq!(chunk_store.pending_uploads().unwrap().len(), 2);
+
+    assert_eq!(chunk_store.remove_expired_uploads(10).unwrap(), 0);
+    assert_eq!(chunk_store.remove_expired_uploads(20).unwrap(), 1);
+
+    assert_eq!(chunk_store.upload_progress(b"abandoned").unwrap(), None);
+    assert_eq!(chunk_store.chunk(b"abandoned", 0).unwrap(), None);
+    assert_eq!(
+        chunk_store.pending_uploads().unwrap(),
+        vec![(
+            b"uploading".to_vec(),
+            UploadProgress {
+                data_size,
+                received_chunks: 2,
+                updated_at: 30,
+            }
+        )]
+    );
+}
+
+#[test]
+fn upload_with_wrong_data_size_should_be_superseded() {
+    let chunk_store = ChunkStore::new_test();
+
+    let data = vec![1u8; CHUNK_SIZE as usize * 2 + 5];
+    let data_size = data.len() as u64;
+    let wrong_data_size = data_size + CHUNK_SIZE as u64;
+
+    chunk_store
+        .put_uploaded_chunk(
+            b"chunk_root",
+            wrong_data_size,
+            0,
+            &data[..CHUNK_SIZE as usize],
+            10,
+        )
+        .unwrap();
+    assert!(chunk_store
+        .put_uploaded_chunk(
+            b"chunk_root",
+            data_size,
+            1,
+            &data[CHUNK_SIZE as usize..CHUNK_SIZE as usize * 2],
+            10,
+        )
+        .is_err());
+
+    // The received chunks are counted against the restarted upload.
+    assert_eq!(
+        chunk_store
+            .restart_upload(b"chunk_root", data_size, 20)
+            .unwrap(),
+        UploadProgress {
+            data_size,
+            received_chunks: 1,
+            updated_at: 20,
+        }
+    );
+    assert_eq!(
+        chunk_store
+            .put_uploaded_chunk(
+                b"chunk_root",
+                data_size,
+                1,
+                &data[CHUNK_SIZE as usize..CHUNK_SIZE as usize * 2],
+                20,
+            )
+            .unwrap()
+            .received_chunks,
+        2
+    );
+
+    // The entire data supersedes the pending upload.
+    chunk_store
+        .write_data(b"chunk_root", data.as_slice())
+        .unwrap();
+    assert_eq!(chunk_store.upload_progress(b"chunk_root").unwrap(), None);
+    assert_eq!(chunk_store.read_data(b"chunk_root").unwrap(), Some(data));
+}
+
+#[test]
+fn legacy_data_should_be_moved_to_chunk_store() {
+    let mut legacy_storage = LocalStorage::new_test();
+    legacy_storage.set(sp_offchain::STORAGE_PREFIX, b"key", b"value");
+
+    let perma_storage =
+        PermanentStorage::new_test(Arc::new(())).with_legacy_storage(legacy_storage.clone());
+
+    assert!(perma_storage.exists(b"key"));
+    assert_eq!(perma_storage.chunk_store().data_info(b"key").unwrap(), None);
+
+    assert_eq!(perma_storage.retrieve(b"key"), Some(b"value".to_vec()));
+    assert_eq!(
+        perma_storage.chunk_store().read_data(b"key").unwrap(),
+        Some(b"value".to_vec())
+    );
+    assert_eq!(
+        legacy_storage.get(sp_offchain::STORAGE_PREFIX, b"key"),
+        None
+    );
+}
+
+#[test]
+fn storage_policy_should_select_ranges_within_budget() {
+    let weave_size = 10 * WEAVE_RANGE_SIZE + 100;
+
+    let unlimited = StoragePolicy::default();
+    assert_eq!(unlimited.selected_ranges(weave_size), SelectedRanges::All);
+
+    let enough = StoragePolicy {
+        max_size: Some(weave_size),
+        selection: RangeSelection::NewestFirst,
+    };
+    assert_eq!(enough.selected_ranges(weave_size), SelectedRanges::All);
+
+    // The partial last range and the two latest full ranges.
+    let newest_first = StoragePolicy {
+        max_size: Some(3 * WEAVE_RANGE_SIZE),
+        selection: RangeSelection::NewestFirst,
+    };
+    let selected = newest_first.selected_ranges(weave_size);
+    assert_eq!(
+        selected,
+        SelectedRanges::Only(vec![8, 9, 10].into_iter().collect())
+    );
+    assert!(selected.contains(10 * WEAVE_RANGE_SIZE));
+    assert!(selected.contains(8 * WEAVE_RANGE_SIZE));
+    assert!(!selected.contains(8 * WEAVE_RANGE_SIZE - 1));
+
+    let random = |seed: u8| StoragePolicy {
+        max_size: Some(4 * WEAVE_RANGE_SIZE),
+        selection: RangeSelection::Random([seed; 32].into()),
+    };
+    let count = |selected: SelectedRanges| match selected {
+        SelectedRanges::Only(ranges) => ranges.len(),
+        SelectedRanges::All => panic!("Only a part of weave is selected"),
+    };
+    assert_eq!(count(random(1).selected_ranges(weave_size)), 4);
+    assert_eq!(
+        random(1).selected_ranges(weave_size),
+        random(1).selected_ranges(weave_size)
+    );
+    assert_ne!(
+        random(1).selected_ranges(weave_size),
+        random(2).selected_ranges(weave_size)
+    );
+
+    let near_account = StoragePolicy {
+        max_size: Some(WEAVE_RANGE_SIZE / 2),
+        selection: RangeSelection::NearAccount([1u8; 32].into()),
+    };
+    // Only the partial last range fits in the budget.
+    assert_eq!(
+        near_account.selected_ranges(weave_size),
+        SelectedRanges::Only(vec![10].into_iter().collect())
+    );
+
+    assert!(unlimited.has_room(u64::MAX, 1));
+    assert!(newest_first.has_room(2 * WEAVE_RANGE_SIZE, WEAVE_RANGE_SIZE));
+    assert!(!newest_first.has_room(2 * WEAVE_RANGE_SIZE, WEAVE_RANGE_SIZE + 1));
+}
+
+#[test]
+fn stored_size_should_be_tracked() {
+    let chunk_store = ChunkStore::new_test();
+
+    let data = (0..CHUNK_SIZE + 10).map(|i| i as u8).collect::<Vec<_>>();
+    let chunk_root = chunk_root(&data).encode();
+    let other = vec![1u8; 100];
+    let other_root = cp_permastore::chunk_root(&other).encode();
+
+    chunk_store
+        .write_data(&chunk_root, data.as_slice())
+        .unwrap();
+    chunk_store
+        .write_data(&other_root, other.as_slice())
+        .unwrap();
+    assert_eq!(chunk_store.stored_size(), data.len() as u64 + 100);
+
+    // Storing the same data again is not counted twice.
+    chunk_store
+        .write_data(&chunk_root, data.as_slice())
+        .unwrap();
+    assert_eq!(chunk_store.stored_size(), data.len() as u64 + 100);
+
+    chunk_store.quarantine(&chunk_root).unwrap();
+    assert_eq!(chunk_store.stored_size(), 100);
+
+    chunk_store.remove(&other_root).unwrap();
+    chunk_store.remove(&other_root).unwrap();
+    assert_eq!(chunk_store.stored_size(), 0);
+}
+
+#[test]
+fn corrupted_data_should_be_detected_and_quarantined() {
+    let chunk_store = ChunkStore::new_test();
+
+    let data = test_data(2, 10);
+    let chunk_root = chunk_root(&data);
+    let key = chunk_root.encode();
+    let write_data = || {
+        chunk_store.write_data(&key, data.as_slice()).unwrap();
+    };
+
+    assert_eq!(
+        verify_data(&chunk_store, &chunk_root).unwrap(),
+        DataIntegrity::NotStored
+    );
+
+    write_data();
+    assert_eq!(
+        verify_data(&chunk_store, &chunk_root).unwrap(),
+        DataIntegrity::Intact(data.len() as u64)
+    );
+
+    // Truncated chunk.
+    chunk_store
+        .put_chunk(&key, 2, &data[CHUNK_SIZE as usize * 2..][..5])
+        .unwrap();
+    assert_eq!(
+        verify_data(&chunk_store, &chunk_root).unwrap(),
+        DataIntegrity::Corrupted(Corruption::InvalidChunkSize(2))
+    );
+
+    // Flipped byte.
+    let mut chunk = data[..CHUNK_SIZE as usize].to_vec();
+    chunk[0] ^= 1;
+    write_data();
+    chunk_store.put_chunk(&key, 0, &chunk).unwrap();
+    assert_eq!(
+        verify_data(&chunk_store, &chunk_root).unwrap(),
+        DataIntegrity::Corrupted(Corruption::ChunkHashesMismatch)
+    );
+
+    // Other data stored under the chunk root.
+    chunk_store.write_data(&key, &data[1..]).unwrap();
+    assert!(matches!(
+        verify_data(&chunk_store, &chunk_root).unwrap(),
+        DataIntegrity::Corrupted(Corruption::ChunkRootMismatch { expected, .. }) if expected == chunk_root
+    ));
+
+    chunk_store.quarantine(&key).unwrap();
+    assert_eq!(chunk_store.data_info(&key).unwrap(), None);
+    assert_eq!(chunk_store.chunk(&key, 0).unwrap(), None);
+    assert_eq!(
+        chunk_store.quarantined().unwrap(),
+        vec![(
+            key.clone(),
+            DataInfo {
+                data_size: data.len() as u64 - 1
+            }
+        )]
+    );
+
+    // The quarantine is lifted once the data is stored again.
+    write_data();
+    assert!(chunk_store.quarantined().unwrap().is_empty());
+    assert_eq!(
+        verify_data(&chunk_store, &chunk_root).unwrap(),
+        DataIntegrity::Intact(data.len() as u64)
+    );
+}
+
+#[test]
+fn verify_data_should_not_write_chunk_hashes() {
+    let chunk_store = ChunkStore::new_test();
+
+    // Stored before the chunk hashes are persisted.
+    let data = (0..CHUNK_SIZE + 10).map(|i| i as u8).collect::<Vec<_>>();
+    let chunk_root = chunk_root(&data);
+    let key = chunk_root.encode();
+    for (chunk_index, chunk) in data.chunks(CHUNK_SIZE as usize).enumerate() {
+        chunk_store
+            .put_chunk(&key, chunk_index as u32, chunk)
+            .unwrap();
+    }
+    chunk_store
+        .put_data_info(
+            &key,
+            DataInfo {
+                data_size: data.len() as u64,
+            },
+        )
+        .unwrap();
+
+    assert_eq!(
+        verify_data(&chunk_store, &chunk_root).unwrap(),
+        DataIntegrity::Intact(data.len() as u64)
+    );
+    assert_eq!(chunk_store.persisted_chunk_hashes(&key).unwrap(), None);
+}
+
+#[test]
+fn legacy_data_should_be_verified() {
+    let data = b"legacy data".to_vec();
+    let chunk_root = chunk_root(&data);
+    let corrupted_root = H256::repeat_byte(1);
+
+    let mut legacy_storage = LocalStorage::new_test();
+    legacy_storage.set(sp_offchain::STORAGE_PREFIX, &chunk_root.encode(), &data);
+    legacy_storage.set(sp_offchain::STORAGE_PREFIX, &corrupted_root.encode(), &data);
+
+    let perma_storage =
+        PermanentStorage::new_test(Arc::new(())).with_legacy_storage(legacy_storage.clone());
+
+    // Verified in place without being moved.
+    assert_eq!(
+        perma_storage
+            .verify_stored_data(&chunk_root, false)
+            .unwrap(),
+        DataIntegrity::Intact(data.len() as u64)
+    );
+    assert!(matches!(
+        perma_storage.verify_stored_data(&corrupted_root, false).unwrap(),
+        DataIntegrity::Corrupted(Corruption::ChunkRootMismatch { expected, .. }) if expected == corrupted_root
+    ));
+    assert_eq!(
+        perma_storage
+            .chunk_store()
+            .data_info(&chunk_root.encode())
+            .unwrap(),
+        None
+    );
+    assert!(legacy_storage
+        .get(sp_offchain::STORAGE_PREFIX, &chunk_root.encode())
+        .is_some());
+
+    // Moved to the chunk store, from which the corrupted data can be quarantined.
+    assert_eq!(
+        perma_storage.verify_stored_data(&chunk_root, true).unwrap(),
+        DataIntegrity::Intact(data.len() as u64)
+    );
+    assert!(matches!(
+        perma_storage
+            .verify_stored_data(&corrupted_root, true)
+            .unwrap(),
+        DataIntegrity::Corrupted(Corruption::ChunkRootMismatch { .. })
+    ));
+    assert!(perma_storage
+        .chunk_store()
+        .data_info(&corrupted_root.encode())
+        .unwrap()
+        .is_some());
+    assert_eq!(
+        legacy_storage.get(sp_offchain::STORAGE_PREFIX, &chunk_root.encode()),
+        None
+    );
+
+    assert_eq!(
+        perma_storage
+            .verify_stored_data(&H256::repeat_byte(2), false)
+            .unwrap(),
+        DataIntegrity::NotStored
+    );
+}
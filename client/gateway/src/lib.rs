@@ -0,0 +1,333 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// This file is part of Canyon.
+//
+// Copyright (c) 2021 Canyon Labs.
+//
+// Canyon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// Canyon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Canyon. If not, see <http://www.gnu.org/licenses/>.
+
+#![deny(missing_docs, unused_extern_crates)]
+
+//! This crate provides an HTTP gateway serving the transaction data stored locally.
+//!
+//! The data is served by chunk root at `/data/{chunk_root}`, or by the position of the
+//! extrinsic storing it at `/tx/{block_hash_or_number}/{extrinsic_index}`. The chunk
+//! root is used as the entity tag since the data under a chunk root never changes,
+//! the range requests are supported and the response body is streamed chunk by chunk
+//! from the [`cc_datastore::ChunkStore`].
+//!
+//! The database reads and runtime calls are blocking, they are performed on the blocking
+//! threads so that the node's shared executor is never stalled by the downloads.
+
+#[cfg(test)]
+mod tests;
+
+use std::convert::Infallible;
+use std::io;
+use std::net::SocketAddr;
+
+use codec::{Decode, Encode};
+use futures::{channel::mpsc, SinkExt};
+use hyper::{
+    header::{self, HeaderMap},
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+
+use sp_core::hexdisplay::HexDisplay;
+use sp_runtime::{generic::BlockId, traits::Block as BlockT};
+
+use cc_datastore::{ChunkStorage, RangeReader, TransactionDataBackend};
+
+/// Maximum number of chunks read ahead of the client when streaming a response body.
+const BODY_BUFFER_SIZE: usize = 4;
+
+/// Byte range of the transaction data requested by the `Range` header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ByteRange {
+    /// The entire data, the `Range` header is absent or ignored.
+    Full,
+    /// Bytes in `start..end` of the data.
+    Partial { start: u64, end: u64 },
+    /// The range does not overlap the data.
+    Unsatisfiable,
+}
+
+impl ByteRange {
+    /// Parses the value of `Range` header given the byte size of data.
+    ///
+    /// Only a single byte range is supported, the header is ignored otherwise as
+    /// permitted by RFC 7233.
+    fn parse(range: &str, data_size: u64) -> Self {
+        let spec = match range.trim().strip_prefix("bytes=") {
+            Some(spec) if !spec.contains(',') => spec.trim(),
+            _ => return Self::Full,
+        };
+
+        let (first, last) = match spec.find('-') {
+            Some(index) => (&spec[..index], &spec[index + 1..]),
+            None => return Self::Full,
+        };
+
+        match (first.parse::<u64>(), last.parse::<u64>()) {
+            // bytes=first-last
+            (Ok(first), Ok(last)) if first <= last => {
+                Self::from_bounds(first, last.saturating_add(1), data_size)
+            }
+            // bytes=first-
+            (Ok(first), Err(_)) if last.is_empty() => {
+                Self::from_bounds(first, data_size, data_size)
+            }
+            // bytes=-suffix_length
+            (Err(_), Ok(suffix_length)) if first.is_empty() => {
+                if suffix_length == 0 || data_size == 0 {
+                    Self::Unsatisfiable
+                } else {
+                    Self::Partial {
+                        start: data_size.saturating_sub(suffix_length),
+                        end: data_size,
+                    }
+                }
+            }
+            _ => Self::Full,
+        }
+    }
+
+    fn from_bounds(start: u64, end: u64, data_size: u64) -> Self {
+        if start >= data_size {
+            Self::Unsatisfiable
+        } else {
+            Self::Partial {
+                start,
+                end: end.min(data_size),
+            }
+        }
+    }
+}
+
+fn error_response(status: StatusCode, message: &'static str) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "text/plain")
+        .body(Body::from(message))
+        .expect("Status and headers are valid; qed")
+}
+
+fn not_found() -> Response<Body> {
+    error_response(StatusCode::NOT_FOUND, "Transaction data not found")
+}
+
+fn internal_error(e: impl std::fmt::Debug) -> Response<Body> {
+    log::warn!(target: "gateway", "Failed to serve the transaction data: {:?}", e);
+    error_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal error")
+}
+
+/// Parses the hex encoded chunk root, returns the database key of transaction data.
+fn parse_chunk_root(chunk_root: &str) -> Option<Vec<u8>> {
+    sp_core::bytes::from_hex(chunk_root)
+        .ok()
+        .filter(|chunk_root| chunk_root.len() == 32)
+}
+
+/// Parses the hex encoded block hash or the decimal block number.
+fn parse_block_id<Block: BlockT>(block: &str) -> Option<BlockId<Block>> {
+    if block.starts_with("0x") {
+        let hash = sp_core::bytes::from_hex(block).ok()?;
+        if hash.len() != Block::Hash::default().as_ref().len() {
+            return None;
+        }
+        Block::Hash::decode(&mut hash.as_slice())
+            .ok()
+            .map(BlockId::Hash)
+    } else {
+        block
+            .parse::<u32>()
+            .ok()
+            .map(|number| BlockId::Number(number.into()))
+    }
+}
+
+/// Returns a response body streaming the pieces of `range_reader`.
+///
+/// The pieces are read on a blocking thread and sent through a bounded channel, so
+/// that the reading can not run ahead of the client by more than [`BODY_BUFFER_SIZE`].
+fn stream_body(range_reader: RangeReader) -> Body {
+    let (mut sender, receiver) = mpsc::channel(BODY_BUFFER_SIZE);
+    tokio::task::spawn_blocking(move || {
+        for piece in range_reader {
+            let failed = piece.is_err();
+            // Stop reading once the client goes away or the data can not be read.
+            if futures::executor::block_on(sender.send(piece)).is_err() || failed {
+                break;
+            }
+        }
+    });
+    Body::wrap_stream(receiver)
+}
+
+/// Serves the transaction data `chunk_root`, honoring the conditional and range requests.
+///
+/// This performs blocking reads and must be called on a blocking thread of a tokio runtime.
+fn serve_data<S: ChunkStorage>(
+    storage: &S,
+    chunk_root: &[u8],
+    method: &Method,
+    headers: &HeaderMap,
+) -> io::Result<Response<Body>> {
+    let data_size = match storage.data_info(chunk_root)? {
+        Some(data_info) => data_info.data_size,
+        None => return Ok(not_found()),
+    };
+
+    let etag = format!("\"0x{}\"", HexDisplay::from(&chunk_root));
+
+    let builder = Response::builder()
+        .header(header::ETAG, etag.as_str())
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::CACHE_CONTROL, "public, max-age=31536000, immutable");
+
+    let not_modified = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|tags| tags.to_str().ok())
+        .map(|tags| {
+            tags.split(',')
+                .map(str::trim)
+                .any(|tag| tag == etag || tag == "*")
+        })
+        .unwrap_or(false);
+    if not_modified {
+        return Ok(builder
+            .status(StatusCode::NOT_MODIFIED)
+            .body(Body::empty())
+            .expect("Status and headers are valid; qed"));
+    }
+
+    let range = headers
+        .get(header::RANGE)
+        .and_then(|range| range.to_str().ok())
+        .map(|range| ByteRange::parse(range, data_size))
+        .unwrap_or(ByteRange::Full);
+
+    let (builder, start, end) = match range {
+        ByteRange::Full => (builder.status(StatusCode::OK), 0, data_size),
+        ByteRange::Partial { start, end } => (
+            builder.status(StatusCode::PARTIAL_CONTENT).header(
+                header::CONTENT_RANGE,
+                format!("bytes {}-{}/{}", start, end - 1, data_size),
+            ),
+            start,
+            end,
+        ),
+        ByteRange::Unsatisfiable => {
+            return Ok(builder
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(header::CONTENT_RANGE, format!("bytes */{}", data_size))
+                .body(Body::empty())
+                .expect("Status and headers are valid; qed"));
+        }
+    };
+
+    let body = if method == Method::HEAD {
+        Body::empty()
+    } else {
+        let range_reader = storage
+            .chunk_store()
+            .range_reader(chunk_root, start, end - start)?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Stored data is missing"))?;
+        stream_body(range_reader)
+    };
+
+    Ok(builder
+        .header(header::CONTENT_TYPE, "application/octet-stream")
+        .header(header::CONTENT_LENGTH, end - start)
+        .body(body)
+        .expect("Status and headers are valid; qed"))
+}
+
+/// Handles a request to the gateway.
+///
+/// This performs blocking reads and runtime calls, which must be called on a blocking
+/// thread of a tokio runtime.
+fn handle_request<Block, S>(storage: &S, req: &Request<Body>) -> Response<Body>
+where
+    Block: BlockT,
+    S: ChunkStorage + TransactionDataBackend<Block>,
+{
+    if req.method() != Method::GET && req.method() != Method::HEAD {
+        return error_response(StatusCode::METHOD_NOT_ALLOWED, "Method not allowed");
+    }
+
+    let segments = req
+        .uri()
+        .path()
+        .trim_matches('/')
+        .split('/')
+        .collect::<Vec<_>>();
+
+    let chunk_root = match segments.as_slice() {
+        ["data", chunk_root] => match parse_chunk_root(chunk_root) {
+            Some(chunk_root) => chunk_root,
+            None => return error_response(StatusCode::BAD_REQUEST, "Invalid chunk root"),
+        },
+        ["tx", block, extrinsic_index] => {
+            let (block_id, extrinsic_index) =
+                match (parse_block_id::<Block>(block), extrinsic_index.parse()) {
+                    (Some(block_id), Ok(extrinsic_index)) => (block_id, extrinsic_index),
+                    _ => {
+                        return error_response(
+                            StatusCode::BAD_REQUEST,
+                            "Invalid block or extrinsic index",
+                        )
+                    }
+                };
+            match storage.transaction_data_info(None, block_id, extrinsic_index) {
+                Ok(Some((chunk_root, _data_size))) => chunk_root.encode(),
+                Ok(None) | Err(cc_datastore::Error::BlockNumberNotFound(_)) => return not_found(),
+                Err(e) => return internal_error(e),
+            }
+        }
+        _ => return error_response(StatusCode::NOT_FOUND, "Not found"),
+    };
+
+    serve_data(storage, &chunk_root, req.method(), req.headers()).unwrap_or_else(internal_error)
+}
+
+/// Runs the HTTP gateway serving the transaction data in `storage` at `addr`.
+pub async fn run<Block, S>(addr: SocketAddr, storage: S) -> Result<(), hyper::Error>
+where
+    Block: BlockT,
+    S: ChunkStorage + TransactionDataBackend<Block> + Clone + Send + 'static,
+{
+    let make_service = make_service_fn(move |_| {
+        let storage = storage.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                let storage = storage.clone();
+                async move {
+                    let response = tokio::task::spawn_blocking(move || {
+                        handle_request::<Block, _>(&storage, &req)
+                    })
+                    .await
+                    .unwrap_or_else(internal_error);
+                    Ok::<_, Infallible>(response)
+                }
+            }))
+        }
+    });
+
+    let server = Server::try_bind(&addr)?.serve(make_service);
+
+    log::info!(target: "gateway", "Data gateway listening on http://{}", addr);
+
+    server.await
+}
@@ -0,0 +1,332 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// This file is part of Canyon.
+//
+// Copyright (c) 2021 Canyon Labs.
+//
+// Canyon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// Canyon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Canyon. If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use hyper::{
+    header::{self, HeaderMap, HeaderValue},
+    Body, Method, Request, Response, StatusCode,
+};
+use parking_lot::Mutex;
+
+use sp_api::{ApiRef, ProvideRuntimeApi};
+use sp_blockchain::HeaderBackend;
+use sp_core::H256;
+use sp_runtime::generic::BlockId;
+use substrate_test_runtime_client::{
+    runtime::{Block, Header},
+    Backend, Client, DefaultTestClientBuilderExt, TestClientBuilderExt,
+};
+
+use cc_datastore::{test_data, ChunkStorage, PermanentStorage};
+
+use super::{handle_request, serve_data, ByteRange};
+
+const CHUNK_ROOT: [u8; 32] = [7u8; 32];
+
+/// `(chunk_root, data_size)` of the transaction data keyed by `(block_number, extrinsic_index)`.
+type TransactionDataMap = HashMap<(u64, u32), (H256, u32)>;
+
+/// A client of which the blocks are provided by the substrate test client and the
+/// permastore runtime API is mocked.
+#[derive(Clone)]
+struct TestChain {
+    inner: Arc<Client<Backend>>,
+    transactions: Arc<Mutex<TransactionDataMap>>,
+}
+
+impl TestChain {
+    fn new() -> Self {
+        Self {
+            inner: Arc::new(substrate_test_runtime_client::new()),
+            transactions: Default::default(),
+        }
+    }
+
+    /// Records the transaction data `(chunk_root, data_size)` on chain.
+    fn store(&self, block_number: u64, extrinsic_index: u32, chunk_root: H256, data_size: u32) {
+        self.transactions
+            .lock()
+            .insert((block_number, extrinsic_index), (chunk_root, data_size));
+    }
+}
+
+struct RuntimeApi {
+    transactions: TransactionDataMap,
+}
+
+impl ProvideRuntimeApi<Block> for TestChain {
+    type Api = RuntimeApi;
+
+    fn runtime_api<'a>(&'a self) -> ApiRef<'a, Self::Api> {
+        RuntimeApi {
+            transactions: self.transactions.lock().clone(),
+        }
+        .into()
+    }
+}
+
+sp_api::mock_impl_runtime_apis! {
+    impl cp_permastore::PermastoreApi<Block, u64, u32, H256> for RuntimeApi {
+        fn chunk_root(block_number: u64, extrinsic_index: u32) -> Option<H256> {
+            self.transactions
+                .get(&(block_number, extrinsic_index))
+                .map(|(chunk_root, _)| *chunk_root)
+        }
+
+        fn find_recall_block(_recall_byte: u64) -> Option<u64> {
+            unimplemented!()
+        }
+
+        fn locate_recall_tx(_recall_byte: u64) -> Option<(u64, u32, u64)> {
+            unimplemented!()
+        }
+
+        fn data_size(block_number: u64, extrinsic_index: u32) -> u32 {
+            self.transactions
+                .get(&(block_number, extrinsic_index))
+                .map(|(_, data_size)| *data_size)
+                .unwrap_or_default()
+        }
+
+        fn weave_offsets(_block_number: u64) -> Vec<(u32, u64)> {
+            unimplemented!()
+        }
+
+        fn is_forgotten(_block_number: u64, _extrinsic_index: u32) -> bool {
+            unimplemented!()
+        }
+
+        fn require_proof_of_access() -> bool {
+            unimplemented!()
+        }
+
+        fn block_size() -> u64 {
+            unimplemented!()
+        }
+
+        fn weave_size() -> u64 {
+            unimplemented!()
+        }
+    }
+}
+
+impl HeaderBackend<Block> for TestChain {
+    fn header(&self, id: BlockId<Block>) -> sp_blockchain::Result<Option<Header>> {
+        HeaderBackend::header(&*self.inner, id)
+    }
+
+    fn info(&self) -> sp_blockchain::Info<Block> {
+        HeaderBackend::info(&*self.inner)
+    }
+
+    fn status(&self, id: BlockId<Block>) -> sp_blockchain::Result<sp_blockchain::BlockStatus> {
+        HeaderBackend::status(&*self.inner, id)
+    }
+
+    fn number(&self, hash: H256) -> sp_blockchain::Result<Option<u64>> {
+        HeaderBackend::number(&*self.inner, hash)
+    }
+
+    fn hash(&self, number: u64) -> sp_blockchain::Result<Option<H256>> {
+        HeaderBackend::hash(&*self.inner, number)
+    }
+}
+
+/// Returns the runtime on which the blocking reads of gateway are spawned.
+fn test_runtime() -> tokio::runtime::Runtime {
+    tokio::runtime::Builder::new_current_thread()
+        .build()
+        .expect("Failed to create the test runtime")
+}
+
+fn test_storage() -> PermanentStorage<()> {
+    let storage = PermanentStorage::new_test(Arc::new(()));
+    storage
+        .chunk_store()
+        .write_data(&CHUNK_ROOT, test_data(2, 10).as_slice())
+        .unwrap();
+    storage
+}
+
+fn request(method: Method, headers: &[(header::HeaderName, &str)]) -> Response<Body> {
+    let headers = headers
+        .iter()
+        .map(|(name, value)| (name.clone(), HeaderValue::from_str(value).unwrap()))
+        .collect::<HeaderMap>();
+    serve_data(&test_storage(), &CHUNK_ROOT, &method, &headers).unwrap()
+}
+
+fn body_of(response: Response<Body>) -> Vec<u8> {
+    futures::executor::block_on(hyper::body::to_bytes(response.into_body()))
+        .unwrap()
+        .to_vec()
+}
+
+#[test]
+fn byte_range_should_be_parsed() {
+    for &(range, expected) in &[
+        ("bytes=0-9", ByteRange::Partial { start: 0, end: 10 }),
+        (
+            "bytes=10-",
+            ByteRange::Partial {
+                start: 10,
+                end: 100,
+            },
+        ),
+        (
+            "bytes=90-200",
+            ByteRange::Partial {
+                start: 90,
+                end: 100,
+            },
+        ),
+        (
+            "bytes=-10",
+            ByteRange::Partial {
+                start: 90,
+                end: 100,
+            },
+        ),
+        ("bytes=-200", ByteRange::Partial { start: 0, end: 100 }),
+        ("bytes=100-", ByteRange::Unsatisfiable),
+        ("bytes=-0", ByteRange::Unsatisfiable),
+        ("bytes=10-5", ByteRange::Full),
+        ("bytes=0-1,5-6", ByteRange::Full),
+        ("items=0-9", ByteRange::Full),
+        ("bytes=x-y", ByteRange::Full),
+    ] {
+        assert_eq!(ByteRange::parse(range, 100), expected, "range: {}", range);
+    }
+}
+
+#[test]
+fn full_data_should_be_served() {
+    let runtime = test_runtime();
+    let _guard = runtime.enter();
+
+    let response = request(Method::GET, &[]);
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[header::ETAG],
+        format!("\"0x{}\"", "07".repeat(32))
+    );
+    assert_eq!(
+        response.headers()[header::CONTENT_LENGTH],
+        test_data(2, 10).len().to_string()
+    );
+    assert_eq!(body_of(response), test_data(2, 10));
+}
+
+#[test]
+fn partial_data_should_be_served() {
+    let runtime = test_runtime();
+    let _guard = runtime.enter();
+
+    let data = test_data(2, 10);
+    let response = request(Method::GET, &[(header::RANGE, "bytes=-20")]);
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(
+        response.headers()[header::CONTENT_RANGE],
+        format!(
+            "bytes {}-{}/{}",
+            data.len() - 20,
+            data.len() - 1,
+            data.len()
+        )
+    );
+    assert_eq!(body_of(response), data[data.len() - 20..].to_vec());
+
+    let response = request(Method::GET, &[(header::RANGE, "bytes=262000-262200")]);
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(body_of(response), data[262000..262201].to_vec());
+
+    let response = request(Method::GET, &[(header::RANGE, "bytes=1000000-")]);
+    assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(
+        response.headers()[header::CONTENT_RANGE],
+        format!("bytes */{}", data.len())
+    );
+}
+
+#[test]
+fn conditional_and_head_requests_should_work() {
+    let runtime = test_runtime();
+    let _guard = runtime.enter();
+
+    let etag = format!("\"0x{}\"", "07".repeat(32));
+
+    let response = request(Method::GET, &[(header::IF_NONE_MATCH, &etag)]);
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert!(body_of(response).is_empty());
+
+    let response = request(Method::GET, &[(header::IF_NONE_MATCH, "\"0x00\"")]);
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = request(Method::HEAD, &[]);
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[header::CONTENT_LENGTH],
+        test_data(2, 10).len().to_string()
+    );
+    assert!(body_of(response).is_empty());
+}
+
+#[test]
+fn unknown_data_should_not_be_found() {
+    let runtime = test_runtime();
+    let _guard = runtime.enter();
+
+    let storage = test_storage();
+    let response = serve_data(&storage, &[0u8; 32], &Method::GET, &HeaderMap::new()).unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn transaction_data_should_be_served_by_block_and_extrinsic_index() {
+    let runtime = test_runtime();
+    let _guard = runtime.enter();
+
+    let chain = TestChain::new();
+    let genesis_hash = chain.info().genesis_hash;
+    let storage = PermanentStorage::new_test(Arc::new(chain.clone()));
+    storage
+        .chunk_store()
+        .write_data(&CHUNK_ROOT, test_data(2, 10).as_slice())
+        .unwrap();
+    chain.store(0, 1, H256::from(CHUNK_ROOT), test_data(2, 10).len() as u32);
+
+    let get = |path: &str| {
+        let req = Request::get(path).body(Body::empty()).unwrap();
+        handle_request::<Block, _>(&storage, &req)
+    };
+
+    let response = get("/tx/0/1");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(body_of(response), test_data(2, 10));
+
+    let response = get(&format!("/tx/{:?}/1", genesis_hash));
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(body_of(response), test_data(2, 10));
+
+    assert_eq!(get("/tx/0/2").status(), StatusCode::NOT_FOUND);
+    assert_eq!(get("/tx/100/1").status(), StatusCode::NOT_FOUND);
+    assert_eq!(get("/tx/0/x").status(), StatusCode::BAD_REQUEST);
+    assert_eq!(get("/tx/0x00/1").status(), StatusCode::BAD_REQUEST);
+}
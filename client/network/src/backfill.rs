@@ -0,0 +1,560 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// This file is part of Canyon.
+//
+// Copyright (c) 2021 Canyon Labs.
+//
+// Canyon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// Canyon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Canyon. If not, see <http://www.gnu.org/licenses/>.
+
+//! Worker backfilling the historical transaction data for the newly joining nodes.
+//!
+//! A node syncing the chain gets the block headers and bodies, but none of the
+//! transaction data stored by the blocks, which is required for producing the PoA
+//! justifications. [`Backfill`] walks through all the finalized blocks from genesis,
+//! fetches the transaction data missing locally from peers, verifies it against the
+//! on-chain chunk root and persists it in the datastore. The transaction data out of
+//! the weave ranges selected by the [`StoragePolicy`] is skipped.
+//!
+//! The transaction data of the historical blocks is read from the state of the best
+//! finalized block, as the state of the historical blocks is pruned.
+
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::task::Poll;
+use std::time::{Duration, Instant};
+
+use codec::{Decode, Encode};
+use futures::{stream::BoxStream, FutureExt, StreamExt};
+use futures_timer::Delay;
+use parking_lot::RwLock;
+use prometheus_endpoint::{register, Counter, Gauge, PrometheusError, Registry, U64};
+
+use sc_network::PeerId;
+use sp_api::ProvideRuntimeApi;
+use sp_blockchain::HeaderBackend;
+use sp_core::H256;
+use sp_runtime::{
+    generic::BlockId,
+    traits::{Block as BlockT, NumberFor, SaturatedConversion},
+};
+
+use cc_datastore::{ChunkStorage, SelectedRanges, StoragePolicy};
+use cp_permastore::PermastoreApi;
+
+use crate::fetcher::fetch_data;
+use crate::sync::{rep, transaction_data_in_block, StoredData};
+use crate::{Network, NetworkEvent};
+
+/// Interval of checking for the newly finalized blocks once the backfill has caught up,
+/// or for the peers once it has stalled.
+const IDLE_INTERVAL: Duration = Duration::from_secs(6);
+
+/// Interval of retrying the transaction data which was not available from any peer.
+const RETRY_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Maximum number of the pending transaction data, the scan stalls once it's reached
+/// until some of them are fetched. The transaction data of the last scanned block may
+/// exceed it.
+const MAX_PENDING_DATA: usize = 4096;
+
+/// Key of the persisted number of the block from which the backfill resumes.
+const NEXT_BLOCK_KEY: &[u8] = b"backfill_next_block";
+
+/// Source of the transaction data stored on chain, walked through by [`Backfill`].
+pub trait ChainDataSource: Send + Sync + 'static {
+    /// Returns the number of the best finalized block.
+    fn finalized_number(&self) -> u64;
+
+    /// Returns the weave size as of the best finalized block.
+    fn weave_size(&self) -> Result<u64, String>;
+
+    /// Returns the transaction data stored in the finalized block `block_number`.
+    fn transaction_data(&self, block_number: u64) -> Result<Vec<StoredData>, String>;
+}
+
+/// [`ChainDataSource`] backed by the client.
+pub struct ClientDataSource<B, C> {
+    client: Arc<C>,
+    phantom: PhantomData<B>,
+}
+
+impl<B, C> ClientDataSource<B, C> {
+    /// Creates a new instance of [`ClientDataSource`].
+    pub fn new(client: Arc<C>) -> Self {
+        Self {
+            client,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<B, C> ChainDataSource for ClientDataSource<B, C>
+where
+    B: BlockT<Hash = H256>,
+    C: HeaderBackend<B> + ProvideRuntimeApi<B> + Send + Sync + 'static,
+    C::Api: PermastoreApi<B, NumberFor<B>, u32, B::Hash>,
+{
+    fn finalized_number(&self) -> u64 {
+        self.client.info().finalized_number.saturated_into()
+    }
+
+    fn weave_size(&self) -> Result<u64, String> {
+        let finalized_hash = self.client.info().finalized_hash;
+        self.client
+            .runtime_api()
+            .weave_size(&BlockId::Hash(finalized_hash))
+            .map_err(|e| e.to_string())
+    }
+
+    fn transaction_data(&self, block_number: u64) -> Result<Vec<StoredData>, String> {
+        let info = self.client.info();
+        let number: NumberFor<B> = block_number.saturated_into();
+        if number > info.finalized_number {
+            return Err(format!("Block #{} is not finalized", block_number));
+        }
+        transaction_data_in_block(&*self.client, BlockId::Hash(info.finalized_hash), number)
+    }
+}
+
+/// Progress of [`Backfill`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BackfillProgress {
+    /// Number of the next block to scan, all the blocks before have been scanned.
+    pub next_block: u64,
+    /// Number of the best finalized block, which the backfill is catching up with.
+    pub target_block: u64,
+    /// Number of the transaction data fetched from peers.
+    pub fetched_data: u64,
+    /// Total byte size of the transaction data fetched from peers.
+    pub fetched_bytes: u64,
+    /// Number of the missing transaction data which is not available from any peer yet.
+    pub pending_data: u64,
+}
+
+/// [`BackfillProgress`] shared between [`Backfill`] and the RPC.
+#[derive(Debug, Clone, Default)]
+pub struct SharedBackfillProgress(Arc<RwLock<BackfillProgress>>);
+
+impl SharedBackfillProgress {
+    /// Returns the current progress.
+    pub fn get(&self) -> BackfillProgress {
+        *self.0.read()
+    }
+
+    fn set(&self, progress: BackfillProgress) {
+        *self.0.write() = progress;
+    }
+}
+
+/// Prometheus metrics of [`Backfill`].
+struct Metrics {
+    next_block: Gauge<U64>,
+    target_block: Gauge<U64>,
+    fetched_data: Counter<U64>,
+    fetched_bytes: Counter<U64>,
+    pending_data: Gauge<U64>,
+}
+
+impl Metrics {
+    fn register(registry: &Registry) -> Result<Self, PrometheusError> {
+        Ok(Self {
+            next_block: register(
+                Gauge::new(
+                    "permastore_backfill_next_block",
+                    "Number of the next block to be scanned by the backfill",
+                )?,
+                registry,
+            )?,
+            target_block: register(
+                Gauge::new(
+                    "permastore_backfill_target_block",
+                    "Number of the finalized block the backfill is catching up with",
+                )?,
+                registry,
+            )?,
+            fetched_data: register(
+                Counter::new(
+                    "permastore_backfill_fetched_data_total",
+                    "Number of the transaction data fetched by the backfill",
+                )?,
+                registry,
+            )?,
+            fetched_bytes: register(
+                Counter::new(
+                    "permastore_backfill_fetched_bytes_total",
+                    "Total byte size of the transaction data fetched by the backfill",
+                )?,
+                registry,
+            )?,
+            pending_data: register(
+                Gauge::new(
+                    "permastore_backfill_pending_data",
+                    "Number of the missing transaction data not available from any peer",
+                )?,
+                registry,
+            )?,
+        })
+    }
+}
+
+/// Yields the execution back to the executor once.
+async fn yield_now() {
+    let mut yielded = false;
+    futures::future::poll_fn(|cx| {
+        if yielded {
+            Poll::Ready(())
+        } else {
+            yielded = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    })
+    .await
+}
+
+/// Worker fetching the transaction data of all the finalized blocks missing locally.
+///
+/// The blocks are scanned in order from genesis, the transaction data out of the weave
+/// ranges selected by the [`StoragePolicy`] of storage is skipped, which is decided by
+/// the weave size as of the best finalized block. The transaction data which is not
+/// available from any connected peer, or does not fit in the storage budget yet, is put
+/// aside and retried periodically once the backfill has caught up with the finalized
+/// block.
+///
+/// The progress is persisted in the datastore, the backfill resumes from the first block
+/// which has not been scanned or of which some transaction data is still pending after
+/// the node restarts. The blocks of which the transaction data has been lost locally,
+/// e.g., quarantined by the datastore scrubber, can be rescanned through
+/// [`Backfill::with_rescan`].
+///
+/// Like [`DataSync`](crate::DataSync), the worker is expected to be spawned as a
+/// blocking task.
+pub struct Backfill<Chain, N, S> {
+    chain: Chain,
+    network: N,
+    storage: S,
+    network_events: BoxStream<'static, NetworkEvent>,
+    /// Numbers of the blocks to scan again.
+    rescan: Option<BoxStream<'static, u64>>,
+    peers: Vec<PeerId>,
+    /// Missing transaction data not available from any peer, with the number of block
+    /// storing it.
+    pending: Vec<(u64, StoredData)>,
+    policy: StoragePolicy,
+    selected_ranges: SelectedRanges,
+    last_retry: Instant,
+    progress: BackfillProgress,
+    shared_progress: SharedBackfillProgress,
+    metrics: Option<Metrics>,
+}
+
+impl<Chain, N, S> Backfill<Chain, N, S>
+where
+    Chain: ChainDataSource,
+    N: Network,
+    S: ChunkStorage,
+{
+    /// Creates a new instance of [`Backfill`], of which the progress is published to
+    /// `shared_progress` and the metrics are registered in `registry` if any.
+    pub fn new(
+        chain: Chain,
+        network: N,
+        storage: S,
+        shared_progress: SharedBackfillProgress,
+        registry: Option<&Registry>,
+    ) -> Self {
+        let metrics = registry.and_then(|registry| {
+            Metrics::register(registry)
+                .map_err(|e| {
+                    log::error!(
+                        target: "sync::permastore",
+                        "Failed to register the backfill metrics: {}",
+                        e,
+                    )
+                })
+                .ok()
+        });
+
+        let next_block = match storage.chunk_store().meta(NEXT_BLOCK_KEY) {
+            Ok(next_block) => next_block
+                .and_then(|next_block| u64::decode(&mut next_block.as_slice()).ok())
+                .unwrap_or_default(),
+            Err(e) => {
+                log::error!(
+                    target: "sync::permastore",
+                    "Failed to read the backfill progress, scanning from genesis: {}",
+                    e,
+                );
+                0
+            }
+        };
+
+        Self {
+            network_events: network.event_stream(),
+            rescan: None,
+            policy: storage.storage_policy(),
+            selected_ranges: SelectedRanges::All,
+            chain,
+            network,
+            storage,
+            peers: Vec::new(),
+            pending: Vec::new(),
+            last_retry: Instant::now(),
+            progress: BackfillProgress {
+                next_block,
+                ..Default::default()
+            },
+            shared_progress,
+            metrics,
+        }
+    }
+
+    /// Sets the stream of the block numbers to scan again, from which the backfill
+    /// restarts if they have been scanned.
+    pub fn with_rescan(mut self, rescan: BoxStream<'static, u64>) -> Self {
+        self.rescan = Some(rescan);
+        self
+    }
+
+    /// Runs the backfill until the network is shut down.
+    pub async fn run(mut self) {
+        self.update_selected_ranges();
+
+        loop {
+            if !self.process_network_events() {
+                return;
+            }
+            self.process_rescans();
+
+            let target_block = self.chain.finalized_number();
+            if target_block != self.progress.target_block {
+                self.update_selected_ranges();
+            }
+            self.progress.target_block = target_block;
+            self.publish_progress();
+
+            if self.progress.next_block > self.progress.target_block || self.is_stalled() {
+                if self.last_retry.elapsed() >= RETRY_INTERVAL {
+                    self.retry_pending().await;
+                    self.last_retry = Instant::now();
+                }
+                Delay::new(IDLE_INTERVAL).await;
+                continue;
+            }
+
+            let block_number = self.progress.next_block;
+            match self.chain.transaction_data(block_number) {
+                Ok(data) => {
+                    for data in data {
+                        if self.is_wanted(&data) && !self.fetch(data).await {
+                            self.pending.push((block_number, data));
+                        }
+                    }
+                    self.progress.next_block = block_number + 1;
+                    self.progress.pending_data = self.pending.len() as u64;
+                    self.persist_progress();
+                    yield_now().await;
+                }
+                Err(e) => {
+                    log::warn!(
+                        target: "sync::permastore",
+                        "Failed to read the transaction data in block #{}: {}",
+                        block_number, e,
+                    );
+                    Delay::new(IDLE_INTERVAL).await;
+                }
+            }
+        }
+    }
+
+    /// Returns `true` if the scan can not make progress for now, i.e., no peer is
+    /// connected to fetch the data from or too much data is pending already.
+    fn is_stalled(&self) -> bool {
+        self.peers.is_empty() || self.pending.len() >= MAX_PENDING_DATA
+    }
+
+    /// Processes the network events received so far, returns `false` if the network
+    /// has been shut down.
+    fn process_network_events(&mut self) -> bool {
+        while let Some(event) = self.network_events.next().now_or_never() {
+            match event {
+                Some(NetworkEvent::PeerConnected(who)) => {
+                    if !self.peers.contains(&who) {
+                        self.peers.push(who);
+                    }
+                }
+                Some(NetworkEvent::PeerDisconnected(who)) => {
+                    self.peers.retain(|peer| *peer != who);
+                }
+                Some(NetworkEvent::Announcement { .. }) => {}
+                None => return false,
+            }
+        }
+        true
+    }
+
+    fn process_rescans(&mut self) {
+        if let Some(rescan) = &mut self.rescan {
+            loop {
+                match rescan.next().now_or_never() {
+                    Some(Some(block_number)) => {
+                        self.progress.next_block = self.progress.next_block.min(block_number);
+                        self.persist_progress();
+                    }
+                    Some(None) => {
+                        self.rescan = None;
+                        return;
+                    }
+                    None => return,
+                }
+            }
+        }
+    }
+
+    /// Updates the selected weave ranges as of the best finalized block.
+    fn update_selected_ranges(&mut self) {
+        if self.policy.is_unlimited() {
+            return;
+        }
+
+        match self.chain.weave_size() {
+            Ok(weave_size) => self.selected_ranges = self.policy.selected_ranges(weave_size),
+            Err(e) => {
+                log::warn!(
+                    target: "sync::permastore",
+                    "Failed to read the weave size: {}",
+                    e,
+                );
+            }
+        }
+    }
+
+    /// Returns `true` if the transaction data is selected but missing locally.
+    fn is_wanted(&self, data: &StoredData) -> bool {
+        self.selected_ranges.contains(data.weave_base) && !self.is_stored_locally(&data.chunk_root)
+    }
+
+    fn is_stored_locally(&self, chunk_root: &H256) -> bool {
+        match self.storage.data_info(&chunk_root.encode()) {
+            Ok(data_info) => data_info.is_some(),
+            Err(e) => {
+                log::error!(
+                    target: "sync::permastore",
+                    "Failed to read the data info of {}: {}",
+                    chunk_root, e,
+                );
+                false
+            }
+        }
+    }
+
+    /// Fetches the transaction data from the connected peers in turn, returns `true`
+    /// if the data is fetched successfully.
+    ///
+    /// The data is not fetched if it does not fit in the storage budget, which may be
+    /// freed up by the pruner later.
+    async fn fetch(&mut self, data: StoredData) -> bool {
+        let StoredData {
+            chunk_root,
+            data_size,
+            ..
+        } = data;
+        let chunk_store = self.storage.chunk_store().clone();
+
+        if !self.policy.has_room(chunk_store.stored_size(), data_size) {
+            log::debug!(
+                target: "sync::permastore",
+                "No room for transaction data {} within the storage budget",
+                chunk_root,
+            );
+            return false;
+        }
+
+        for who in self.peers.clone() {
+            match fetch_data(&self.network, &chunk_store, who, chunk_root, data_size).await {
+                Ok(()) => {
+                    log::debug!(
+                        target: "sync::permastore",
+                        "Backfilled transaction data {} from {}",
+                        chunk_root, who,
+                    );
+                    self.progress.fetched_data += 1;
+                    self.progress.fetched_bytes += data_size;
+                    if let Some(metrics) = &self.metrics {
+                        metrics.fetched_data.inc();
+                        metrics.fetched_bytes.inc_by(data_size);
+                    }
+                    return true;
+                }
+                Err(e) => {
+                    log::debug!(
+                        target: "sync::permastore",
+                        "Failed to backfill transaction data {} from {}: {}",
+                        chunk_root, who, e,
+                    );
+                    if e.is_misbehavior() {
+                        self.network.report_peer(who, rep::INVALID_DATA);
+                    }
+                }
+            }
+        }
+
+        false
+    }
+
+    /// Retries fetching the transaction data which was not available before.
+    async fn retry_pending(&mut self) {
+        self.process_network_events();
+
+        for (block_number, data) in std::mem::take(&mut self.pending) {
+            if self.is_wanted(&data) && !self.fetch(data).await {
+                self.pending.push((block_number, data));
+            }
+        }
+
+        self.progress.pending_data = self.pending.len() as u64;
+        self.persist_progress();
+        self.publish_progress();
+    }
+
+    /// Persists the number of the block from which the backfill resumes after restart,
+    /// the blocks of the pending transaction data are scanned again.
+    fn persist_progress(&self) {
+        let next_block = self
+            .pending
+            .iter()
+            .map(|(block_number, _)| *block_number)
+            .fold(self.progress.next_block, u64::min);
+
+        if let Err(e) = self
+            .storage
+            .chunk_store()
+            .put_meta(NEXT_BLOCK_KEY, &next_block.encode())
+        {
+            log::error!(
+                target: "sync::permastore",
+                "Failed to persist the backfill progress: {}",
+                e,
+            );
+        }
+    }
+
+    fn publish_progress(&self) {
+        self.shared_progress.set(self.progress);
+        if let Some(metrics) = &self.metrics {
+            metrics.next_block.set(self.progress.next_block);
+            metrics.target_block.set(self.progress.target_block);
+            metrics.pending_data.set(self.progress.pending_data);
+        }
+    }
+}
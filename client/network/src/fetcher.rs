@@ -0,0 +1,166 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// This file is part of Canyon.
+//
+// Copyright (c) 2021 Canyon Labs.
+//
+// Canyon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// Canyon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Canyon. If not, see <http://www.gnu.org/licenses/>.
+
+//! Fetching the transaction data from peers.
+
+use std::io;
+
+use codec::{Decode, Encode};
+
+use sc_network::{PeerId, RequestFailure};
+use sp_core::H256;
+
+use cc_datastore::{unix_timestamp, ChunkStore, DataInfo};
+use cp_permastore::{chunk_root_of_hashes, verify_chunk_proof, VerifyError, CHUNK_SIZE};
+
+use crate::protocol::{ChunkRequest, ChunkResponse};
+use crate::Network;
+
+/// Error of fetching the transaction data from a peer.
+#[derive(Debug, thiserror::Error)]
+pub enum FetchError {
+    /// The request failed.
+    #[error("Chunk request failed: {0}")]
+    Request(#[from] RequestFailure),
+    /// The response can not be decoded.
+    #[error("Failed to decode the chunk response: {0}")]
+    Decode(#[from] codec::Error),
+    /// The peer does not have the requested chunk.
+    #[error("Chunk not found")]
+    NotFound,
+    /// The chunk does not match the transaction data.
+    #[error("Invalid chunk: {0}")]
+    InvalidChunk(&'static str),
+    /// The chunk proof can not be verified against the chunk root.
+    #[error("Invalid chunk proof: {0:?}")]
+    InvalidProof(VerifyError),
+    /// Error from the datastore.
+    #[error(transparent)]
+    Datastore(#[from] io::Error),
+}
+
+impl FetchError {
+    /// Returns `true` if the error is caused by the peer serving invalid data.
+    pub fn is_misbehavior(&self) -> bool {
+        matches!(
+            self,
+            Self::Decode(_) | Self::InvalidChunk(_) | Self::InvalidProof(_)
+        )
+    }
+}
+
+/// Fetches chunk `chunk_index` of the transaction data `chunk_root` of `data_size`
+/// bytes from `who`, the chunk is verified against the chunk root.
+pub async fn fetch_chunk<N: Network>(
+    network: &N,
+    who: PeerId,
+    chunk_root: H256,
+    data_size: u64,
+    chunk_index: u32,
+) -> Result<Vec<u8>, FetchError> {
+    let request = ChunkRequest {
+        chunk_root,
+        chunk_index,
+    };
+
+    let response = network.request(who, request.encode()).await?;
+
+    let ChunkResponse {
+        data_size: provided_data_size,
+        chunk,
+        proof,
+    } = Option::<ChunkResponse>::decode(&mut response.as_slice())?.ok_or(FetchError::NotFound)?;
+
+    if provided_data_size != data_size {
+        return Err(FetchError::InvalidChunk("data size mismatch"));
+    }
+
+    // All the chunks except the last one are expected to be full.
+    let expected_chunk_size =
+        (data_size - chunk_index as u64 * CHUNK_SIZE as u64).min(CHUNK_SIZE as u64);
+    if chunk.len() as u64 != expected_chunk_size {
+        return Err(FetchError::InvalidChunk("unexpected chunk size"));
+    }
+
+    verify_chunk_proof(&chunk_root, &chunk, chunk_index, &proof)
+        .map_err(FetchError::InvalidProof)?;
+
+    Ok(chunk)
+}
+
+/// Fetches the entire transaction data `chunk_root` of `data_size` bytes from `who`
+/// into `chunk_store` chunk by chunk.
+///
+/// The chunks received before, e.g., in an interrupted fetch, are skipped. Once all
+/// the chunks are received, the chunk root is recomputed so that the data is rejected
+/// if the chunk trie contains any extra chunks beyond `data_size`.
+///
+/// `data_size` is recorded on chain, a pending upload of the same data declaring a
+/// different size, e.g., submitted by a misbehaving RPC client, is restarted.
+pub async fn fetch_data<N: Network>(
+    network: &N,
+    chunk_store: &ChunkStore,
+    who: PeerId,
+    chunk_root: H256,
+    data_size: u64,
+) -> Result<(), FetchError> {
+    let key = chunk_root.encode();
+
+    let chunk_count = DataInfo { data_size }.chunk_count();
+    if chunk_count == 0 {
+        return Err(FetchError::InvalidChunk("empty transaction data"));
+    }
+
+    if matches!(chunk_store.upload_progress(&key)?, Some(progress) if progress.data_size != data_size)
+    {
+        chunk_store.restart_upload(&key, data_size, unix_timestamp())?;
+    }
+
+    for chunk_index in 0..chunk_count {
+        if chunk_store.chunk(&key, chunk_index)?.is_some() {
+            continue;
+        }
+        let chunk = fetch_chunk(network, who, chunk_root, data_size, chunk_index).await?;
+        match chunk_store.put_uploaded_chunk(&key, data_size, chunk_index, &chunk, unix_timestamp())
+        {
+            // Completed by a concurrent fetch of the same data, e.g., by the backfill.
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => return Ok(()),
+            result => result?,
+        };
+    }
+
+    if chunk_store.data_info(&key)?.is_some() {
+        return Ok(());
+    }
+
+    let chunk_hashes = chunk_store.uploaded_chunk_hashes(&key)?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            "Fetched chunks are missing in the chunk store",
+        )
+    })?;
+
+    if chunk_root_of_hashes(chunk_hashes.iter().copied()) != chunk_root {
+        chunk_store.remove(&key)?;
+        return Err(FetchError::InvalidChunk("chunk root mismatch"));
+    }
+
+    chunk_store.complete_upload(&key, &chunk_hashes)?;
+
+    Ok(())
+}
@@ -0,0 +1,230 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// This file is part of Canyon.
+//
+// Copyright (c) 2021 Canyon Labs.
+//
+// Canyon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// Canyon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Canyon. If not, see <http://www.gnu.org/licenses/>.
+
+#![deny(missing_docs, unused_extern_crates)]
+
+//! This crate implements the network protocols replicating the transaction data
+//! between the nodes.
+//!
+//! Two protocols are involved:
+//!
+//! - The request-response protocol [`CHUNK_PROTOCOL_NAME`], through which a node
+//!   requests a single chunk by `(chunk_root, chunk_index)` and gets it back along
+//!   with the proof against the chunk root.
+//! - The notifications protocol [`ANNOUNCEMENT_PROTOCOL_NAME`], through which the
+//!   nodes gossip the transaction data available locally.
+//!
+//! Once a block containing the `store` extrinsics is imported, the nodes holding
+//! the data announce it to their peers, and the others fetch the data chunk by chunk
+//! from the announcers, verifying each chunk against the on-chain chunk root. The
+//! node announces the data in turn once it has been fetched entirely.
+//!
+//! The historical transaction data stored before the node joins the network is
+//! fetched by [`Backfill`] in the background.
+//!
+//! A node with a limited disk budget only stores the transaction data in the weave
+//! ranges selected by its [`cc_datastore::StoragePolicy`], which is honoured by both
+//! [`DataSync`] and [`Backfill`], the data falling out of the selection as the weave
+//! grows is removed by [`Pruner`].
+
+mod backfill;
+mod fetcher;
+mod protocol;
+mod pruner;
+mod request_handler;
+mod sync;
+#[cfg(test)]
+mod tests;
+
+use std::iter;
+use std::sync::Arc;
+
+use futures::{
+    future::BoxFuture,
+    stream::{self, BoxStream},
+    FutureExt, StreamExt,
+};
+
+use sc_network::{
+    config::{NonDefaultSetConfig, NonReservedPeerMode, SetConfig},
+    multiaddr, Event, ExHashT, IfDisconnected, NetworkService, PeerId, ReputationChange,
+    RequestFailure,
+};
+use sp_runtime::traits::Block as BlockT;
+
+pub use self::backfill::{
+    Backfill, BackfillProgress, ChainDataSource, ClientDataSource, SharedBackfillProgress,
+};
+pub use self::fetcher::{fetch_chunk, fetch_data, FetchError};
+pub use self::protocol::{ChunkRequest, ChunkResponse, DataAnnouncement};
+pub use self::pruner::Pruner;
+pub use self::request_handler::{generate_protocol_config, ChunkRequestHandler};
+pub use self::sync::{imported_transaction_data, DataSync, StoredData};
+
+/// Name of the request-response protocol for fetching the chunks of transaction data.
+pub const CHUNK_PROTOCOL_NAME: &str = "/canyon/permastore/chunk/1";
+
+/// Name of the notifications protocol for announcing the transaction data.
+pub const ANNOUNCEMENT_PROTOCOL_NAME: &str = "/canyon/permastore/announcement/1";
+
+/// Maximum byte size of a [`DataAnnouncement`] notification.
+const MAX_ANNOUNCEMENT_SIZE: u64 = 1024;
+
+/// Returns the configuration of the peer set of [`ANNOUNCEMENT_PROTOCOL_NAME`].
+///
+/// Like GRANDPA, the peers connected for syncing are added to the set as they
+/// connect, see [`Network::event_stream`].
+pub fn announcement_peers_set_config() -> NonDefaultSetConfig {
+    NonDefaultSetConfig {
+        notifications_protocol: ANNOUNCEMENT_PROTOCOL_NAME.into(),
+        fallback_names: Vec::new(),
+        max_notification_size: MAX_ANNOUNCEMENT_SIZE,
+        set_config: SetConfig {
+            in_peers: 0,
+            out_peers: 0,
+            reserved_nodes: Vec::new(),
+            non_reserved_mode: NonReservedPeerMode::Deny,
+        },
+    }
+}
+
+/// Network event relevant to the transaction data sync.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NetworkEvent {
+    /// The announcement substream with the peer has been opened.
+    PeerConnected(PeerId),
+    /// The announcement substream with the peer has been closed.
+    PeerDisconnected(PeerId),
+    /// Received an announcement from the peer.
+    Announcement {
+        /// Peer sending the announcement.
+        who: PeerId,
+        /// Encoded [`DataAnnouncement`].
+        message: Vec<u8>,
+    },
+}
+
+/// Abstraction over the network service used by the transaction data sync.
+pub trait Network: Clone + Send + Sync + 'static {
+    /// Returns a new stream of the network events relevant to the transaction data sync.
+    ///
+    /// Each stream receives all the events since it is created.
+    fn event_stream(&self) -> BoxStream<'static, NetworkEvent>;
+
+    /// Sends an encoded [`DataAnnouncement`] to the peer.
+    fn announce(&self, who: PeerId, message: Vec<u8>);
+
+    /// Sends an encoded [`ChunkRequest`] to the peer and returns the response.
+    fn request(
+        &self,
+        who: PeerId,
+        request: Vec<u8>,
+    ) -> BoxFuture<'static, Result<Vec<u8>, RequestFailure>>;
+
+    /// Adjusts the reputation of the peer.
+    fn report_peer(&self, who: PeerId, cost_benefit: ReputationChange);
+}
+
+impl<B: BlockT, H: ExHashT> Network for Arc<NetworkService<B, H>> {
+    fn event_stream(&self) -> BoxStream<'static, NetworkEvent> {
+        let network = self.clone();
+        NetworkService::event_stream(self, "permastore-sync")
+            .flat_map(move |event| {
+                let events = match event {
+                    Event::SyncConnected { remote } => {
+                        let addr = iter::once(multiaddr::Protocol::P2p(remote.into()))
+                            .collect::<multiaddr::Multiaddr>();
+                        if let Err(e) = network.add_peers_to_reserved_set(
+                            ANNOUNCEMENT_PROTOCOL_NAME.into(),
+                            iter::once(addr).collect(),
+                        ) {
+                            log::error!(
+                                target: "sync::permastore",
+                                "Failed to add reserved peer: {}",
+                                e,
+                            );
+                        }
+                        Vec::new()
+                    }
+                    Event::SyncDisconnected { remote } => {
+                        let addr = iter::once(multiaddr::Protocol::P2p(remote.into()))
+                            .collect::<multiaddr::Multiaddr>();
+                        if let Err(e) = network.remove_peers_from_reserved_set(
+                            ANNOUNCEMENT_PROTOCOL_NAME.into(),
+                            iter::once(addr).collect(),
+                        ) {
+                            log::error!(
+                                target: "sync::permastore",
+                                "Failed to remove reserved peer: {}",
+                                e,
+                            );
+                        }
+                        Vec::new()
+                    }
+                    Event::NotificationStreamOpened {
+                        remote, protocol, ..
+                    } if protocol == ANNOUNCEMENT_PROTOCOL_NAME => {
+                        vec![NetworkEvent::PeerConnected(remote)]
+                    }
+                    Event::NotificationStreamClosed { remote, protocol }
+                        if protocol == ANNOUNCEMENT_PROTOCOL_NAME =>
+                    {
+                        vec![NetworkEvent::PeerDisconnected(remote)]
+                    }
+                    Event::NotificationsReceived { remote, messages } => messages
+                        .into_iter()
+                        .filter(|(protocol, _)| protocol == ANNOUNCEMENT_PROTOCOL_NAME)
+                        .map(|(_, message)| NetworkEvent::Announcement {
+                            who: remote,
+                            message: message.to_vec(),
+                        })
+                        .collect(),
+                    _ => Vec::new(),
+                };
+                stream::iter(events)
+            })
+            .boxed()
+    }
+
+    fn announce(&self, who: PeerId, message: Vec<u8>) {
+        self.write_notification(who, ANNOUNCEMENT_PROTOCOL_NAME.into(), message);
+    }
+
+    fn request(
+        &self,
+        who: PeerId,
+        request: Vec<u8>,
+    ) -> BoxFuture<'static, Result<Vec<u8>, RequestFailure>> {
+        let network = self.clone();
+        async move {
+            NetworkService::request(
+                &network,
+                who,
+                CHUNK_PROTOCOL_NAME,
+                request,
+                IfDisconnected::ImmediateError,
+            )
+            .await
+        }
+        .boxed()
+    }
+
+    fn report_peer(&self, who: PeerId, cost_benefit: ReputationChange) {
+        NetworkService::report_peer(self, who, cost_benefit);
+    }
+}
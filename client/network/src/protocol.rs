@@ -0,0 +1,55 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// This file is part of Canyon.
+//
+// Copyright (c) 2021 Canyon Labs.
+//
+// Canyon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// Canyon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Canyon. If not, see <http://www.gnu.org/licenses/>.
+
+//! Messages of the transaction data sync protocols, encoded in SCALE.
+
+use codec::{Decode, Encode};
+
+use sp_core::H256;
+
+/// Request for a chunk of the transaction data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub struct ChunkRequest {
+    /// Chunk root of the transaction data.
+    pub chunk_root: H256,
+    /// Index of the requested chunk.
+    pub chunk_index: u32,
+}
+
+/// Chunk of the transaction data along with its proof.
+///
+/// The response to a [`ChunkRequest`] is an encoded `Option<ChunkResponse>`, which
+/// is `None` if the requested chunk is not stored by the peer.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct ChunkResponse {
+    /// Byte size of the entire transaction data.
+    pub data_size: u64,
+    /// Content of the chunk.
+    pub chunk: Vec<u8>,
+    /// Trie nodes proving the chunk is included in the chunk root.
+    pub proof: Vec<Vec<u8>>,
+}
+
+/// Announcement of the transaction data available from the sender.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub struct DataAnnouncement {
+    /// Chunk root of the transaction data.
+    pub chunk_root: H256,
+    /// Byte size of the transaction data.
+    pub data_size: u64,
+}
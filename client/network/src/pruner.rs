@@ -0,0 +1,151 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// This file is part of Canyon.
+//
+// Copyright (c) 2021 Canyon Labs.
+//
+// Canyon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// Canyon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Canyon. If not, see <http://www.gnu.org/licenses/>.
+
+//! Worker pruning the transaction data out of the storage policy.
+//!
+//! The weave ranges selected by the [`StoragePolicy`] shift as the weave grows, the
+//! transaction data stored locally but no longer selected is removed periodically so
+//! that the datastore stays within the disk budget.
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use codec::Encode;
+use futures_timer::Delay;
+
+use cc_datastore::{ChunkStorage, SelectedRanges, StoragePolicy};
+use cp_permastore::PermaStorage;
+
+use crate::backfill::ChainDataSource;
+
+/// Interval of pruning the transaction data.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Worker removing the transaction data of the finalized blocks which is out of the
+/// weave ranges selected by the [`StoragePolicy`] of storage.
+///
+/// A pass walks through all the finalized blocks, the worker is expected to be spawned
+/// as a blocking task. The pass is skipped if the selected weave ranges have not changed
+/// since the last one, in which case there is nothing new to prune.
+///
+/// The transaction data left in the legacy offchain storage is not counted in the
+/// stored size of the datastore, a pass moves the selected legacy data to the chunk
+/// store, where it's counted, and removes the unselected one.
+pub struct Pruner<Chain, S> {
+    chain: Chain,
+    storage: S,
+    policy: StoragePolicy,
+    /// Weave ranges selected in the last successful pass.
+    last_selected: Option<SelectedRanges>,
+}
+
+impl<Chain, S> Pruner<Chain, S>
+where
+    Chain: ChainDataSource,
+    S: ChunkStorage,
+{
+    /// Creates a new instance of [`Pruner`].
+    pub fn new(chain: Chain, storage: S) -> Self {
+        let policy = storage.storage_policy();
+        Self {
+            chain,
+            storage,
+            policy,
+            last_selected: None,
+        }
+    }
+
+    /// Runs the pruner forever, returns immediately if the storage policy is unlimited.
+    pub async fn run(mut self) {
+        if self.policy.is_unlimited() {
+            return;
+        }
+
+        loop {
+            match self.prune() {
+                Ok(0) => {}
+                Ok(removed) => {
+                    log::info!(
+                        target: "sync::permastore",
+                        "Pruned {} transaction data out of the storage policy",
+                        removed,
+                    );
+                }
+                Err(e) => {
+                    log::error!(
+                        target: "sync::permastore",
+                        "Failed to prune the transaction data: {}",
+                        e,
+                    );
+                }
+            }
+            Delay::new(PRUNE_INTERVAL).await;
+        }
+    }
+
+    /// Removes the transaction data out of the selected weave ranges as of the best
+    /// finalized block, returns the number of the removed transaction data.
+    pub fn prune(&mut self) -> Result<usize, String> {
+        let selected_ranges = self.policy.selected_ranges(self.chain.weave_size()?);
+        if selected_ranges == SelectedRanges::All
+            || self.last_selected.as_ref() == Some(&selected_ranges)
+        {
+            return Ok(0);
+        }
+
+        // The same data can be stored more than once, it's kept as long as any of
+        // its weave positions is selected.
+        let mut selected = HashSet::new();
+        let mut unselected = HashSet::new();
+        for block_number in 0..=self.chain.finalized_number() {
+            for data in self.chain.transaction_data(block_number)? {
+                if selected_ranges.contains(data.weave_base) {
+                    selected.insert(data.chunk_root);
+                } else {
+                    unselected.insert(data.chunk_root);
+                }
+            }
+        }
+
+        for chunk_root in &selected {
+            // Moves the legacy data to the chunk store if any.
+            self.storage
+                .data_info(&chunk_root.encode())
+                .map_err(|e| e.to_string())?;
+        }
+
+        let mut removed = 0;
+        for chunk_root in unselected.difference(&selected) {
+            let key = chunk_root.encode();
+            // Including the data in the legacy storage.
+            if self.storage.exists(&key) {
+                self.storage.remove(&key);
+                log::debug!(
+                    target: "sync::permastore",
+                    "Removed transaction data {} out of the storage policy",
+                    chunk_root,
+                );
+                removed += 1;
+            }
+        }
+
+        self.last_selected = Some(selected_ranges);
+
+        Ok(removed)
+    }
+}
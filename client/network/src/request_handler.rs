@@ -0,0 +1,195 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// This file is part of Canyon.
+//
+// Copyright (c) 2021 Canyon Labs.
+//
+// Canyon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// Canyon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Canyon. If not, see <http://www.gnu.org/licenses/>.
+
+//! Handler of the inbound chunk requests.
+
+use std::io;
+use std::time::Duration;
+
+use codec::{Decode, Encode};
+use futures::{channel::mpsc, StreamExt};
+use lru::LruCache;
+
+use sc_network::config::{IncomingRequest, OutgoingResponse, RequestResponseConfig};
+use sp_core::H256;
+
+use cc_datastore::ChunkStorage;
+use cp_permastore::ChunkTrie;
+
+use crate::protocol::{ChunkRequest, ChunkResponse};
+use crate::CHUNK_PROTOCOL_NAME;
+
+/// Maximum byte size of an encoded [`ChunkRequest`].
+const MAX_REQUEST_SIZE: u64 = 1024;
+
+/// Maximum byte size of an encoded [`ChunkResponse`], which is a full chunk plus
+/// the proof of a few KiB.
+const MAX_RESPONSE_SIZE: u64 = 1024 * 1024;
+
+/// Maximum number of the inbound chunk requests queued for being handled.
+const MAX_PENDING_REQUESTS: usize = 32;
+
+/// Maximum number of the chunk tries kept in memory, a peer fetching the data
+/// requests its chunks one after another. The trie of 1GiB data is about 1MiB.
+const MAX_CACHED_TRIES: usize = 16;
+
+mod rep {
+    use sc_network::ReputationChange as Rep;
+
+    /// Reputation change when a peer sends us a request which can not be decoded.
+    pub const BAD_REQUEST: Rep = Rep::new(-(1 << 12), "Bad chunk request");
+}
+
+/// Error of handling an inbound chunk request.
+#[derive(Debug, thiserror::Error)]
+enum HandleRequestError {
+    #[error("Failed to decode the request: {0}")]
+    Decode(#[from] codec::Error),
+    #[error(transparent)]
+    Datastore(#[from] io::Error),
+}
+
+/// Generates the [`RequestResponseConfig`] of [`CHUNK_PROTOCOL_NAME`] without an
+/// inbound queue.
+pub fn generate_protocol_config() -> RequestResponseConfig {
+    RequestResponseConfig {
+        name: CHUNK_PROTOCOL_NAME.into(),
+        max_request_size: MAX_REQUEST_SIZE,
+        max_response_size: MAX_RESPONSE_SIZE,
+        request_timeout: Duration::from_secs(20),
+        inbound_queue: None,
+    }
+}
+
+/// Handler serving the chunks of transaction data stored locally to the peers.
+///
+/// The requests are handled with blocking reads of the datastore, the handler is
+/// expected to be spawned as a blocking task.
+pub struct ChunkRequestHandler<S> {
+    storage: S,
+    request_receiver: mpsc::Receiver<IncomingRequest>,
+    /// Chunk tries of the recently requested data, which are expensive to rebuild
+    /// for each chunk.
+    chunk_tries: LruCache<H256, ChunkTrie>,
+}
+
+impl<S: ChunkStorage> ChunkRequestHandler<S> {
+    /// Creates a new [`ChunkRequestHandler`] along with the protocol config which
+    /// has to be registered in the network.
+    pub fn new(storage: S) -> (Self, RequestResponseConfig) {
+        let (request_sender, request_receiver) = mpsc::channel(MAX_PENDING_REQUESTS);
+
+        let mut protocol_config = generate_protocol_config();
+        protocol_config.inbound_queue = Some(request_sender);
+
+        (
+            Self {
+                storage,
+                request_receiver,
+                chunk_tries: LruCache::new(MAX_CACHED_TRIES),
+            },
+            protocol_config,
+        )
+    }
+
+    /// Runs the handler until the network is shut down.
+    pub async fn run(mut self) {
+        while let Some(request) = self.request_receiver.next().await {
+            let IncomingRequest {
+                peer,
+                payload,
+                pending_response,
+            } = request;
+
+            let (result, reputation_changes) = match self.handle_request(&payload) {
+                Ok(response) => (Ok(response.encode()), Vec::new()),
+                Err(HandleRequestError::Decode(e)) => {
+                    log::debug!(
+                        target: "sync::permastore",
+                        "Invalid chunk request from {}: {}",
+                        peer, e,
+                    );
+                    (Err(()), vec![rep::BAD_REQUEST])
+                }
+                Err(HandleRequestError::Datastore(e)) => {
+                    log::warn!(
+                        target: "sync::permastore",
+                        "Failed to handle the chunk request from {}: {}",
+                        peer, e,
+                    );
+                    (Err(()), Vec::new())
+                }
+            };
+
+            let _ = pending_response.send(OutgoingResponse {
+                result,
+                reputation_changes,
+                sent_feedback: None,
+            });
+        }
+    }
+
+    /// Returns the requested chunk along with its proof, or `None` if it's not
+    /// stored locally.
+    fn handle_request(
+        &mut self,
+        payload: &[u8],
+    ) -> Result<Option<ChunkResponse>, HandleRequestError> {
+        let ChunkRequest {
+            chunk_root,
+            chunk_index,
+        } = ChunkRequest::decode(&mut &payload[..])?;
+
+        let key = chunk_root.encode();
+
+        let data_info = match self.storage.data_info(&key)? {
+            Some(data_info) if chunk_index < data_info.chunk_count() => data_info,
+            _ => return Ok(None),
+        };
+
+        let chunk_store = self.storage.chunk_store();
+
+        let chunk = match chunk_store.chunk(&key, chunk_index)? {
+            Some(chunk) => chunk,
+            None => return Ok(None),
+        };
+
+        if !self.chunk_tries.contains(&chunk_root) {
+            let chunk_hashes = match chunk_store.chunk_hashes(&key)? {
+                Some(chunk_hashes) => chunk_hashes,
+                None => return Ok(None),
+            };
+            let chunk_trie = ChunkTrie::new(chunk_hashes)
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("{:?}", e)))?;
+            self.chunk_tries.put(chunk_root, chunk_trie);
+        }
+
+        let proof = self
+            .chunk_tries
+            .get(&chunk_root)
+            .expect("Chunk trie has just been inserted if missing; qed")
+            .generate_proof(chunk_index)
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("{:?}", e)))?;
+
+        Ok(Some(ChunkResponse {
+            data_size: data_info.data_size,
+            chunk,
+            proof,
+        }))
+    }
+}
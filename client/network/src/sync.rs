@@ -0,0 +1,552 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// This file is part of Canyon.
+//
+// Copyright (c) 2021 Canyon Labs.
+//
+// Canyon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// Canyon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Canyon. If not, see <http://www.gnu.org/licenses/>.
+
+//! Worker replicating the transaction data between peers.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+use codec::{Decode, Encode};
+use futures::{
+    future::BoxFuture,
+    stream::{self, BoxStream, Fuse, FuturesUnordered},
+    FutureExt, Stream, StreamExt,
+};
+use lru::LruCache;
+
+use sc_client_api::BlockchainEvents;
+use sc_network::PeerId;
+use sp_api::{ApiExt, ProvideRuntimeApi};
+use sp_core::H256;
+use sp_runtime::{
+    generic::BlockId,
+    traits::{Block as BlockT, Header as HeaderT, NumberFor},
+};
+
+use cc_datastore::{ChunkStorage, SelectedRanges, StoragePolicy};
+use cp_permastore::PermastoreApi;
+
+use crate::fetcher::{fetch_data, FetchError};
+use crate::protocol::DataAnnouncement;
+use crate::{Network, NetworkEvent};
+
+/// Maximum number of the chunk roots remembered as known by each peer.
+const MAX_KNOWN_DATA: usize = 4096;
+
+/// Maximum number of the announced chunk roots remembered before they are seen on chain.
+const MAX_PENDING_ANNOUNCEMENTS: usize = 4096;
+
+/// Maximum number of the missing transaction data waiting for being fetched.
+const MAX_WANTED_DATA: usize = 4096;
+
+/// Maximum number of the transaction data remembered after it failed to be fetched from
+/// every candidate.
+const MAX_STALLED_DATA: usize = 4096;
+
+/// Maximum number of the transaction data being fetched concurrently.
+const MAX_PARALLEL_FETCHES: usize = 4;
+
+pub(crate) mod rep {
+    use sc_network::ReputationChange as Rep;
+
+    /// Reputation change when a peer sends us an announcement which can not be decoded.
+    pub const BAD_ANNOUNCEMENT: Rep = Rep::new(-(1 << 12), "Bad data announcement");
+    /// Reputation change when a peer serves us invalid transaction data.
+    pub const INVALID_DATA: Rep = Rep::new_fatal("Invalid transaction data");
+    /// Reputation change when a peer serves us the transaction data we want.
+    pub const GOOD_DATA: Rep = Rep::new(1 << 7, "Good transaction data");
+}
+
+/// Transaction data stored on chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StoredData {
+    /// Chunk root of the transaction data.
+    pub chunk_root: H256,
+    /// Byte size of the transaction data.
+    pub data_size: u64,
+    /// Weave offset of the first byte of the transaction data.
+    pub weave_base: u64,
+}
+
+/// Transaction data stored on chain but missing locally.
+struct WantedData {
+    data_size: u64,
+    /// Peers to fetch the data from in order, the announcers come first.
+    candidates: VecDeque<PeerId>,
+    /// Whether the data is being fetched.
+    fetching: bool,
+}
+
+type FetchResult = (H256, PeerId, Result<(), FetchError>);
+
+/// Worker replicating the transaction data stored on chain between peers.
+///
+/// The worker is driven by a stream of the transaction data stored on chain, see
+/// [`imported_transaction_data`]. The data available locally is announced to the peers,
+/// the missing data is fetched from the peers announcing it, or from any connected peer
+/// otherwise, unless it's out of the weave ranges selected by the [`StoragePolicy`] or
+/// does not fit in its budget.
+///
+/// The datastore and the runtime API are accessed in a blocking manner, the worker is
+/// expected to be spawned as a blocking task.
+pub struct DataSync<N, S> {
+    network: N,
+    storage: S,
+    network_events: Fuse<BoxStream<'static, NetworkEvent>>,
+    /// Connected peers with the chunk roots known by them.
+    peers: HashMap<PeerId, LruCache<H256, ()>>,
+    /// Announcements of the transaction data which has not been seen on chain yet.
+    pending_announcements: LruCache<H256, (u64, Vec<PeerId>)>,
+    wanted: HashMap<H256, WantedData>,
+    /// Wanted data of which all the candidates have been tried in vain, with the data size.
+    ///
+    /// It's fetched again once it's announced or a new peer is connected.
+    stalled: LruCache<H256, u64>,
+    fetches: FuturesUnordered<BoxFuture<'static, FetchResult>>,
+    policy: StoragePolicy,
+    /// Weave size known from the transaction data seen so far.
+    weave_size: u64,
+    selected_ranges: SelectedRanges,
+}
+
+impl<N, S> DataSync<N, S>
+where
+    N: Network,
+    S: ChunkStorage,
+{
+    /// Creates a new instance of [`DataSync`].
+    pub fn new(network: N, storage: S) -> Self {
+        let network_events = network.event_stream().fuse();
+        let policy = storage.storage_policy();
+        Self {
+            network,
+            storage,
+            network_events,
+            peers: HashMap::new(),
+            pending_announcements: LruCache::new(MAX_PENDING_ANNOUNCEMENTS),
+            wanted: HashMap::new(),
+            stalled: LruCache::new(MAX_STALLED_DATA),
+            fetches: FuturesUnordered::new(),
+            policy,
+            weave_size: 0,
+            selected_ranges: SelectedRanges::All,
+        }
+    }
+
+    /// Runs the worker until either the network or `new_data` is terminated.
+    pub async fn run<D>(mut self, new_data: D)
+    where
+        D: Stream<Item = StoredData> + Unpin,
+    {
+        let mut new_data = new_data.fuse();
+
+        loop {
+            futures::select! {
+                event = self.network_events.next() => match event {
+                    Some(event) => self.on_network_event(event),
+                    None => return,
+                },
+                data = new_data.next() => match data {
+                    Some(data) => self.on_new_data(data),
+                    None => return,
+                },
+                result = self.fetches.select_next_some() => self.on_fetch_finished(result),
+            }
+            self.stall_exhausted();
+            self.start_fetches();
+        }
+    }
+
+    fn is_stored_locally(&self, chunk_root: &H256) -> bool {
+        match self.storage.data_info(&chunk_root.encode()) {
+            Ok(data_info) => data_info.is_some(),
+            Err(e) => {
+                log::error!(
+                    target: "sync::permastore",
+                    "Failed to read the data info of {}: {}",
+                    chunk_root, e,
+                );
+                false
+            }
+        }
+    }
+
+    fn on_network_event(&mut self, event: NetworkEvent) {
+        match event {
+            NetworkEvent::PeerConnected(who) => {
+                self.peers.insert(who, LruCache::new(MAX_KNOWN_DATA));
+                for wanted in self.wanted.values_mut() {
+                    wanted.candidates.push_back(who);
+                }
+                // Give the stalled data another chance with the new peer.
+                while self.wanted.len() < MAX_WANTED_DATA {
+                    match self.stalled.pop_lru() {
+                        Some((chunk_root, data_size)) => {
+                            self.want(chunk_root, data_size, vec![who].into())
+                        }
+                        None => break,
+                    }
+                }
+            }
+            NetworkEvent::PeerDisconnected(who) => {
+                self.peers.remove(&who);
+                for wanted in self.wanted.values_mut() {
+                    wanted.candidates.retain(|candidate| *candidate != who);
+                }
+            }
+            NetworkEvent::Announcement { who, message } => {
+                match DataAnnouncement::decode(&mut message.as_slice()) {
+                    Ok(announcement) => self.on_announcement(who, announcement),
+                    Err(e) => {
+                        log::debug!(
+                            target: "sync::permastore",
+                            "Bad announcement from {}: {}",
+                            who, e,
+                        );
+                        self.network.report_peer(who, rep::BAD_ANNOUNCEMENT);
+                    }
+                }
+            }
+        }
+    }
+
+    fn on_announcement(&mut self, who: PeerId, announcement: DataAnnouncement) {
+        let DataAnnouncement {
+            chunk_root,
+            data_size,
+        } = announcement;
+
+        if let Some(known_data) = self.peers.get_mut(&who) {
+            known_data.put(chunk_root, ());
+        }
+
+        if let Some(wanted) = self.wanted.get_mut(&chunk_root) {
+            if wanted.data_size == data_size {
+                wanted.candidates.retain(|candidate| *candidate != who);
+                wanted.candidates.push_front(who);
+            }
+            return;
+        }
+
+        if self.stalled.peek(&chunk_root) == Some(&data_size) && self.wanted.len() < MAX_WANTED_DATA
+        {
+            self.stalled.pop(&chunk_root);
+            self.want(chunk_root, data_size, vec![who].into());
+            return;
+        }
+
+        if self.is_stored_locally(&chunk_root) {
+            return;
+        }
+
+        // The data may be announced before the block storing it is imported locally.
+        match self.pending_announcements.get_mut(&chunk_root) {
+            Some((size, announcers)) if *size == data_size => {
+                if !announcers.contains(&who) {
+                    announcers.push(who);
+                }
+            }
+            Some(_) => {}
+            None => {
+                self.pending_announcements
+                    .put(chunk_root, (data_size, vec![who]));
+            }
+        }
+    }
+
+    fn on_new_data(&mut self, data: StoredData) {
+        let StoredData {
+            chunk_root,
+            data_size,
+            weave_base,
+        } = data;
+
+        if self.is_stored_locally(&chunk_root) {
+            self.announce(chunk_root, data_size);
+            return;
+        }
+
+        if weave_base + data_size > self.weave_size {
+            self.weave_size = weave_base + data_size;
+            if !self.policy.is_unlimited() {
+                self.selected_ranges = self.policy.selected_ranges(self.weave_size);
+            }
+        }
+
+        if !self.selected_ranges.contains(weave_base) {
+            log::trace!(
+                target: "sync::permastore",
+                "Transaction data {} is out of the selected weave ranges",
+                chunk_root,
+            );
+            self.pending_announcements.pop(&chunk_root);
+            self.stalled.pop(&chunk_root);
+            return;
+        }
+
+        if self.wanted.contains_key(&chunk_root) {
+            return;
+        }
+
+        self.stalled.pop(&chunk_root);
+
+        if self.wanted.len() >= MAX_WANTED_DATA {
+            log::debug!(
+                target: "sync::permastore",
+                "Too many transaction data to fetch, deferring {}",
+                chunk_root,
+            );
+            self.stalled.put(chunk_root, data_size);
+            return;
+        }
+
+        let mut candidates = match self.pending_announcements.pop(&chunk_root) {
+            Some((size, announcers)) if size == data_size => announcers.into_iter().collect(),
+            _ => VecDeque::new(),
+        };
+        for who in self.peers.keys() {
+            if !candidates.contains(who) {
+                candidates.push_back(*who);
+            }
+        }
+
+        self.want(chunk_root, data_size, candidates);
+    }
+
+    fn want(&mut self, chunk_root: H256, data_size: u64, candidates: VecDeque<PeerId>) {
+        self.wanted.insert(
+            chunk_root,
+            WantedData {
+                data_size,
+                candidates,
+                fetching: false,
+            },
+        );
+    }
+
+    /// Moves the wanted data having no more candidates to fetch from to the stalled data,
+    /// so that it does not occupy the wanted data forever.
+    fn stall_exhausted(&mut self) {
+        let exhausted = self
+            .wanted
+            .iter()
+            .filter(|(_, wanted)| !wanted.fetching && wanted.candidates.is_empty())
+            .map(|(chunk_root, _)| *chunk_root)
+            .collect::<Vec<_>>();
+
+        for chunk_root in exhausted {
+            if let Some(wanted) = self.wanted.remove(&chunk_root) {
+                log::debug!(
+                    target: "sync::permastore",
+                    "No peer to fetch transaction data {} from, stalled",
+                    chunk_root,
+                );
+                self.stalled.put(chunk_root, wanted.data_size);
+            }
+        }
+    }
+
+    fn on_fetch_finished(&mut self, (chunk_root, who, result): FetchResult) {
+        match result {
+            Ok(()) => {
+                log::debug!(
+                    target: "sync::permastore",
+                    "Fetched transaction data {} from {}",
+                    chunk_root, who,
+                );
+                self.network.report_peer(who, rep::GOOD_DATA);
+                if let Some(known_data) = self.peers.get_mut(&who) {
+                    known_data.put(chunk_root, ());
+                }
+                if let Some(wanted) = self.wanted.remove(&chunk_root) {
+                    self.announce(chunk_root, wanted.data_size);
+                }
+            }
+            Err(e) => {
+                log::debug!(
+                    target: "sync::permastore",
+                    "Failed to fetch transaction data {} from {}: {}",
+                    chunk_root, who, e,
+                );
+                if e.is_misbehavior() {
+                    self.network.report_peer(who, rep::INVALID_DATA);
+                }
+                if let Some(wanted) = self.wanted.get_mut(&chunk_root) {
+                    wanted.fetching = false;
+                }
+            }
+        }
+    }
+
+    /// Announces the transaction data to the peers which do not know it yet.
+    fn announce(&mut self, chunk_root: H256, data_size: u64) {
+        let message = DataAnnouncement {
+            chunk_root,
+            data_size,
+        }
+        .encode();
+
+        for (who, known_data) in self.peers.iter_mut() {
+            if known_data.put(chunk_root, ()).is_none() {
+                self.network.announce(*who, message.clone());
+            }
+        }
+    }
+
+    /// Starts fetching the wanted data from the next candidate, as long as the number
+    /// of ongoing fetches is under the limit.
+    ///
+    /// The wanted data which does not fit in the storage budget, including the data
+    /// being fetched, is given up.
+    fn start_fetches(&mut self) {
+        let mut reserved_size = self.storage.chunk_store().stored_size()
+            + self
+                .wanted
+                .values()
+                .filter(|wanted| wanted.fetching)
+                .map(|wanted| wanted.data_size)
+                .sum::<u64>();
+        let mut no_room = Vec::new();
+
+        for (chunk_root, wanted) in self.wanted.iter_mut() {
+            if self.fetches.len() >= MAX_PARALLEL_FETCHES {
+                break;
+            }
+
+            if wanted.fetching {
+                continue;
+            }
+
+            if !self.policy.has_room(reserved_size, wanted.data_size) {
+                no_room.push(*chunk_root);
+                continue;
+            }
+
+            if let Some(who) = wanted.candidates.pop_front() {
+                wanted.fetching = true;
+                reserved_size += wanted.data_size;
+
+                let network = self.network.clone();
+                let chunk_store = self.storage.chunk_store().clone();
+                let chunk_root = *chunk_root;
+                let data_size = wanted.data_size;
+
+                self.fetches.push(
+                    async move {
+                        let result =
+                            fetch_data(&network, &chunk_store, who, chunk_root, data_size).await;
+                        (chunk_root, who, result)
+                    }
+                    .boxed(),
+                );
+            }
+        }
+
+        for chunk_root in no_room {
+            log::debug!(
+                target: "sync::permastore",
+                "No room for transaction data {} within the storage budget",
+                chunk_root,
+            );
+            self.wanted.remove(&chunk_root);
+        }
+    }
+}
+
+/// Returns the transaction data stored in block `block_number`, read from the state
+/// of block `at`.
+///
+/// The transaction data is indexed by the block number on chain, `at` can be any block
+/// descending from block `block_number`, e.g., the best finalized block, whose state
+/// is kept unlike the pruned state of the historical blocks.
+pub(crate) fn transaction_data_in_block<B, C>(
+    client: &C,
+    at: BlockId<B>,
+    block_number: NumberFor<B>,
+) -> Result<Vec<StoredData>, String>
+where
+    B: BlockT<Hash = H256>,
+    C: ProvideRuntimeApi<B>,
+    C::Api: PermastoreApi<B, NumberFor<B>, u32, B::Hash>,
+{
+    let api = client.runtime_api();
+
+    // The weave offsets are only available since version 2 of `PermastoreApi`.
+    if !api
+        .has_api_with::<dyn PermastoreApi<B, NumberFor<B>, u32, B::Hash>, _>(&at, |version| {
+            version >= 2
+        })
+        .map_err(|e| e.to_string())?
+    {
+        return Err(format!(
+            "PermastoreApi at block {} does not provide the weave offsets",
+            at
+        ));
+    }
+
+    let weave_offsets = api
+        .weave_offsets(&at, block_number)
+        .map_err(|e| e.to_string())?;
+
+    let mut data = Vec::with_capacity(weave_offsets.len());
+    for (extrinsic_index, weave_base) in weave_offsets {
+        if let Some(chunk_root) = api
+            .chunk_root(&at, block_number, extrinsic_index)
+            .map_err(|e| e.to_string())?
+        {
+            let data_size = api
+                .data_size(&at, block_number, extrinsic_index)
+                .map_err(|e| e.to_string())? as u64;
+            data.push(StoredData {
+                chunk_root,
+                data_size,
+                weave_base,
+            });
+        }
+    }
+
+    Ok(data)
+}
+
+/// Returns a stream of the transaction data stored in the newly imported blocks, which
+/// drives [`DataSync`].
+pub fn imported_transaction_data<B, C>(client: Arc<C>) -> impl Stream<Item = StoredData>
+where
+    B: BlockT<Hash = H256>,
+    C: BlockchainEvents<B> + ProvideRuntimeApi<B> + Send + Sync + 'static,
+    C::Api: PermastoreApi<B, NumberFor<B>, u32, B::Hash>,
+{
+    client
+        .import_notification_stream()
+        .flat_map(move |notification| {
+            let data = transaction_data_in_block(
+                &*client,
+                BlockId::Hash(notification.hash),
+                *notification.header.number(),
+            )
+            .unwrap_or_else(|e| {
+                log::error!(
+                    target: "sync::permastore",
+                    "Failed to read the transaction data in block {}: {}",
+                    notification.hash, e,
+                );
+                Vec::new()
+            });
+            stream::iter(data)
+        })
+}
@@ -0,0 +1,845 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// This file is part of Canyon.
+//
+// Copyright (c) 2021 Canyon Labs.
+//
+// Canyon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// Canyon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Canyon. If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use codec::Encode;
+use futures::{
+    channel::{mpsc, oneshot},
+    executor::ThreadPool,
+    future::BoxFuture,
+    stream::BoxStream,
+    FutureExt, SinkExt, StreamExt,
+};
+
+use sc_client_db::offchain::LocalStorage;
+use sc_network::{
+    config::{IncomingRequest, OutgoingResponse},
+    PeerId, ReputationChange, RequestFailure,
+};
+use sp_core::H256;
+use sp_runtime::offchain::OffchainStorage;
+
+use cc_datastore::{
+    test_data, ChunkStorage, DataInfo, PermanentStorage, RangeSelection, StoragePolicy,
+    WEAVE_RANGE_SIZE,
+};
+use cp_permastore::{chunk_root, PermaStorage, CHUNK_SIZE};
+
+use crate::{
+    fetch_chunk, Backfill, BackfillProgress, ChainDataSource, ChunkRequestHandler, DataSync,
+    FetchError, Network, NetworkEvent, Pruner, SharedBackfillProgress, StoredData,
+};
+
+/// Finalized chain storing the transaction data in some blocks.
+#[derive(Clone)]
+struct TestChain {
+    finalized_number: u64,
+    weave_size: u64,
+    transaction_data: HashMap<u64, Vec<StoredData>>,
+}
+
+impl ChainDataSource for TestChain {
+    fn finalized_number(&self) -> u64 {
+        self.finalized_number
+    }
+
+    fn weave_size(&self) -> Result<u64, String> {
+        Ok(self.weave_size)
+    }
+
+    fn transaction_data(&self, block_number: u64) -> Result<Vec<StoredData>, String> {
+        Ok(self
+            .transaction_data
+            .get(&block_number)
+            .cloned()
+            .unwrap_or_default())
+    }
+}
+
+/// Endpoints of a node in the [`TestNet`].
+struct TestPeer {
+    /// Subscribers of the network events.
+    events: Vec<mpsc::UnboundedSender<NetworkEvent>>,
+    requests: mpsc::Sender<IncomingRequest>,
+}
+
+impl TestPeer {
+    fn send_event(&self, event: NetworkEvent) {
+        for events in &self.events {
+            let _ = events.unbounded_send(event.clone());
+        }
+    }
+}
+
+/// In-process network routing the announcements and requests between the nodes.
+#[derive(Clone)]
+struct TestNetwork {
+    local_peer_id: PeerId,
+    peers: Arc<Mutex<HashMap<PeerId, TestPeer>>>,
+    reports: Arc<Mutex<Vec<(PeerId, ReputationChange)>>>,
+}
+
+impl Network for TestNetwork {
+    fn event_stream(&self) -> BoxStream<'static, NetworkEvent> {
+        let (events_tx, events_rx) = mpsc::unbounded();
+        self.peers
+            .lock()
+            .unwrap()
+            .get_mut(&self.local_peer_id)
+            .expect("Local peer is registered in the network")
+            .events
+            .push(events_tx);
+        events_rx.boxed()
+    }
+
+    fn announce(&self, who: PeerId, message: Vec<u8>) {
+        if let Some(peer) = self.peers.lock().unwrap().get(&who) {
+            peer.send_event(NetworkEvent::Announcement {
+                who: self.local_peer_id,
+                message,
+            });
+        }
+    }
+
+    fn request(
+        &self,
+        who: PeerId,
+        request: Vec<u8>,
+    ) -> BoxFuture<'static, Result<Vec<u8>, RequestFailure>> {
+        let requests = self
+            .peers
+            .lock()
+            .unwrap()
+            .get(&who)
+            .map(|peer| peer.requests.clone());
+        let local_peer_id = self.local_peer_id;
+
+        async move {
+            let mut requests = requests.ok_or(RequestFailure::NotConnected)?;
+            let (pending_response, response) = oneshot::channel();
+            requests
+                .send(IncomingRequest {
+                    peer: local_peer_id,
+                    payload: request,
+                    pending_response,
+                })
+                .await
+                .map_err(|_| RequestFailure::Refused)?;
+            let OutgoingResponse { result, .. } =
+                response.await.map_err(|_| RequestFailure::Refused)?;
+            result.map_err(|()| RequestFailure::Refused)
+        }
+        .boxed()
+    }
+
+    fn report_peer(&self, who: PeerId, cost_benefit: ReputationChange) {
+        self.reports.lock().unwrap().push((who, cost_benefit));
+    }
+}
+
+struct TestNode {
+    network: TestNetwork,
+    storage: PermanentStorage<()>,
+    new_data: mpsc::UnboundedSender<StoredData>,
+}
+
+struct TestNet {
+    nodes: Vec<TestNode>,
+    peers: Arc<Mutex<HashMap<PeerId, TestPeer>>>,
+    /// Executor running the nodes.
+    pool: ThreadPool,
+}
+
+impl TestNet {
+    /// Creates a network of `n` nodes running the chunk request handler and the data sync.
+    fn new(n: usize) -> Self {
+        Self::with_policy(n, StoragePolicy::default())
+    }
+
+    /// Creates a network of `n` nodes of which the storage is subject to `policy`.
+    fn with_policy(n: usize, policy: StoragePolicy) -> Self {
+        let pool = ThreadPool::new().unwrap();
+        let peers = Arc::new(Mutex::new(HashMap::new()));
+
+        let nodes = (0..n)
+            .map(|_| {
+                let storage = PermanentStorage::new_test(Arc::new(())).with_storage_policy(policy);
+                let (handler, mut protocol_config) = ChunkRequestHandler::new(storage.clone());
+                pool.spawn_ok(handler.run());
+
+                let local_peer_id = PeerId::random();
+                peers.lock().unwrap().insert(
+                    local_peer_id,
+                    TestPeer {
+                        events: Vec::new(),
+                        requests: protocol_config.inbound_queue.take().unwrap(),
+                    },
+                );
+
+                let network = TestNetwork {
+                    local_peer_id,
+                    peers: peers.clone(),
+                    reports: Arc::new(Mutex::new(Vec::new())),
+                };
+
+                let (new_data, new_data_rx) = mpsc::unbounded();
+                pool.spawn_ok(DataSync::new(network.clone(), storage.clone()).run(new_data_rx));
+
+                TestNode {
+                    network,
+                    storage,
+                    new_data,
+                }
+            })
+            .collect();
+
+        Self { nodes, peers, pool }
+    }
+
+    /// Connects node `a` and node `b` with each other.
+    fn connect(&self, a: usize, b: usize) {
+        let peers = self.peers.lock().unwrap();
+        let a = self.nodes[a].network.local_peer_id;
+        let b = self.nodes[b].network.local_peer_id;
+        peers[&a].send_event(NetworkEvent::PeerConnected(b));
+        peers[&b].send_event(NetworkEvent::PeerConnected(a));
+    }
+
+    /// Creates the backfill of node `index` walking through `chain`.
+    fn backfill(
+        &self,
+        index: usize,
+        chain: TestChain,
+    ) -> (
+        Backfill<TestChain, TestNetwork, PermanentStorage<()>>,
+        SharedBackfillProgress,
+    ) {
+        let node = &self.nodes[index];
+        let progress = SharedBackfillProgress::default();
+        let backfill = Backfill::new(
+            chain,
+            node.network.clone(),
+            node.storage.clone(),
+            progress.clone(),
+            None,
+        );
+        (backfill, progress)
+    }
+
+    /// Notifies all the nodes that the transaction data is stored on chain.
+    fn import_data(&self, data: StoredData) {
+        for node in &self.nodes {
+            node.new_data.unbounded_send(data).unwrap();
+        }
+    }
+
+    /// Waits until `condition` holds, panics on timeout.
+    fn wait_until(&self, mut condition: impl FnMut(&Self) -> bool) {
+        let started = Instant::now();
+        while !condition(self) {
+            assert!(
+                started.elapsed() < Duration::from_secs(10),
+                "Timed out waiting for the network"
+            );
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+fn stored_data(chunk_root: H256, data_size: u64, weave_base: u64) -> StoredData {
+    StoredData {
+        chunk_root,
+        data_size,
+        weave_base,
+    }
+}
+
+fn read_data(node: &TestNode, chunk_root: H256) -> Option<Vec<u8>> {
+    node.storage
+        .chunk_store()
+        .read_data(&chunk_root.encode())
+        .unwrap()
+}
+
+#[test]
+fn transaction_data_should_be_gossiped_across_the_network() {
+    let net = TestNet::new(3);
+    // 0 <-> 1 <-> 2, node 2 is only able to get the data via node 1.
+    net.connect(0, 1);
+    net.connect(1, 2);
+
+    let data = test_data(2, 100);
+    let chunk_root = chunk_root(&data);
+    net.nodes[0]
+        .storage
+        .chunk_store()
+        .write_data(&chunk_root.encode(), data.as_slice())
+        .unwrap();
+
+    net.import_data(stored_data(chunk_root, data.len() as u64, 0));
+
+    net.wait_until(|net| {
+        net.nodes
+            .iter()
+            .all(|node| read_data(node, chunk_root).is_some())
+    });
+
+    for node in &net.nodes {
+        assert_eq!(read_data(node, chunk_root), Some(data.clone()));
+    }
+}
+
+#[test]
+fn pending_upload_with_wrong_data_size_should_not_block_fetching() {
+    let net = TestNet::new(2);
+    net.connect(0, 1);
+
+    let data = test_data(2, 100);
+    let chunk_root = chunk_root(&data);
+    net.nodes[0]
+        .storage
+        .chunk_store()
+        .write_data(&chunk_root.encode(), data.as_slice())
+        .unwrap();
+
+    // A genuine chunk has been uploaded to node 1 with a wrong data size.
+    net.nodes[1]
+        .storage
+        .chunk_store()
+        .put_uploaded_chunk(
+            &chunk_root.encode(),
+            data.len() as u64 + CHUNK_SIZE as u64,
+            0,
+            &data[..CHUNK_SIZE as usize],
+            0,
+        )
+        .unwrap();
+
+    net.import_data(stored_data(chunk_root, data.len() as u64, 0));
+
+    net.wait_until(|net| read_data(&net.nodes[1], chunk_root).is_some());
+
+    assert_eq!(read_data(&net.nodes[1], chunk_root), Some(data));
+}
+
+#[test]
+fn invalid_transaction_data_should_be_rejected() {
+    let net = TestNet::new(2);
+    net.connect(0, 1);
+
+    let data = test_data(2, 100);
+    let chunk_root = chunk_root(&data);
+
+    // Node 0 serves some other data of the same size under `chunk_root`.
+    let mut corrupted = data.clone();
+    corrupted[0] ^= 1;
+    net.nodes[0]
+        .storage
+        .chunk_store()
+        .write_data(&chunk_root.encode(), corrupted.as_slice())
+        .unwrap();
+
+    net.import_data(stored_data(chunk_root, data.len() as u64, 0));
+
+    let malicious = net.nodes[0].network.local_peer_id;
+    net.wait_until(|net| {
+        net.nodes[1]
+            .network
+            .reports
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|(who, rep)| *who == malicious && rep.value == i32::MIN)
+    });
+
+    assert_eq!(read_data(&net.nodes[1], chunk_root), None);
+}
+
+#[test]
+fn unavailable_transaction_data_should_not_block_fetching() {
+    let net = TestNet::new(2);
+
+    // Nobody has the data, which used to occupy the wanted data of node 1 forever.
+    for i in 0..4096 {
+        net.nodes[1]
+            .new_data
+            .unbounded_send(stored_data(H256::from_low_u64_be(i + 1), 1, 0))
+            .unwrap();
+    }
+
+    let data = test_data(2, 100);
+    let chunk_root = chunk_root(&data);
+    net.nodes[0]
+        .storage
+        .chunk_store()
+        .write_data(&chunk_root.encode(), data.as_slice())
+        .unwrap();
+
+    net.import_data(stored_data(chunk_root, data.len() as u64, 0));
+    net.connect(0, 1);
+
+    net.wait_until(|net| read_data(&net.nodes[1], chunk_root).is_some());
+
+    assert_eq!(read_data(&net.nodes[1], chunk_root), Some(data));
+}
+
+#[test]
+fn fetch_chunk_should_verify_chunk_against_chunk_root() {
+    let net = TestNet::new(2);
+
+    let data = test_data(2, 100);
+    let chunk_root = chunk_root(&data);
+    net.nodes[0]
+        .storage
+        .chunk_store()
+        .write_data(&chunk_root.encode(), data.as_slice())
+        .unwrap();
+
+    let network = &net.nodes[1].network;
+    let who = net.nodes[0].network.local_peer_id;
+    let data_size = data.len() as u64;
+    let chunk_size = CHUNK_SIZE as usize;
+
+    let chunk =
+        futures::executor::block_on(fetch_chunk(network, who, chunk_root, data_size, 2)).unwrap();
+    assert_eq!(chunk, data[chunk_size * 2..].to_vec());
+
+    assert!(matches!(
+        futures::executor::block_on(fetch_chunk(network, who, chunk_root, data_size, 3)),
+        Err(FetchError::NotFound)
+    ));
+    assert!(matches!(
+        futures::executor::block_on(fetch_chunk(
+            network,
+            who,
+            H256::repeat_byte(1),
+            data_size,
+            0
+        )),
+        Err(FetchError::NotFound)
+    ));
+    assert!(matches!(
+        futures::executor::block_on(fetch_chunk(network, who, chunk_root, data_size + 1, 0)),
+        Err(FetchError::InvalidChunk(_))
+    ));
+}
+
+#[test]
+fn backfill_should_fetch_historical_transaction_data() {
+    let net = TestNet::new(2);
+
+    let data = test_data(2, 100);
+    let chunk_root = chunk_root(&data);
+    let small_data = b"small data".to_vec();
+    let small_chunk_root = cp_permastore::chunk_root(&small_data);
+    for (chunk_root, data) in &[(chunk_root, &data), (small_chunk_root, &small_data)] {
+        net.nodes[0]
+            .storage
+            .chunk_store()
+            .write_data(&chunk_root.encode(), data.as_slice())
+            .unwrap();
+    }
+    let unavailable_chunk_root = H256::repeat_byte(1);
+
+    let data_size = data.len() as u64;
+    let chain = TestChain {
+        finalized_number: 5,
+        weave_size: data_size + 100 + small_data.len() as u64,
+        transaction_data: vec![
+            (1, vec![stored_data(chunk_root, data_size, 0)]),
+            (
+                3,
+                vec![
+                    stored_data(unavailable_chunk_root, 100, data_size),
+                    stored_data(small_chunk_root, small_data.len() as u64, data_size + 100),
+                ],
+            ),
+        ]
+        .into_iter()
+        .collect(),
+    };
+
+    let (backfill, progress) = net.backfill(1, chain);
+    net.connect(0, 1);
+    net.pool.spawn_ok(backfill.run());
+
+    net.wait_until(|_| progress.get().next_block > 5);
+
+    assert_eq!(
+        progress.get(),
+        BackfillProgress {
+            next_block: 6,
+            target_block: 5,
+            fetched_data: 2,
+            fetched_bytes: (data.len() + small_data.len()) as u64,
+            pending_data: 1,
+        }
+    );
+    assert_eq!(read_data(&net.nodes[1], chunk_root), Some(data));
+    assert_eq!(read_data(&net.nodes[1], small_chunk_root), Some(small_data));
+}
+
+#[test]
+fn backfill_should_wait_for_peers() {
+    let net = TestNet::new(2);
+
+    let data = test_data(2, 100);
+    let chunk_root = chunk_root(&data);
+    net.nodes[0]
+        .storage
+        .chunk_store()
+        .write_data(&chunk_root.encode(), data.as_slice())
+        .unwrap();
+
+    let data_size = data.len() as u64;
+    let chain = TestChain {
+        finalized_number: 5,
+        weave_size: data_size,
+        transaction_data: vec![(1, vec![stored_data(chunk_root, data_size, 0)])]
+            .into_iter()
+            .collect(),
+    };
+
+    let (backfill, progress) = net.backfill(1, chain);
+    net.pool.spawn_ok(backfill.run());
+
+    // Nothing is scanned without any peer to fetch the data from.
+    std::thread::sleep(Duration::from_millis(100));
+    assert_eq!(progress.get().next_block, 0);
+    assert_eq!(progress.get().pending_data, 0);
+
+    net.connect(0, 1);
+    net.wait_until(|_| progress.get().next_block > 5);
+
+    assert_eq!(progress.get().fetched_data, 1);
+    assert_eq!(progress.get().pending_data, 0);
+    assert_eq!(read_data(&net.nodes[1], chunk_root), Some(data));
+}
+
+#[test]
+fn backfill_should_resume_from_persisted_progress() {
+    let net = TestNet::new(2);
+
+    let data = test_data(2, 100);
+    let chunk_root = chunk_root(&data);
+    net.nodes[0]
+        .storage
+        .chunk_store()
+        .write_data(&chunk_root.encode(), data.as_slice())
+        .unwrap();
+    let unavailable_chunk_root = H256::repeat_byte(1);
+
+    let data_size = data.len() as u64;
+    let chain = TestChain {
+        finalized_number: 5,
+        weave_size: data_size + 100,
+        transaction_data: vec![
+            (1, vec![stored_data(chunk_root, data_size, 0)]),
+            (3, vec![stored_data(unavailable_chunk_root, 100, data_size)]),
+        ]
+        .into_iter()
+        .collect(),
+    };
+
+    let (backfill, progress) = net.backfill(1, chain.clone());
+    net.connect(0, 1);
+    net.pool.spawn_ok(backfill.run());
+
+    net.wait_until(|_| progress.get().next_block > 5);
+    assert_eq!(progress.get().fetched_data, 1);
+
+    // Block 1 is not scanned again after restart, unlike block 3 of which the data is
+    // still pending.
+    net.nodes[1]
+        .storage
+        .chunk_store()
+        .remove(&chunk_root.encode())
+        .unwrap();
+    let (backfill, progress) = net.backfill(1, chain);
+    net.connect(0, 1);
+    net.pool.spawn_ok(backfill.run());
+
+    net.wait_until(|_| progress.get().next_block > 5);
+
+    assert_eq!(
+        progress.get(),
+        BackfillProgress {
+            next_block: 6,
+            target_block: 5,
+            fetched_data: 0,
+            fetched_bytes: 0,
+            pending_data: 1,
+        }
+    );
+    assert_eq!(read_data(&net.nodes[1], chunk_root), None);
+}
+
+/// Policy storing only the latest weave range.
+fn latest_range_policy() -> StoragePolicy {
+    StoragePolicy {
+        max_size: Some(WEAVE_RANGE_SIZE),
+        selection: RangeSelection::NewestFirst,
+    }
+}
+
+#[test]
+fn backfill_should_skip_transaction_data_out_of_storage_policy() {
+    let net = TestNet::with_policy(2, latest_range_policy());
+
+    let old_data = test_data(2, 100);
+    let old_chunk_root = chunk_root(&old_data);
+    let new_data = b"new data".to_vec();
+    let new_chunk_root = chunk_root(&new_data);
+    for (chunk_root, data) in &[(old_chunk_root, &old_data), (new_chunk_root, &new_data)] {
+        net.nodes[0]
+            .storage
+            .chunk_store()
+            .write_data(&chunk_root.encode(), data.as_slice())
+            .unwrap();
+    }
+
+    // The data sizes are faked, only the latest one of the 3 ranges is selected.
+    let chain = TestChain {
+        finalized_number: 2,
+        weave_size: 3 * WEAVE_RANGE_SIZE,
+        transaction_data: vec![
+            (
+                1,
+                vec![stored_data(old_chunk_root, old_data.len() as u64, 0)],
+            ),
+            (
+                2,
+                vec![stored_data(
+                    new_chunk_root,
+                    new_data.len() as u64,
+                    2 * WEAVE_RANGE_SIZE,
+                )],
+            ),
+        ]
+        .into_iter()
+        .collect(),
+    };
+
+    let (backfill, progress) = net.backfill(1, chain);
+    net.connect(0, 1);
+    net.pool.spawn_ok(backfill.run());
+
+    net.wait_until(|_| progress.get().next_block > 2);
+
+    assert_eq!(
+        progress.get(),
+        BackfillProgress {
+            next_block: 3,
+            target_block: 2,
+            fetched_data: 1,
+            fetched_bytes: new_data.len() as u64,
+            pending_data: 0,
+        }
+    );
+    assert_eq!(read_data(&net.nodes[1], old_chunk_root), None);
+    assert_eq!(read_data(&net.nodes[1], new_chunk_root), Some(new_data));
+}
+
+#[test]
+fn backfill_should_not_exceed_storage_budget() {
+    let net = TestNet::with_policy(2, latest_range_policy());
+
+    let data = test_data(2, 100);
+    let chunk_root = chunk_root(&data);
+    net.nodes[0]
+        .storage
+        .chunk_store()
+        .write_data(&chunk_root.encode(), data.as_slice())
+        .unwrap();
+
+    // The data stored previously is faked to leave less room than the data to fetch.
+    net.nodes[1]
+        .storage
+        .chunk_store()
+        .put_data_info(
+            &H256::repeat_byte(1).encode(),
+            DataInfo {
+                data_size: WEAVE_RANGE_SIZE - data.len() as u64 + 1,
+            },
+        )
+        .unwrap();
+
+    let chain = TestChain {
+        finalized_number: 1,
+        weave_size: 2 * WEAVE_RANGE_SIZE,
+        transaction_data: vec![(
+            1,
+            vec![stored_data(
+                chunk_root,
+                data.len() as u64,
+                WEAVE_RANGE_SIZE + 100,
+            )],
+        )]
+        .into_iter()
+        .collect(),
+    };
+
+    let (backfill, progress) = net.backfill(1, chain);
+    net.connect(0, 1);
+    net.pool.spawn_ok(backfill.run());
+
+    net.wait_until(|_| progress.get().next_block > 1);
+
+    assert_eq!(progress.get().fetched_data, 0);
+    assert_eq!(progress.get().pending_data, 1);
+    assert_eq!(read_data(&net.nodes[1], chunk_root), None);
+}
+
+#[test]
+fn pruner_should_remove_transaction_data_out_of_storage_policy() {
+    let storage =
+        PermanentStorage::new_test(Arc::new(())).with_storage_policy(latest_range_policy());
+
+    let data = (0..4u8).map(|i| vec![i; 100]).collect::<Vec<_>>();
+    let chunk_roots = data.iter().map(|data| chunk_root(data)).collect::<Vec<_>>();
+    for (chunk_root, data) in chunk_roots.iter().zip(&data) {
+        storage
+            .chunk_store()
+            .write_data(&chunk_root.encode(), data.as_slice())
+            .unwrap();
+    }
+
+    // The data sizes are faked, data 0 is stored again in the latest range.
+    let chain = TestChain {
+        finalized_number: 3,
+        weave_size: 3 * WEAVE_RANGE_SIZE,
+        transaction_data: vec![
+            (1, vec![stored_data(chunk_roots[0], 100, 0)]),
+            (2, vec![stored_data(chunk_roots[1], 100, WEAVE_RANGE_SIZE)]),
+            (
+                3,
+                vec![
+                    stored_data(chunk_roots[2], 100, 2 * WEAVE_RANGE_SIZE),
+                    stored_data(chunk_roots[0], 100, 2 * WEAVE_RANGE_SIZE + 100),
+                ],
+            ),
+        ]
+        .into_iter()
+        .collect(),
+    };
+
+    let mut pruner = Pruner::new(chain, storage.clone());
+    assert_eq!(pruner.prune(), Ok(1));
+    assert_eq!(pruner.prune(), Ok(0));
+
+    let is_stored = |chunk_root: &H256| {
+        storage
+            .chunk_store()
+            .data_info(&chunk_root.encode())
+            .unwrap()
+            .is_some()
+    };
+    assert!(is_stored(&chunk_roots[0]));
+    assert!(!is_stored(&chunk_roots[1]));
+    assert!(is_stored(&chunk_roots[2]));
+    // Data 3 is not stored on chain, which is left untouched.
+    assert!(is_stored(&chunk_roots[3]));
+}
+
+#[test]
+fn pruner_should_move_or_remove_legacy_transaction_data() {
+    let data = (0..2u8).map(|i| vec![i; 100]).collect::<Vec<_>>();
+    let chunk_roots = data.iter().map(|data| chunk_root(data)).collect::<Vec<_>>();
+
+    let mut legacy_storage = LocalStorage::new_test();
+    for (chunk_root, data) in chunk_roots.iter().zip(&data) {
+        legacy_storage.set(sp_offchain::STORAGE_PREFIX, &chunk_root.encode(), data);
+    }
+
+    let storage = PermanentStorage::new_test(Arc::new(()))
+        .with_legacy_storage(legacy_storage.clone())
+        .with_storage_policy(latest_range_policy());
+    assert_eq!(storage.chunk_store().stored_size(), 0);
+
+    let chain = TestChain {
+        finalized_number: 2,
+        weave_size: 2 * WEAVE_RANGE_SIZE,
+        transaction_data: vec![
+            (1, vec![stored_data(chunk_roots[0], 100, 0)]),
+            (2, vec![stored_data(chunk_roots[1], 100, WEAVE_RANGE_SIZE)]),
+        ]
+        .into_iter()
+        .collect(),
+    };
+
+    let mut pruner = Pruner::new(chain, storage.clone());
+    assert_eq!(pruner.prune(), Ok(1));
+
+    // The unselected legacy data is removed, the selected one is counted.
+    assert!(!storage.exists(&chunk_roots[0].encode()));
+    assert_eq!(
+        legacy_storage.get(sp_offchain::STORAGE_PREFIX, &chunk_roots[1].encode()),
+        None
+    );
+    assert_eq!(
+        storage
+            .chunk_store()
+            .read_data(&chunk_roots[1].encode())
+            .unwrap(),
+        Some(data[1].clone())
+    );
+    assert_eq!(storage.chunk_store().stored_size(), 100);
+}
+
+#[test]
+fn backfill_should_rescan_blocks_of_lost_transaction_data() {
+    let net = TestNet::new(2);
+
+    let data = test_data(2, 100);
+    let chunk_root = chunk_root(&data);
+    net.nodes[0]
+        .storage
+        .chunk_store()
+        .write_data(&chunk_root.encode(), data.as_slice())
+        .unwrap();
+
+    let chain = TestChain {
+        finalized_number: 3,
+        weave_size: data.len() as u64,
+        transaction_data: vec![(2, vec![stored_data(chunk_root, data.len() as u64, 0)])]
+            .into_iter()
+            .collect(),
+    };
+
+    let (rescan, rescan_rx) = mpsc::unbounded();
+    let (backfill, progress) = net.backfill(1, chain);
+    net.connect(0, 1);
+    net.pool
+        .spawn_ok(backfill.with_rescan(rescan_rx.boxed()).run());
+
+    net.wait_until(|_| progress.get().fetched_data == 1);
+
+    // The data is lost locally, e.g., quarantined as corrupted.
+    net.nodes[1]
+        .storage
+        .chunk_store()
+        .remove(&chunk_root.encode())
+        .unwrap();
+    rescan.unbounded_send(2).unwrap();
+
+    net.wait_until(|_| progress.get().fetched_data == 2);
+    assert_eq!(read_data(&net.nodes[1], chunk_root), Some(data));
+}
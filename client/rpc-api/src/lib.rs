@@ -19,3 +19,4 @@
 //! RPC api for Canyon RPC.
 
 pub mod permastore;
+pub mod poa;
@@ -57,6 +57,18 @@ pub enum Error {
     DataSizeTooLarge,
     #[error("invalid proof: ")]
     InvalidProof,
+    #[error("chunk index is out of range. {}", _0)]
+    InvalidChunkIndex(InvalidCount),
+    #[error("chunk size is invalid, expected: {expected}, got: {provided}")]
+    InvalidChunkSize { expected: u32, provided: u32 },
+    #[error("too many pending uploads. {}", _0)]
+    TooManyPendingUploads(InvalidCount),
+    #[error("pending uploads are too large, provided: {provided}, max: {max}")]
+    PendingUploadsTooLarge { provided: u64, max: u64 },
+    #[error("datastore: {0}")]
+    Datastore(#[from] std::io::Error),
+    #[error("client error: {0}")]
+    Client(Box<dyn std::error::Error + Send + Sync>),
     #[error("authoring api: {0}")]
     AuthoringApiError(#[from] sc_rpc_api::author::error::Error),
     /// Call to an unsafe RPC was denied.
@@ -109,6 +121,42 @@ impl From<Error> for rpc::Error {
                 message: e.to_string(),
                 data: None,
             },
+            Error::InvalidChunkIndex(invalid_count) => rpc::Error {
+                code: rpc::ErrorCode::ServerError(BASE_ERROR + 8),
+                message: format!("chunk index is out of range. {}", invalid_count),
+                data: None,
+            },
+            Error::InvalidChunkSize { expected, provided } => rpc::Error {
+                code: rpc::ErrorCode::ServerError(BASE_ERROR + 9),
+                message: format!(
+                    "chunk size is invalid, expected: {}, got: {}",
+                    expected, provided
+                ),
+                data: None,
+            },
+            Error::Datastore(e) => rpc::Error {
+                code: rpc::ErrorCode::ServerError(BASE_ERROR + 10),
+                message: e.to_string(),
+                data: None,
+            },
+            Error::Client(e) => rpc::Error {
+                code: rpc::ErrorCode::ServerError(BASE_ERROR + 11),
+                message: e.to_string(),
+                data: None,
+            },
+            Error::TooManyPendingUploads(invalid_count) => rpc::Error {
+                code: rpc::ErrorCode::ServerError(BASE_ERROR + 12),
+                message: format!("too many pending uploads. {}", invalid_count),
+                data: Some("the abandoned uploads expire after one hour without receiving any new chunk.".into()),
+            },
+            Error::PendingUploadsTooLarge { provided, max } => rpc::Error {
+                code: rpc::ErrorCode::ServerError(BASE_ERROR + 13),
+                message: format!(
+                    "pending uploads are too large, provided: {}, max: {}",
+                    provided, max
+                ),
+                data: Some("the abandoned uploads expire after one hour without receiving any new chunk.".into()),
+            },
             Error::UnsafeRpcCalled(e) => e.into(),
         }
     }
@@ -19,15 +19,116 @@
 pub mod error;
 
 use jsonrpc_derive::rpc;
+use jsonrpc_pubsub::{typed::Subscriber, SubscriptionId};
+use serde::{Deserialize, Serialize};
 
 use sc_rpc_api::author::{error::FutureResult, hash::ExtrinsicOrHash};
 
 use sp_core::{Bytes, H256};
+use sp_rpc::number::NumberOrHex;
 
 use self::error::Result;
 
 pub use self::gen_client::Client as OffchainClient;
 
+/// Status of the transaction data being uploaded chunk by chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadStatus {
+    /// Number of distinct chunks received so far.
+    pub received_chunks: u32,
+    /// Total number of chunks of the transaction data.
+    pub total_chunks: u32,
+    /// Whether the transaction data has been assembled and persisted.
+    pub completed: bool,
+}
+
+/// A chunk of the transaction data along with its proof against the chunk root.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChunkWithProof {
+    /// Index of the chunk in the transaction data.
+    pub chunk_index: u32,
+    /// Raw bytes of the chunk.
+    pub chunk: Bytes,
+    /// Trie proof of the chunk against the chunk root.
+    pub proof: Vec<Bytes>,
+}
+
+/// Block specified by either the block hash or the block number.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum HashOrNumber<Hash> {
+    /// Block hash.
+    Hash(Hash),
+    /// Block number.
+    Number(NumberOrHex),
+}
+
+/// Metadata of the transaction data recorded on chain.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionDataInfo<Hash> {
+    /// Chunk root of the transaction data.
+    pub chunk_root: Hash,
+    /// Byte size of the transaction data.
+    pub data_size: u32,
+}
+
+/// Notification of the transaction data stored or forgotten on chain.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "camelCase")]
+pub enum DataNotification<Hash, AccountId> {
+    /// New transaction data has been stored.
+    #[serde(rename_all = "camelCase")]
+    Stored {
+        /// Block in which the data is stored.
+        block_hash: Hash,
+        /// Index of the extrinsic storing the data.
+        extrinsic_index: u32,
+        /// Account storing the data.
+        sender: AccountId,
+        /// Chunk root of the transaction data.
+        chunk_root: Hash,
+        /// Byte size of the transaction data.
+        data_size: u32,
+        /// Whether the transaction data is present in the local storage.
+        stored_locally: bool,
+    },
+    /// Transaction data stored previously has been forgotten.
+    #[serde(rename_all = "camelCase")]
+    Forgotten {
+        /// Block in which the data is forgotten.
+        block_hash: Hash,
+        /// Index of the extrinsic forgetting the data.
+        extrinsic_index: u32,
+        /// Number of the block in which the data was stored.
+        stored_block_number: NumberOrHex,
+        /// Index of the extrinsic which stored the data.
+        stored_extrinsic_index: u32,
+        /// Chunk root of the transaction data.
+        chunk_root: Option<Hash>,
+        /// Whether the transaction data is present in the local storage.
+        stored_locally: bool,
+    },
+}
+
+/// Progress of backfilling the historical transaction data from peers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackfillStatus {
+    /// Number of the next block to scan, all the blocks before have been scanned.
+    pub next_block: u64,
+    /// Number of the finalized block which the backfill is catching up with.
+    pub target_block: u64,
+    /// Number of the transaction data fetched from peers.
+    pub fetched_data: u64,
+    /// Total byte size of the transaction data fetched from peers.
+    pub fetched_bytes: u64,
+    /// Number of the missing transaction data not available from any peer yet.
+    pub pending_data: u64,
+}
+
 /// Canyon perma storage RPC API.
 #[rpc]
 pub trait PermastoreApi<Hash, BlockHash> {
@@ -47,7 +148,89 @@ pub trait PermastoreApi<Hash, BlockHash> {
     #[rpc(name = "permastore_submit")]
     fn submit(&self, value: Bytes) -> Result<H256>;
 
+    /// Submit a chunk of the transaction data along with its proof against `chunk_root`.
+    ///
+    /// The transaction data of `data_size` bytes is persisted once all of its chunks
+    /// are received, which makes it possible to upload the large data resumably.
+    ///
+    /// An upload is dropped along with the chunks received so far if no new chunk is
+    /// received for one hour, the number of pending uploads is limited.
+    #[rpc(name = "permastore_submitChunk")]
+    fn submit_chunk(
+        &self,
+        chunk_root: H256,
+        data_size: u64,
+        chunk_index: u32,
+        chunk: Bytes,
+        proof: Vec<Bytes>,
+    ) -> Result<UploadStatus>;
+
     /// Fetch storage under given key.
     #[rpc(name = "permastore_retrieve")]
     fn retrieve(&self, key: Bytes) -> Result<Option<Bytes>>;
+
+    /// Fetch the chunk `chunk_index` of the transaction data along with its proof.
+    #[rpc(name = "permastore_retrieveChunk")]
+    fn retrieve_chunk(&self, chunk_root: H256, chunk_index: u32) -> Result<Option<ChunkWithProof>>;
+
+    /// Fetch at most `len` bytes of the transaction data starting from `offset`.
+    ///
+    /// The range is truncated at the end of the transaction data.
+    #[rpc(name = "permastore_retrieveRange")]
+    fn retrieve_range(&self, chunk_root: H256, offset: u64, len: u32) -> Result<Option<Bytes>>;
+
+    /// Fetch the transaction data stored by the extrinsic `extrinsic_index` in `block`.
+    #[rpc(name = "permastore_transactionData")]
+    fn transaction_data(
+        &self,
+        block: HashOrNumber<BlockHash>,
+        extrinsic_index: u32,
+    ) -> Result<Option<Bytes>>;
+
+    /// Returns the chunk root and size of the transaction data stored by the extrinsic
+    /// `extrinsic_index` in `block`.
+    #[rpc(name = "permastore_transactionDataInfo")]
+    fn transaction_data_info(
+        &self,
+        block: HashOrNumber<BlockHash>,
+        extrinsic_index: u32,
+    ) -> Result<Option<TransactionDataInfo<BlockHash>>>;
+
+    /// Returns the progress of backfilling the historical transaction data, or `None`
+    /// if the backfill is not running on the node.
+    #[rpc(name = "permastore_backfillStatus")]
+    fn backfill_status(&self) -> Result<Option<BackfillStatus>>;
+}
+
+/// Canyon perma storage subscription RPC API.
+#[rpc(server)]
+pub trait PermastoreSubscriptionApi<BlockHash, AccountId> {
+    /// RPC metadata
+    type Metadata;
+
+    /// Subscribe to the transaction data stored or forgotten in the new best blocks,
+    /// or in the finalized blocks if `finalized` is `true`.
+    #[pubsub(
+        subscription = "permastore_stored",
+        subscribe,
+        name = "permastore_subscribeStored"
+    )]
+    fn subscribe_stored(
+        &self,
+        metadata: Self::Metadata,
+        subscriber: Subscriber<DataNotification<BlockHash, AccountId>>,
+        finalized: Option<bool>,
+    );
+
+    /// Unsubscribe from the transaction data notifications.
+    #[pubsub(
+        subscription = "permastore_stored",
+        unsubscribe,
+        name = "permastore_unsubscribeStored"
+    )]
+    fn unsubscribe_stored(
+        &self,
+        metadata: Option<Self::Metadata>,
+        id: SubscriptionId,
+    ) -> jsonrpc_core::Result<bool>;
 }
@@ -0,0 +1,41 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// This file is part of Canyon.
+//
+// Copyright (c) 2021 Canyon Labs.
+//
+// Canyon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// Canyon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Canyon. If not, see <http://www.gnu.org/licenses/>.
+
+pub mod error;
+
+use jsonrpc_derive::rpc;
+
+use sp_runtime::Permill;
+
+use self::error::Result;
+
+pub use self::gen_client::Client as PoaClient;
+
+/// Canyon PoA RPC API.
+#[rpc]
+pub trait PoaApi<BlockHash, AccountId> {
+    /// Returns the estimated storage capacity of `who`.
+    ///
+    /// `None` if `who` has never authored a block with a valid proof of access.
+    #[rpc(name = "poa_storageCapacity")]
+    fn storage_capacity(&self, who: AccountId, at: Option<BlockHash>) -> Result<Option<Permill>>;
+
+    /// Returns the estimated storage capacities of all the block authors so far.
+    #[rpc(name = "poa_allCapacities")]
+    fn all_capacities(&self, at: Option<BlockHash>) -> Result<Vec<(AccountId, Permill)>>;
+}
@@ -19,3 +19,4 @@
 //! Canyon specific RPCs.
 
 pub mod permastore;
+pub mod poa;
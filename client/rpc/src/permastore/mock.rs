@@ -0,0 +1,124 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// This file is part of Canyon.
+//
+// Copyright (c) 2021 Canyon Labs.
+//
+// Canyon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// Canyon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Canyon. If not, see <http://www.gnu.org/licenses/>.
+
+//! A mock runtime including `pallet_permastore` for decoding its events.
+
+use frame_support::{parameter_types, traits::Everything, PalletId};
+use sp_core::H256;
+use sp_runtime::{
+    testing::Header,
+    traits::{BlakeTwo256, IdentityLookup},
+    Perbill, Permill,
+};
+
+type UncheckedExtrinsic = frame_system::mocking::MockUncheckedExtrinsic<Test>;
+type Block = frame_system::mocking::MockBlock<Test>;
+
+frame_support::construct_runtime!(
+    pub enum Test where
+        Block = Block,
+        NodeBlock = Block,
+        UncheckedExtrinsic = UncheckedExtrinsic,
+    {
+        System: frame_system::{Pallet, Call, Config, Storage, Event<T>},
+        Balances: pallet_balances::{Pallet, Call, Storage, Config<T>, Event<T>},
+        Permastore: pallet_permastore::{Pallet, Call, Storage, Config, Event<T>},
+    }
+);
+
+parameter_types! {
+    pub const BlockHashCount: u64 = 250;
+}
+impl frame_system::Config for Test {
+    type BaseCallFilter = Everything;
+    type BlockWeights = ();
+    type BlockLength = ();
+    type DbWeight = ();
+    type Origin = Origin;
+    type Index = u64;
+    type BlockNumber = u64;
+    type Hash = H256;
+    type Call = Call;
+    type Hashing = BlakeTwo256;
+    type AccountId = u64;
+    type Lookup = IdentityLookup<Self::AccountId>;
+    type Header = Header;
+    type Event = Event;
+    type BlockHashCount = BlockHashCount;
+    type Version = ();
+    type PalletInfo = PalletInfo;
+    type AccountData = pallet_balances::AccountData<u64>;
+    type OnNewAccount = ();
+    type OnKilledAccount = ();
+    type SystemWeightInfo = ();
+    type SS58Prefix = ();
+    type OnSetCode = ();
+}
+parameter_types! {
+    pub const ExistentialDeposit: u64 = 1;
+    pub const MaxLocks: u32 = 50;
+    pub const MaxReserves: u32 = 50;
+}
+impl pallet_balances::Config for Test {
+    type MaxLocks = MaxLocks;
+    type MaxReserves = MaxReserves;
+    type ReserveIdentifier = [u8; 8];
+    type Balance = u64;
+    type DustRemoval = ();
+    type Event = Event;
+    type ExistentialDeposit = ExistentialDeposit;
+    type AccountStore = System;
+    type WeightInfo = ();
+}
+parameter_types! {
+    pub const PermastorePalletId: PalletId = PalletId(*b"py/prmst");
+    pub const EndowmentRelease: Perbill = Perbill::from_percent(1);
+    pub const RefundPeriod: u64 = 100;
+    pub const MaxRefund: Perbill = Perbill::from_percent(50);
+    pub const MaxDataSize: u32 = 1024 * 1024 * 1024;
+    pub const BaseStorageCost: u64 = 1;
+    pub const StorageCostDeclineRate: Perbill = Perbill::one();
+    pub const DiscountRate: Perbill = Perbill::zero();
+}
+impl pallet_permastore::Config for Test {
+    type Event = Event;
+    type Currency = Balances;
+    type PalletId = PermastorePalletId;
+    type EndowmentRelease = EndowmentRelease;
+    type StorageCapacity = TestStorageCapacity;
+    type RefundPeriod = RefundPeriod;
+    type MaxRefund = MaxRefund;
+    type MaxDataSize = MaxDataSize;
+    type BaseStorageCost = BaseStorageCost;
+    type StorageCostDeclineRate = StorageCostDeclineRate;
+    type DiscountRate = DiscountRate;
+    type WeightInfo = ();
+}
+
+/// Storage capacity of validators, which is irrelevant to the events.
+pub struct TestStorageCapacity;
+
+impl pallet_permastore::StorageCapacity<u64> for TestStorageCapacity {
+    fn author() -> u64 {
+        0
+    }
+
+    fn storage_capacity(_who: &u64) -> Option<Permill> {
+        None
+    }
+}
@@ -16,9 +16,14 @@
 // You should have received a copy of the GNU General Public License
 // along with Canyon. If not, see <http://www.gnu.org/licenses/>.
 
+#[cfg(test)]
+mod mock;
+mod subscription;
 #[cfg(test)]
 mod tests;
 
+use std::convert::TryInto;
+use std::io;
 use std::marker::PhantomData;
 use std::ops::Deref;
 use std::sync::Arc;
@@ -33,14 +38,18 @@ use sc_rpc_api::{
 use sc_transaction_pool_api::{TransactionPool, TxHash};
 
 use sp_core::{Bytes, Encode, H256};
-use sp_runtime::traits::{BlakeTwo256, Block as BlockT, Hash};
+use sp_runtime::{generic::BlockId, traits::Block as BlockT};
 
+use cc_datastore::{ChunkStorage, DataInfo, TransactionDataBackend};
+use cc_network::{BackfillProgress, SharedBackfillProgress};
 use cc_rpc_api::permastore::{
     error::{Error, InvalidCount, Result},
-    PermastoreApi,
+    BackfillStatus, ChunkWithProof, HashOrNumber, PermastoreApi, TransactionDataInfo, UploadStatus,
 };
 use cp_permastore::{PermaStorage, CHUNK_SIZE};
 
+pub use self::subscription::PermastoreSubscription;
+
 #[derive(Debug)]
 pub struct Permastore<T, P, A, B> {
     /// Permanent data storage.
@@ -54,6 +63,8 @@ pub struct Permastore<T, P, A, B> {
     /// TODO: since this is a pretty dangerous operation we might
     /// need a more restricted way to prevent from the risks.
     deny_unsafe: DenyUnsafe,
+    /// Progress of backfilling the historical transaction data, if running.
+    backfill: Option<SharedBackfillProgress>,
     /// Block.
     phatom: PhantomData<B>,
 }
@@ -65,9 +76,16 @@ impl<T, P, A, B> Permastore<T, P, A, B> {
             pool,
             author,
             deny_unsafe,
+            backfill: None,
             phatom: PhantomData::<B>,
         }
     }
+
+    /// Exposes the progress of backfilling the historical transaction data.
+    pub fn with_backfill(mut self, backfill: SharedBackfillProgress) -> Self {
+        self.backfill = Some(backfill);
+        self
+    }
 }
 
 /// Maximum byte size of uploading transaction data directly. 10MiB
@@ -76,9 +94,63 @@ const MAX_UPLOAD_DATA_SIZE: u32 = 10 * 1024 * 1024;
 /// Maximum byte size of downloading transaction data directly. 12MiB
 const MAX_DOWNLOAD_DATA_SIZE: u32 = 12 * 1024 * 1024;
 
+/// Maximum byte size of uploading transaction data chunk by chunk, which is
+/// in line with the `MaxDataSize` of runtime. 1GiB
+const MAX_CHUNKED_UPLOAD_DATA_SIZE: u64 = 1024 * 1024 * 1024;
+
+/// Maximum byte size of the proof of a submitted chunk. 64KiB
+const MAX_CHUNK_PROOF_SIZE: usize = 64 * 1024;
+
+/// Maximum number of the transaction data being uploaded chunk by chunk at the same time.
+const MAX_PENDING_UPLOADS: usize = 16;
+
+/// Maximum total byte size of the transaction data being uploaded chunk by chunk at
+/// the same time, which bounds the disk space pinned by the pending uploads. 4GiB
+const MAX_PENDING_UPLOAD_BYTES: u64 = 4 * 1024 * 1024 * 1024;
+
+/// Seconds after which an upload without receiving any new chunk is abandoned. 1 hour
+const UPLOAD_TTL: u64 = 60 * 60;
+
+/// Converts the block hash or number into [`BlockId`].
+fn block_id<B: BlockT>(block: HashOrNumber<B::Hash>) -> Result<BlockId<B>> {
+    match block {
+        HashOrNumber::Hash(hash) => Ok(BlockId::Hash(hash)),
+        HashOrNumber::Number(number) => {
+            let block_number: u32 = number.clone().try_into().map_err(|_| {
+                Error::Client(format!("Block number {:?} is out of range", number).into())
+            })?;
+            Ok(BlockId::Number(block_number.into()))
+        }
+    }
+}
+
+/// Returns the block at which the transaction data of block `id` is resolved.
+///
+/// A block hash may point to a block on a side branch, of which the transaction data
+/// must be read from its own chain, while a block number always refers to the best chain.
+fn state_at<B: BlockT>(id: BlockId<B>) -> Option<BlockId<B>> {
+    match id {
+        BlockId::Hash(_) => Some(id),
+        BlockId::Number(_) => None,
+    }
+}
+
+/// Converts the result of [`TransactionDataBackend`], the unknown block and the
+/// extrinsic without transaction data are treated as no data found.
+fn transaction_data_result<R, B: BlockT>(
+    result: std::result::Result<Option<R>, cc_datastore::Error<B>>,
+) -> Result<Option<R>> {
+    match result {
+        Ok(res) => Ok(res),
+        Err(cc_datastore::Error::BlockNumberNotFound(_))
+        | Err(cc_datastore::Error::ChunkRootIsNone(..)) => Ok(None),
+        Err(e) => Err(Error::Client(Box::new(e))),
+    }
+}
+
 impl<T, P, A, B> PermastoreApi<TxHash<P>, <B as BlockT>::Hash> for Permastore<T, P, A, B>
 where
-    T: PermaStorage + 'static,
+    T: ChunkStorage + TransactionDataBackend<B> + 'static,
     P: TransactionPool + Send + Sync + 'static,
     B: BlockT,
     A: AuthorApi<TxHash<P>, <B as BlockT>::Hash>,
@@ -118,13 +190,7 @@ where
             )));
         }
 
-        let chunks = value
-            .0
-            .chunks(CHUNK_SIZE as usize)
-            .map(|c| BlakeTwo256::hash(c).encode())
-            .collect();
-
-        let chunk_root = BlakeTwo256::ordered_trie_root(chunks);
+        let chunk_root = cp_permastore::chunk_root(&value);
 
         let key = chunk_root.encode();
 
@@ -140,6 +206,118 @@ where
         Ok(chunk_root)
     }
 
+    fn submit_chunk(
+        &self,
+        chunk_root: H256,
+        data_size: u64,
+        chunk_index: u32,
+        chunk: Bytes,
+        proof: Vec<Bytes>,
+    ) -> Result<UploadStatus> {
+        if data_size > MAX_CHUNKED_UPLOAD_DATA_SIZE {
+            return Err(Error::DataSizeTooLarge);
+        }
+
+        let total_chunks = DataInfo { data_size }.chunk_count();
+        if chunk_index >= total_chunks {
+            return Err(Error::InvalidChunkIndex(InvalidCount::new(
+                chunk_index,
+                total_chunks.saturating_sub(1),
+            )));
+        }
+
+        if chunk.len() > CHUNK_SIZE as usize {
+            return Err(Error::ChunkTooLarge);
+        }
+
+        // All the chunks except the last one are expected to be full.
+        let expected_chunk_size =
+            (data_size - chunk_index as u64 * CHUNK_SIZE as u64).min(CHUNK_SIZE as u64) as u32;
+        if chunk.len() as u32 != expected_chunk_size {
+            return Err(Error::InvalidChunkSize {
+                expected: expected_chunk_size,
+                provided: chunk.len() as u32,
+            });
+        }
+
+        if proof.iter().map(|node| node.len()).sum::<usize>() > MAX_CHUNK_PROOF_SIZE {
+            return Err(Error::DataPathTooLarge);
+        }
+
+        let proof = proof.into_iter().map(|node| node.0).collect::<Vec<_>>();
+        cp_permastore::verify_chunk_proof(&chunk_root, &chunk, chunk_index, &proof)
+            .map_err(|_| Error::InvalidProof)?;
+
+        let key = chunk_root.encode();
+
+        // Hold the write lock so that the chunks of the same data are handled in sequence.
+        let storage = self.storage.write();
+
+        if storage.exists(&key) {
+            return Err(Error::DataExists);
+        }
+
+        let chunk_store = storage.chunk_store();
+
+        // Drop the abandoned uploads, which also unblocks the data of which an upload
+        // with a wrong data size has been started.
+        let now = cc_datastore::unix_timestamp();
+        chunk_store.remove_expired_uploads(now.saturating_sub(UPLOAD_TTL))?;
+
+        if chunk_store.upload_progress(&key)?.is_none() {
+            let pending_uploads = chunk_store.pending_uploads()?;
+            if pending_uploads.len() >= MAX_PENDING_UPLOADS {
+                return Err(Error::TooManyPendingUploads(InvalidCount::new(
+                    pending_uploads.len() as u32,
+                    MAX_PENDING_UPLOADS as u32,
+                )));
+            }
+
+            let pending_bytes = pending_uploads
+                .iter()
+                .map(|(_, progress)| progress.data_size)
+                .sum::<u64>()
+                + data_size;
+            if pending_bytes > MAX_PENDING_UPLOAD_BYTES {
+                return Err(Error::PendingUploadsTooLarge {
+                    provided: pending_bytes,
+                    max: MAX_PENDING_UPLOAD_BYTES,
+                });
+            }
+        }
+
+        if chunk_store.chunk(&key, chunk_index)?.is_some() {
+            return Err(Error::ChunkExists);
+        }
+
+        let progress = chunk_store.put_uploaded_chunk(&key, data_size, chunk_index, &chunk, now)?;
+
+        if progress.is_complete() {
+            let chunk_hashes = chunk_store.uploaded_chunk_hashes(&key)?.unwrap_or_default();
+
+            // Each chunk matches `chunk_root` individually, but the trie of `chunk_root`
+            // could still contain more chunks than declared by `data_size`.
+            if cp_permastore::chunk_root_of_hashes(chunk_hashes.iter().copied()) != chunk_root {
+                chunk_store.remove(&key)?;
+                return Err(Error::InvalidProof);
+            }
+
+            chunk_store.complete_upload(&key, &chunk_hashes)?;
+
+            log::debug!(
+                target: "rpc::permastore",
+                "Assembled the transaction data of chunk_root: {:?}, data_size: {}",
+                chunk_root, data_size,
+            );
+        }
+
+        Ok(UploadStatus {
+            received_chunks: progress.received_chunks,
+            total_chunks,
+            completed: progress.is_complete(),
+        })
+    }
+
     fn retrieve(&self, key: Bytes) -> Result<Option<Bytes>> {
         if let Some(value) = self.storage.read().retrieve(&*key) {
             let data_size = value.len() as u32;
@@ -154,4 +332,135 @@ where
             Ok(None)
         }
     }
+
+    fn retrieve_chunk(&self, chunk_root: H256, chunk_index: u32) -> Result<Option<ChunkWithProof>> {
+        let key = chunk_root.encode();
+        let storage = self.storage.read();
+
+        let data_info = match storage.data_info(&key)? {
+            Some(data_info) => data_info,
+            None => return Ok(None),
+        };
+
+        let chunk_count = data_info.chunk_count();
+        if chunk_index >= chunk_count {
+            return Err(Error::InvalidChunkIndex(InvalidCount::new(
+                chunk_index,
+                chunk_count.saturating_sub(1),
+            )));
+        }
+
+        let chunk_store = storage.chunk_store();
+        let (chunk, chunk_hashes) = match (
+            chunk_store.chunk(&key, chunk_index)?,
+            chunk_store.chunk_hashes(&key)?,
+        ) {
+            (Some(chunk), Some(chunk_hashes)) => (chunk, chunk_hashes),
+            _ => return Ok(None),
+        };
+
+        let proof =
+            cp_permastore::generate_chunk_proof(chunk_hashes, chunk_index).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::Other,
+                    format!("Failed to generate the chunk proof: {:?}", e),
+                )
+            })?;
+
+        Ok(Some(ChunkWithProof {
+            chunk_index,
+            chunk: chunk.into(),
+            proof: proof.into_iter().map(Into::into).collect(),
+        }))
+    }
+
+    fn retrieve_range(&self, chunk_root: H256, offset: u64, len: u32) -> Result<Option<Bytes>> {
+        if len > MAX_DOWNLOAD_DATA_SIZE {
+            return Err(Error::DataTooLarge(InvalidCount::new(
+                len,
+                MAX_DOWNLOAD_DATA_SIZE,
+            )));
+        }
+
+        let key = chunk_root.encode();
+        let storage = self.storage.read();
+
+        if storage.data_info(&key)?.is_none() {
+            return Ok(None);
+        }
+
+        Ok(storage
+            .chunk_store()
+            .read_range(&key, offset, len as u64)?
+            .map(Into::into))
+    }
+
+    fn transaction_data(
+        &self,
+        block: HashOrNumber<<B as BlockT>::Hash>,
+        extrinsic_index: u32,
+    ) -> Result<Option<Bytes>> {
+        let id = block_id::<B>(block)?;
+        let storage = self.storage.read();
+
+        // Check the data size beforehand to avoid reading the large data into memory.
+        let data_size = match transaction_data_result(storage.transaction_data_info(
+            state_at(id),
+            id,
+            extrinsic_index,
+        ))? {
+            Some((_chunk_root, data_size)) => data_size,
+            None => return Ok(None),
+        };
+        if data_size > MAX_DOWNLOAD_DATA_SIZE {
+            return Err(Error::DataTooLarge(InvalidCount::new(
+                data_size,
+                MAX_DOWNLOAD_DATA_SIZE,
+            )));
+        }
+
+        Ok(
+            transaction_data_result(storage.transaction_data(state_at(id), id, extrinsic_index))?
+                .map(Into::into),
+        )
+    }
+
+    fn transaction_data_info(
+        &self,
+        block: HashOrNumber<<B as BlockT>::Hash>,
+        extrinsic_index: u32,
+    ) -> Result<Option<TransactionDataInfo<<B as BlockT>::Hash>>> {
+        let id = block_id::<B>(block)?;
+
+        Ok(
+            transaction_data_result(self.storage.read().transaction_data_info(
+                state_at(id),
+                id,
+                extrinsic_index,
+            ))?
+            .map(|(chunk_root, data_size)| TransactionDataInfo {
+                chunk_root,
+                data_size,
+            }),
+        )
+    }
+
+    fn backfill_status(&self) -> Result<Option<BackfillStatus>> {
+        Ok(self.backfill.as_ref().map(|backfill| {
+            let BackfillProgress {
+                next_block,
+                target_block,
+                fetched_data,
+                fetched_bytes,
+                pending_data,
+            } = backfill.get();
+            BackfillStatus {
+                next_block,
+                target_block,
+                fetched_data,
+                fetched_bytes,
+                pending_data,
+            }
+        }))
+    }
 }
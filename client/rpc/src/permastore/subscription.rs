@@ -0,0 +1,343 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// This file is part of Canyon.
+//
+// Copyright (c) 2021 Canyon Labs.
+//
+// Canyon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// Canyon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Canyon. If not, see <http://www.gnu.org/licenses/>.
+
+//! Subscriptions to the transaction data stored or forgotten on chain.
+
+use std::convert::TryInto;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use codec::{Decode, Encode};
+use frame_support::storage::StorageValue;
+use futures::{future, stream, FutureExt, SinkExt, StreamExt};
+use jsonrpc_pubsub::{manager::SubscriptionManager, typed::Subscriber, SubscriptionId};
+use serde::Serialize;
+
+use sc_client_api::{BlockchainEvents, StorageProvider};
+use sp_api::ProvideRuntimeApi;
+use sp_blockchain::HeaderBackend;
+use sp_core::storage::StorageKey;
+use sp_runtime::{
+    generic::BlockId,
+    traits::{Block as BlockT, Header as HeaderT, NumberFor, UniqueSaturatedInto},
+};
+
+use cc_rpc_api::permastore::{DataNotification, PermastoreSubscriptionApi};
+use cp_permastore::{PermaStorage, PermastoreApi};
+
+pub(super) type EventRecord<R> = frame_system::EventRecord<
+    <R as frame_system::Config>::Event,
+    <R as frame_system::Config>::Hash,
+>;
+
+type Notification<B, R> =
+    DataNotification<<B as BlockT>::Hash, <R as frame_system::Config>::AccountId>;
+
+/// Returns the notifications of the transaction data stored or forgotten in block `block_hash`.
+fn data_notifications<B, C, BE, S, R>(
+    client: &C,
+    storage: &S,
+    block_hash: B::Hash,
+) -> Result<Vec<Notification<B, R>>, String>
+where
+    B: BlockT,
+    BE: sc_client_api::Backend<B>,
+    C: ProvideRuntimeApi<B> + HeaderBackend<B> + StorageProvider<B, BE>,
+    C::Api: PermastoreApi<B, NumberFor<B>, u32, B::Hash>,
+    S: PermaStorage,
+    R: pallet_permastore::Config<Hash = B::Hash, BlockNumber = NumberFor<B>>,
+    R::Event: TryInto<pallet_permastore::Event<R>>,
+{
+    let events_key = StorageKey(frame_system::Events::<R>::hashed_key().to_vec());
+
+    let events = match client
+        .storage(&BlockId::Hash(block_hash), &events_key)
+        .map_err(|e| e.to_string())?
+    {
+        Some(events) => {
+            Vec::<EventRecord<R>>::decode(&mut events.0.as_slice()).map_err(|e| e.to_string())?
+        }
+        None => return Ok(Vec::new()),
+    };
+
+    event_notifications::<B, C, S, R>(client, storage, block_hash, events)
+}
+
+/// Returns the notifications of the transaction data stored or forgotten given the
+/// `events` deposited in block `block_hash`.
+pub(super) fn event_notifications<B, C, S, R>(
+    client: &C,
+    storage: &S,
+    block_hash: B::Hash,
+    events: Vec<EventRecord<R>>,
+) -> Result<Vec<Notification<B, R>>, String>
+where
+    B: BlockT,
+    C: ProvideRuntimeApi<B> + HeaderBackend<B>,
+    C::Api: PermastoreApi<B, NumberFor<B>, u32, B::Hash>,
+    S: PermaStorage,
+    R: pallet_permastore::Config<Hash = B::Hash, BlockNumber = NumberFor<B>>,
+    R::Event: TryInto<pallet_permastore::Event<R>>,
+{
+    let at = BlockId::Hash(block_hash);
+
+    let block_number = client
+        .number(block_hash)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Block {} not found", block_hash))?;
+
+    let runtime_api = client.runtime_api();
+
+    let mut notifications = Vec::new();
+    for record in events {
+        let extrinsic_index = match record.phase {
+            frame_system::Phase::ApplyExtrinsic(extrinsic_index) => extrinsic_index,
+            _ => continue,
+        };
+
+        let event: Result<pallet_permastore::Event<R>, _> = record.event.try_into();
+        match event {
+            Ok(pallet_permastore::Event::Stored(sender, chunk_root)) => {
+                let data_size = runtime_api
+                    .data_size(&at, block_number, extrinsic_index)
+                    .map_err(|e| e.to_string())?;
+                notifications.push(DataNotification::Stored {
+                    block_hash,
+                    extrinsic_index,
+                    sender,
+                    chunk_root,
+                    data_size,
+                    stored_locally: storage.exists(&chunk_root.encode()),
+                });
+            }
+            Ok(pallet_permastore::Event::Forgot(stored_block_number, stored_extrinsic_index)) => {
+                let chunk_root = runtime_api
+                    .chunk_root(&at, stored_block_number, stored_extrinsic_index)
+                    .map_err(|e| e.to_string())?;
+                notifications.push(DataNotification::Forgotten {
+                    block_hash,
+                    extrinsic_index,
+                    stored_block_number: UniqueSaturatedInto::<u64>::unique_saturated_into(
+                        stored_block_number,
+                    )
+                    .into(),
+                    stored_extrinsic_index,
+                    chunk_root,
+                    stored_locally: chunk_root
+                        .map(|chunk_root| storage.exists(&chunk_root.encode()))
+                        .unwrap_or(false),
+                });
+            }
+            _ => {}
+        }
+    }
+
+    Ok(notifications)
+}
+
+/// Returns the hashes of blocks finalized along with `finalized` in ascending order,
+/// given the number of last finalized block `last_finalized`.
+///
+/// Multiple blocks can be finalized at once, in which case all of them are returned
+/// so that the data stored in the implicitly finalized blocks is notified too.
+fn newly_finalized_blocks<B, C>(
+    client: &C,
+    last_finalized: NumberFor<B>,
+    finalized: &B::Header,
+) -> sp_blockchain::Result<Vec<B::Hash>>
+where
+    B: BlockT,
+    C: HeaderBackend<B>,
+{
+    let mut hashes = Vec::new();
+    let mut header = finalized.clone();
+    while *header.number() > last_finalized {
+        hashes.push(header.hash());
+        let parent_hash = *header.parent_hash();
+        header = client.header(BlockId::Hash(parent_hash))?.ok_or_else(|| {
+            sp_blockchain::Error::UnknownBlock(format!("Header of {} not found", parent_hash))
+        })?;
+    }
+    hashes.reverse();
+    Ok(hashes)
+}
+
+/// Permastore subscription RPC handler.
+///
+/// The notifications are extracted from the events of `pallet_permastore` in runtime `R`.
+pub struct PermastoreSubscription<B, C, BE, S, R> {
+    /// Client.
+    client: Arc<C>,
+    /// Permanent data storage.
+    storage: S,
+    /// Subscriptions manager.
+    manager: SubscriptionManager,
+    /// Block, backend and runtime.
+    phantom: PhantomData<(B, BE, R)>,
+}
+
+impl<B, C, BE, S, R> PermastoreSubscription<B, C, BE, S, R> {
+    /// Creates a new instance of [`PermastoreSubscription`].
+    pub fn new<E>(client: Arc<C>, storage: S, executor: E) -> Self
+    where
+        E: futures::task::Spawn + Send + Sync + 'static,
+    {
+        Self {
+            client,
+            storage,
+            manager: SubscriptionManager::new(Arc::new(executor)),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<B, C, BE, S, R> PermastoreSubscriptionApi<B::Hash, R::AccountId>
+    for PermastoreSubscription<B, C, BE, S, R>
+where
+    B: BlockT,
+    BE: sc_client_api::Backend<B> + 'static,
+    C: ProvideRuntimeApi<B>
+        + HeaderBackend<B>
+        + BlockchainEvents<B>
+        + StorageProvider<B, BE>
+        + Send
+        + Sync
+        + 'static,
+    C::Api: PermastoreApi<B, NumberFor<B>, u32, B::Hash>,
+    S: PermaStorage + Clone + 'static,
+    R: pallet_permastore::Config<Hash = B::Hash, BlockNumber = NumberFor<B>>
+        + Send
+        + Sync
+        + 'static,
+    R::Event: TryInto<pallet_permastore::Event<R>>,
+    R::AccountId: Serialize + Send + Sync + 'static,
+{
+    type Metadata = sc_rpc_api::Metadata;
+
+    fn subscribe_stored(
+        &self,
+        _metadata: Self::Metadata,
+        subscriber: Subscriber<Notification<B, R>>,
+        finalized: Option<bool>,
+    ) {
+        let block_hashes = if finalized.unwrap_or(false) {
+            let client = self.client.clone();
+            let mut last_finalized = self.client.info().finalized_number;
+            self.client
+                .finality_notification_stream()
+                .flat_map(move |notification| {
+                    let hashes = newly_finalized_blocks::<B, _>(
+                        &*client,
+                        last_finalized,
+                        &notification.header,
+                    )
+                    .unwrap_or_else(|e| {
+                        log::warn!(
+                            target: "rpc::permastore",
+                            "Failed to collect the blocks finalized along with {}: {:?}",
+                            notification.hash, e,
+                        );
+                        vec![notification.hash]
+                    });
+                    last_finalized = *notification.header.number();
+                    stream::iter(hashes)
+                })
+                .boxed()
+        } else {
+            self.client
+                .import_notification_stream()
+                .filter(|notification| future::ready(notification.is_new_best))
+                .map(|notification| notification.hash)
+                .boxed()
+        };
+
+        let client = self.client.clone();
+        let storage = self.storage.clone();
+        let notifications = block_hashes.flat_map(move |block_hash| {
+            let notifications =
+                data_notifications::<B, C, BE, S, R>(&*client, &storage, block_hash)
+                    .unwrap_or_else(|e| {
+                        log::warn!(
+                            target: "rpc::permastore",
+                            "Failed to collect the data notifications of block {}: {}",
+                            block_hash, e,
+                        );
+                        Vec::new()
+                    });
+            stream::iter(notifications)
+        });
+
+        self.manager.add(subscriber, |sink| {
+            notifications
+                .map(|notification| Ok::<_, ()>(Ok(notification)))
+                .forward(sink.sink_map_err(|e| {
+                    log::debug!(target: "rpc::permastore", "Error sending notifications: {:?}", e)
+                }))
+                .map(drop)
+        });
+    }
+
+    fn unsubscribe_stored(
+        &self,
+        _metadata: Option<Self::Metadata>,
+        id: SubscriptionId,
+    ) -> jsonrpc_core::Result<bool> {
+        Ok(self.manager.cancel(id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::executor::block_on;
+    use sc_block_builder::BlockBuilderProvider;
+    use sp_consensus::BlockOrigin;
+    use substrate_test_runtime_client::{runtime::Block, ClientBlockImportExt};
+
+    #[test]
+    fn newly_finalized_blocks_should_include_implicitly_finalized_ones() {
+        let mut client = substrate_test_runtime_client::new();
+
+        let mut hashes = Vec::new();
+        for _ in 0..3 {
+            let block = client
+                .new_block(Default::default())
+                .unwrap()
+                .build()
+                .unwrap()
+                .block;
+            hashes.push(block.header.hash());
+            block_on(client.import(BlockOrigin::Own, block)).unwrap();
+        }
+
+        let finalized = client.header(&BlockId::Hash(hashes[2])).unwrap().unwrap();
+
+        assert_eq!(
+            newly_finalized_blocks::<Block, _>(&client, 0, &finalized).unwrap(),
+            hashes
+        );
+        assert_eq!(
+            newly_finalized_blocks::<Block, _>(&client, 1, &finalized).unwrap(),
+            hashes[1..].to_vec()
+        );
+        assert!(newly_finalized_blocks::<Block, _>(&client, 3, &finalized)
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -18,22 +18,25 @@
 
 use super::*;
 
+use std::collections::HashMap;
 use std::sync::Arc;
 
 use assert_matches::assert_matches;
 use codec::Encode;
 use futures::{executor, StreamExt};
 use jsonrpc_pubsub::{manager::SubscriptionManager, SubscriptionId};
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 
 use sc_rpc::author::Author;
 use sc_rpc_api::{author::hash::ExtrinsicOrHash, DenyUnsafe};
 use sc_transaction_pool::{BasicPool, FullChainApi};
-use sp_core::{blake2_256, hexdisplay::HexDisplay, H256};
+use sp_api::{ApiError, ApiRef, ProvideRuntimeApi};
+use sp_blockchain::HeaderBackend;
+use sp_core::{blake2_256, hexdisplay::HexDisplay, NativeOrEncoded, H256};
 use sp_keystore::testing::KeyStore;
 use substrate_test_runtime_client::{
     self,
-    runtime::{Block, Extrinsic, Transfer},
+    runtime::{Block, Extrinsic, Header, Transfer},
     AccountKeyring, Backend, Client, DefaultTestClientBuilderExt, TestClientBuilderExt,
 };
 
@@ -47,12 +50,175 @@ fn uxt(sender: AccountKeyring, nonce: u64) -> Extrinsic {
     tx.into_signed_tx()
 }
 
+/// `(chunk_root, data_size)` of the transaction data keyed by `(block_number, extrinsic_index)`.
+type TransactionDataMap = HashMap<(u64, u32), (H256, u32)>;
+
+/// A client of which the blocks are provided by the substrate test client and the
+/// permastore runtime API is mocked.
+#[derive(Clone)]
+struct TestChain {
+    inner: Arc<Client<Backend>>,
+    transactions: Arc<Mutex<TransactionDataMap>>,
+    /// The transaction data only visible in the state of a specific block.
+    fork_transactions: Arc<Mutex<HashMap<H256, TransactionDataMap>>>,
+}
+
+impl TestChain {
+    fn new(inner: Arc<Client<Backend>>) -> Self {
+        Self {
+            inner,
+            transactions: Default::default(),
+            fork_transactions: Default::default(),
+        }
+    }
+
+    /// Records the transaction data `(chunk_root, data_size)` on chain.
+    fn store(&self, block_number: u64, extrinsic_index: u32, chunk_root: H256, data_size: u32) {
+        self.transactions
+            .lock()
+            .insert((block_number, extrinsic_index), (chunk_root, data_size));
+    }
+
+    /// Records the transaction data `(chunk_root, data_size)` in the state of block `at` only.
+    fn store_at(
+        &self,
+        at: H256,
+        block_number: u64,
+        extrinsic_index: u32,
+        chunk_root: H256,
+        data_size: u32,
+    ) {
+        self.fork_transactions
+            .lock()
+            .entry(at)
+            .or_default()
+            .insert((block_number, extrinsic_index), (chunk_root, data_size));
+    }
+}
+
+struct RuntimeApi {
+    transactions: TransactionDataMap,
+    fork_transactions: HashMap<H256, TransactionDataMap>,
+}
+
+impl RuntimeApi {
+    fn transaction_at(
+        &self,
+        at: &BlockId<Block>,
+        block_number: u64,
+        extrinsic_index: u32,
+    ) -> Option<(H256, u32)> {
+        let transactions = match at {
+            BlockId::Hash(hash) => self
+                .fork_transactions
+                .get(hash)
+                .unwrap_or(&self.transactions),
+            BlockId::Number(_) => &self.transactions,
+        };
+        transactions.get(&(block_number, extrinsic_index)).copied()
+    }
+}
+
+impl ProvideRuntimeApi<Block> for TestChain {
+    type Api = RuntimeApi;
+
+    fn runtime_api<'a>(&'a self) -> ApiRef<'a, Self::Api> {
+        RuntimeApi {
+            transactions: self.transactions.lock().clone(),
+            fork_transactions: self.fork_transactions.lock().clone(),
+        }
+        .into()
+    }
+}
+
+sp_api::mock_impl_runtime_apis! {
+    impl cp_permastore::PermastoreApi<Block, u64, u32, H256> for RuntimeApi {
+        #[advanced]
+        fn chunk_root(
+            &self,
+            at: &BlockId<Block>,
+            block_number: u64,
+            extrinsic_index: u32,
+        ) -> Result<NativeOrEncoded<Option<H256>>, ApiError> {
+            Ok(self
+                .transaction_at(at, block_number, extrinsic_index)
+                .map(|(chunk_root, _)| chunk_root)
+                .into())
+        }
+
+        fn find_recall_block(_recall_byte: u64) -> Option<u64> {
+            unimplemented!()
+        }
+
+        fn locate_recall_tx(_recall_byte: u64) -> Option<(u64, u32, u64)> {
+            unimplemented!()
+        }
+
+        #[advanced]
+        fn data_size(
+            &self,
+            at: &BlockId<Block>,
+            block_number: u64,
+            extrinsic_index: u32,
+        ) -> Result<NativeOrEncoded<u32>, ApiError> {
+            Ok(self
+                .transaction_at(at, block_number, extrinsic_index)
+                .map(|(_, data_size)| data_size)
+                .unwrap_or_default()
+                .into())
+        }
+
+        fn weave_offsets(_block_number: u64) -> Vec<(u32, u64)> {
+            unimplemented!()
+        }
+
+        fn is_forgotten(_block_number: u64, _extrinsic_index: u32) -> bool {
+            unimplemented!()
+        }
+
+        fn require_proof_of_access() -> bool {
+            unimplemented!()
+        }
+
+        fn block_size() -> u64 {
+            unimplemented!()
+        }
+
+        fn weave_size() -> u64 {
+            unimplemented!()
+        }
+    }
+}
+
+impl HeaderBackend<Block> for TestChain {
+    fn header(&self, id: BlockId<Block>) -> sp_blockchain::Result<Option<Header>> {
+        HeaderBackend::header(&*self.inner, id)
+    }
+
+    fn info(&self) -> sp_blockchain::Info<Block> {
+        HeaderBackend::info(&*self.inner)
+    }
+
+    fn status(&self, id: BlockId<Block>) -> sp_blockchain::Result<sp_blockchain::BlockStatus> {
+        HeaderBackend::status(&*self.inner, id)
+    }
+
+    fn number(&self, hash: H256) -> sp_blockchain::Result<Option<u64>> {
+        HeaderBackend::number(&*self.inner, hash)
+    }
+
+    fn hash(&self, number: u64) -> sp_blockchain::Result<Option<H256>> {
+        HeaderBackend::hash(&*self.inner, number)
+    }
+}
+
 type FullTransactionPool = BasicPool<FullChainApi<Client<Backend>, Block>, Block>;
 
 type TestAuthor = Author<FullTransactionPool, Client<Backend>>;
 
 struct TestSetup {
     pub client: Arc<Client<Backend>>,
+    pub chain: TestChain,
     pub keystore: Arc<KeyStore>,
     pub pool: Arc<FullTransactionPool>,
 }
@@ -73,6 +239,7 @@ impl Default for TestSetup {
         );
 
         TestSetup {
+            chain: TestChain::new(client.clone()),
             client,
             keystore,
             pool,
@@ -94,19 +261,16 @@ impl TestSetup {
 
     fn permastore(
         &self,
-    ) -> Permastore<
-        cc_datastore::PermanentStorage<Client<Backend>>,
-        FullTransactionPool,
-        TestAuthor,
-        Block,
-    > {
+    ) -> Permastore<cc_datastore::PermanentStorage<TestChain>, FullTransactionPool, TestAuthor, Block>
+    {
         Permastore {
             storage: Arc::new(RwLock::new(cc_datastore::PermanentStorage::new_test(
-                self.client.clone(),
+                Arc::new(self.chain.clone()),
             ))),
             pool: self.pool.clone(),
             author: self.author(),
             deny_unsafe: DenyUnsafe::No,
+            backfill: None,
             phatom: PhantomData::<Block>,
         }
     }
@@ -264,3 +428,515 @@ fn should_remove_extrinsics() {
 
     assert_eq!(removed.len(), 3);
 }
+
+#[test]
+fn submitted_data_should_be_stored_under_its_chunk_root() {
+    let p = TestSetup::default().permastore();
+
+    let data = (0..cp_permastore::CHUNK_SIZE * 2 + 10)
+        .map(|i| i as u8)
+        .collect::<Vec<_>>();
+
+    let chunk_root = p.submit(data.clone().into()).unwrap();
+    assert_eq!(chunk_root, cp_permastore::chunk_root(&data));
+
+    let storage = p.storage.read();
+    let key = chunk_root.encode();
+    assert_eq!(storage.retrieve(&key), Some(data));
+
+    // The chunk hashes persisted by the datastore lead to the same chunk root.
+    let chunk_hashes = storage.chunk_store().chunk_hashes(&key).unwrap().unwrap();
+    assert_eq!(
+        cp_permastore::chunk_root_of_hashes(chunk_hashes),
+        chunk_root
+    );
+}
+
+fn chunk_proofs(data: &[u8]) -> Vec<(Bytes, Vec<Bytes>)> {
+    (0..data.len())
+        .step_by(CHUNK_SIZE as usize)
+        .map(|offset| {
+            let chunk_proof =
+                cc_consensus_poa::ChunkProofBuilder::new(data.to_vec(), CHUNK_SIZE, offset as u32)
+                    .build()
+                    .unwrap();
+            (
+                chunk_proof.chunk.into(),
+                chunk_proof.proof.into_iter().map(Into::into).collect(),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn submit_chunk_should_assemble_transaction_data() {
+    let p = TestSetup::default().permastore();
+
+    let data = cc_datastore::test_data(2, 10);
+    let data_size = data.len() as u64;
+    let chunk_root = cp_permastore::chunk_root(&data);
+
+    let mut chunk_proofs = chunk_proofs(&data);
+    let (last_chunk, last_proof) = chunk_proofs.pop().unwrap();
+
+    // Upload the chunks out of order.
+    assert_eq!(
+        p.submit_chunk(
+            chunk_root,
+            data_size,
+            2,
+            last_chunk.clone(),
+            last_proof.clone()
+        )
+        .unwrap(),
+        UploadStatus {
+            received_chunks: 1,
+            total_chunks: 3,
+            completed: false,
+        }
+    );
+    assert_matches!(
+        p.submit_chunk(chunk_root, data_size, 2, last_chunk, last_proof),
+        Err(Error::ChunkExists)
+    );
+    assert_eq!(p.retrieve(chunk_root.encode().into()).unwrap(), None);
+
+    let (chunk, proof) = chunk_proofs[0].clone();
+    assert_eq!(
+        p.submit_chunk(chunk_root, data_size, 0, chunk, proof)
+            .unwrap()
+            .received_chunks,
+        2
+    );
+
+    let (chunk, proof) = chunk_proofs[1].clone();
+    assert_eq!(
+        p.submit_chunk(chunk_root, data_size, 1, chunk.clone(), proof.clone())
+            .unwrap(),
+        UploadStatus {
+            received_chunks: 3,
+            total_chunks: 3,
+            completed: true,
+        }
+    );
+    assert_eq!(
+        p.retrieve(chunk_root.encode().into()).unwrap(),
+        Some(data.into())
+    );
+
+    assert_matches!(
+        p.submit_chunk(chunk_root, data_size, 1, chunk, proof),
+        Err(Error::DataExists)
+    );
+}
+
+#[test]
+fn submit_chunk_should_limit_pending_uploads() {
+    let p = TestSetup::default().permastore();
+
+    let upload_first_chunk = |seed: usize| {
+        let data = cc_datastore::seeded_test_data(1, 10, seed as u32);
+        let chunk_root = cp_permastore::chunk_root(&data);
+        let (chunk, proof) = chunk_proofs(&data)[0].clone();
+        p.submit_chunk(chunk_root, data.len() as u64, 0, chunk, proof)
+    };
+
+    for seed in 0..MAX_PENDING_UPLOADS {
+        assert!(upload_first_chunk(seed).is_ok());
+    }
+    assert_matches!(
+        upload_first_chunk(MAX_PENDING_UPLOADS),
+        Err(Error::TooManyPendingUploads(_))
+    );
+
+    // The pending uploads are still able to make progress.
+    assert_matches!(upload_first_chunk(0), Err(Error::ChunkExists));
+
+    // The abandoned uploads no longer count once they are expired.
+    let chunk_store = p.storage.read().chunk_store().clone();
+    assert_eq!(
+        chunk_store
+            .remove_expired_uploads(cc_datastore::unix_timestamp() + 1)
+            .unwrap(),
+        MAX_PENDING_UPLOADS
+    );
+    assert!(upload_first_chunk(MAX_PENDING_UPLOADS).is_ok());
+}
+
+#[test]
+fn submit_chunk_should_limit_pending_upload_bytes() {
+    let p = TestSetup::default().permastore();
+
+    // Only the first chunk is submitted, the declared data size is pinned until the
+    // upload completes or expires.
+    let upload_first_chunk = |seed: usize| {
+        let data = cc_datastore::seeded_test_data(1, 10, seed as u32);
+        let chunk_root = cp_permastore::chunk_root(&data);
+        let (chunk, proof) = chunk_proofs(&data)[0].clone();
+        p.submit_chunk(chunk_root, MAX_CHUNKED_UPLOAD_DATA_SIZE, 0, chunk, proof)
+    };
+
+    let max_uploads = (MAX_PENDING_UPLOAD_BYTES / MAX_CHUNKED_UPLOAD_DATA_SIZE) as usize;
+    for seed in 0..max_uploads {
+        assert!(upload_first_chunk(seed).is_ok());
+    }
+    assert_matches!(
+        upload_first_chunk(max_uploads),
+        Err(Error::PendingUploadsTooLarge { .. })
+    );
+}
+
+#[test]
+fn submit_chunk_should_reject_invalid_chunk() {
+    let p = TestSetup::default().permastore();
+
+    let data = cc_datastore::test_data(1, 10);
+    let data_size = data.len() as u64;
+    let chunk_root = cp_permastore::chunk_root(&data);
+    let chunk_proofs = chunk_proofs(&data);
+    let (chunk, proof) = chunk_proofs[0].clone();
+
+    assert_matches!(
+        p.submit_chunk(
+            chunk_root,
+            2 * 1024 * 1024 * 1024,
+            0,
+            chunk.clone(),
+            proof.clone()
+        ),
+        Err(Error::DataSizeTooLarge)
+    );
+    assert_matches!(
+        p.submit_chunk(chunk_root, data_size, 2, chunk.clone(), proof.clone()),
+        Err(Error::InvalidChunkIndex(_))
+    );
+    assert_matches!(
+        p.submit_chunk(chunk_root, data_size, 1, chunk.clone(), proof.clone()),
+        Err(Error::InvalidChunkSize { expected: 10, .. })
+    );
+    assert_matches!(
+        p.submit_chunk(
+            chunk_root,
+            data_size,
+            0,
+            chunk.clone(),
+            vec![vec![0u8; 64 * 1024 + 1].into()]
+        ),
+        Err(Error::DataPathTooLarge)
+    );
+
+    // The proof of chunk 0 does not prove any other chunk.
+    let mut forged_chunk = chunk.to_vec();
+    forged_chunk[0] ^= 1;
+    assert_matches!(
+        p.submit_chunk(chunk_root, data_size, 0, forged_chunk.into(), proof.clone()),
+        Err(Error::InvalidProof)
+    );
+    let (last_chunk, _) = chunk_proofs[1].clone();
+    assert_matches!(
+        p.submit_chunk(chunk_root, data_size, 1, last_chunk, proof),
+        Err(Error::InvalidProof)
+    );
+
+    // The chunks are consistent with `chunk_root`, but `data_size` declares fewer chunks.
+    let (chunk, proof) = chunk_proofs[0].clone();
+    assert_matches!(
+        p.submit_chunk(chunk_root, CHUNK_SIZE as u64, 0, chunk, proof),
+        Err(Error::InvalidProof)
+    );
+    assert_eq!(p.retrieve(chunk_root.encode().into()).unwrap(), None);
+    assert_eq!(
+        p.storage
+            .read()
+            .chunk_store()
+            .upload_progress(&chunk_root.encode())
+            .unwrap(),
+        None
+    );
+}
+
+#[test]
+fn retrieve_chunk_should_return_verifiable_chunk() {
+    let p = TestSetup::default().permastore();
+
+    let data = cc_datastore::test_data(2, 10);
+    let chunk_root = p.submit(data.clone().into()).unwrap();
+
+    for (chunk_index, expected) in data.chunks(CHUNK_SIZE as usize).enumerate() {
+        let chunk_index = chunk_index as u32;
+        let ChunkWithProof { chunk, proof, .. } =
+            p.retrieve_chunk(chunk_root, chunk_index).unwrap().unwrap();
+        assert_eq!(&*chunk, expected);
+        let proof = proof.into_iter().map(|node| node.0).collect::<Vec<_>>();
+        assert!(
+            cp_permastore::verify_chunk_proof(&chunk_root, &chunk, chunk_index, &proof).is_ok()
+        );
+    }
+
+    assert_matches!(
+        p.retrieve_chunk(chunk_root, 3),
+        Err(Error::InvalidChunkIndex(_))
+    );
+    assert_eq!(p.retrieve_chunk(H256::random(), 0).unwrap(), None);
+}
+
+#[test]
+fn retrieve_range_should_work() {
+    let p = TestSetup::default().permastore();
+
+    let data = cc_datastore::test_data(2, 10);
+    let chunk_root = p.submit(data.clone().into()).unwrap();
+
+    // Across the chunk boundaries.
+    let offset = CHUNK_SIZE as usize - 5;
+    let len = CHUNK_SIZE as usize + 10;
+    assert_eq!(
+        p.retrieve_range(chunk_root, offset as u64, len as u32)
+            .unwrap()
+            .map(|bytes| bytes.0),
+        Some(data[offset..offset + len].to_vec())
+    );
+
+    // Truncated at the end of data.
+    assert_eq!(
+        p.retrieve_range(chunk_root, data.len() as u64 - 3, 100)
+            .unwrap()
+            .map(|bytes| bytes.0),
+        Some(data[data.len() - 3..].to_vec())
+    );
+    assert_eq!(
+        p.retrieve_range(chunk_root, data.len() as u64, 100)
+            .unwrap()
+            .map(|bytes| bytes.0),
+        Some(Vec::new())
+    );
+
+    assert_matches!(
+        p.retrieve_range(chunk_root, 0, MAX_DOWNLOAD_DATA_SIZE + 1),
+        Err(Error::DataTooLarge(_))
+    );
+    assert_eq!(p.retrieve_range(H256::random(), 0, 100).unwrap(), None);
+}
+
+#[test]
+fn transaction_data_should_be_found_by_block_and_extrinsic_index() {
+    let setup = TestSetup::default();
+    let p = setup.permastore();
+
+    let data = cc_datastore::test_data(1, 10);
+    let chunk_root = p.submit(data.clone().into()).unwrap();
+    setup.chain.store(0, 1, chunk_root, data.len() as u32);
+
+    let genesis_hash = setup.client.info().genesis_hash;
+    for block in vec![
+        HashOrNumber::Hash(genesis_hash),
+        HashOrNumber::Number(0u64.into()),
+    ] {
+        assert_eq!(
+            p.transaction_data_info(block.clone(), 1).unwrap(),
+            Some(TransactionDataInfo {
+                chunk_root,
+                data_size: data.len() as u32,
+            })
+        );
+        assert_eq!(
+            p.transaction_data(block, 1).unwrap().map(|bytes| bytes.0),
+            Some(data.clone())
+        );
+    }
+
+    // No data is stored by the extrinsic.
+    assert_eq!(
+        p.transaction_data_info(HashOrNumber::Hash(genesis_hash), 0)
+            .unwrap(),
+        None
+    );
+    assert_eq!(
+        p.transaction_data(HashOrNumber::Hash(genesis_hash), 0)
+            .unwrap(),
+        None
+    );
+    // Unknown block.
+    assert_eq!(
+        p.transaction_data(HashOrNumber::Hash(H256::random()), 1)
+            .unwrap(),
+        None
+    );
+    assert_eq!(
+        p.transaction_data(HashOrNumber::Number(100u64.into()), 1)
+            .unwrap(),
+        None
+    );
+}
+
+#[test]
+fn transaction_data_should_be_resolved_on_the_chain_of_block_hash() {
+    use sc_block_builder::BlockBuilderProvider;
+    use sp_consensus::BlockOrigin;
+    use substrate_test_runtime_client::{BlockBuilderExt, ClientBlockImportExt};
+
+    let setup = TestSetup::default();
+    let p = setup.permastore();
+
+    // genesis <- a1 (best)
+    //         \
+    //          <- b1
+    let mut client = setup.client.clone();
+    let genesis_hash = client.info().genesis_hash;
+    let a1 = client
+        .new_block(Default::default())
+        .unwrap()
+        .build()
+        .unwrap()
+        .block;
+    executor::block_on(client.import(BlockOrigin::Own, a1.clone())).unwrap();
+    let mut builder = client
+        .new_block_at(&BlockId::Hash(genesis_hash), Default::default(), false)
+        .unwrap();
+    builder
+        .push_transfer(Transfer {
+            from: AccountKeyring::Alice.into(),
+            to: AccountKeyring::Bob.into(),
+            amount: 1,
+            nonce: 0,
+        })
+        .unwrap();
+    let b1 = builder.build().unwrap().block;
+    executor::block_on(client.import(BlockOrigin::Own, b1.clone())).unwrap();
+    assert_eq!(client.info().best_hash, a1.hash());
+
+    let data_a = vec![1u8; 100];
+    let chunk_root_a = p.submit(data_a.clone().into()).unwrap();
+    setup.chain.store(1, 1, chunk_root_a, data_a.len() as u32);
+
+    let data_b = vec![2u8; 200];
+    let chunk_root_b = p.submit(data_b.clone().into()).unwrap();
+    setup
+        .chain
+        .store_at(b1.hash(), 1, 1, chunk_root_b, data_b.len() as u32);
+
+    for (block, data) in vec![
+        (HashOrNumber::Number(1u64.into()), &data_a),
+        (HashOrNumber::Hash(a1.hash()), &data_a),
+        (HashOrNumber::Hash(b1.hash()), &data_b),
+    ] {
+        assert_eq!(
+            p.transaction_data_info(block.clone(), 1)
+                .unwrap()
+                .map(|info| info.data_size),
+            Some(data.len() as u32)
+        );
+        assert_eq!(
+            p.transaction_data(block, 1).unwrap().map(|bytes| bytes.0),
+            Some(data.clone())
+        );
+    }
+}
+
+#[test]
+fn transaction_data_should_reject_large_data() {
+    let setup = TestSetup::default();
+    let p = setup.permastore();
+
+    setup
+        .chain
+        .store(0, 1, H256::random(), MAX_DOWNLOAD_DATA_SIZE + 1);
+
+    assert_matches!(
+        p.transaction_data(HashOrNumber::Number(0u64.into()), 1),
+        Err(Error::DataTooLarge(_))
+    );
+    assert_eq!(
+        p.transaction_data_info(HashOrNumber::Number(0u64.into()), 1)
+            .unwrap()
+            .map(|info| info.data_size),
+        Some(MAX_DOWNLOAD_DATA_SIZE + 1)
+    );
+}
+
+#[test]
+fn backfill_status_should_be_reported_if_running() {
+    let p = TestSetup::default().permastore();
+    assert_eq!(p.backfill_status().unwrap(), None);
+
+    let p = p.with_backfill(SharedBackfillProgress::default());
+    assert_eq!(
+        p.backfill_status().unwrap(),
+        Some(BackfillStatus {
+            next_block: 0,
+            target_block: 0,
+            fetched_data: 0,
+            fetched_bytes: 0,
+            pending_data: 0,
+        })
+    );
+}
+
+#[test]
+fn data_notifications_should_be_extracted_from_events() {
+    use cc_rpc_api::permastore::DataNotification;
+    use frame_system::Phase;
+
+    use super::mock::{Event, Test};
+    use super::subscription::{event_notifications, EventRecord};
+
+    let setup = TestSetup::default();
+    let p = setup.permastore();
+
+    let data = b"stored data".to_vec();
+    let chunk_root = p.submit(data.clone().into()).unwrap();
+    setup.chain.store(0, 1, chunk_root, data.len() as u32);
+
+    let forgotten_chunk_root = H256::random();
+    setup.chain.store(0, 2, forgotten_chunk_root, 100);
+
+    let record = |phase, event| EventRecord::<Test> {
+        phase,
+        event,
+        topics: vec![],
+    };
+    let events = vec![
+        record(
+            Phase::ApplyExtrinsic(1),
+            Event::Permastore(pallet_permastore::Event::Stored(7, chunk_root)),
+        ),
+        // Only the events of extrinsics are notified.
+        record(
+            Phase::Initialization,
+            Event::Permastore(pallet_permastore::Event::Stored(8, chunk_root)),
+        ),
+        record(
+            Phase::ApplyExtrinsic(2),
+            Event::System(frame_system::Event::ExtrinsicSuccess(Default::default())),
+        ),
+        record(
+            Phase::ApplyExtrinsic(3),
+            Event::Permastore(pallet_permastore::Event::Forgot(0, 2)),
+        ),
+    ];
+
+    let block_hash = setup.client.info().genesis_hash;
+    let storage = p.storage.read();
+    assert_eq!(
+        event_notifications::<Block, _, _, Test>(&setup.chain, &*storage, block_hash, events)
+            .unwrap(),
+        vec![
+            DataNotification::Stored {
+                block_hash,
+                extrinsic_index: 1,
+                sender: 7,
+                chunk_root,
+                data_size: data.len() as u32,
+                stored_locally: true,
+            },
+            DataNotification::Forgotten {
+                block_hash,
+                extrinsic_index: 3,
+                stored_block_number: 0u64.into(),
+                stored_extrinsic_index: 2,
+                chunk_root: Some(forgotten_chunk_root),
+                stored_locally: false,
+            },
+        ]
+    );
+}
@@ -0,0 +1,82 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// This file is part of Canyon.
+//
+// Copyright (c) 2021 Canyon Labs.
+//
+// Canyon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// Canyon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Canyon. If not, see <http://www.gnu.org/licenses/>.
+
+#[cfg(test)]
+mod tests;
+
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use codec::Codec;
+
+use sp_api::ProvideRuntimeApi;
+use sp_blockchain::HeaderBackend;
+use sp_runtime::{generic::BlockId, traits::Block as BlockT, Permill};
+
+use cc_rpc_api::poa::{
+    error::{Error, Result},
+    PoaApi,
+};
+use cp_poa::PoaApi as PoaRuntimeApi;
+
+/// Provides the RPC methods of PoA consensus.
+pub struct Poa<C, B> {
+    /// Shared reference to the client.
+    client: Arc<C>,
+    /// Block.
+    phatom: PhantomData<B>,
+}
+
+impl<C, B> Poa<C, B> {
+    pub fn new(client: Arc<C>) -> Self {
+        Self {
+            client,
+            phatom: PhantomData::<B>,
+        }
+    }
+}
+
+impl<C, B, AccountId> PoaApi<<B as BlockT>::Hash, AccountId> for Poa<C, B>
+where
+    B: BlockT,
+    C: ProvideRuntimeApi<B> + HeaderBackend<B> + Send + Sync + 'static,
+    C::Api: PoaRuntimeApi<B, AccountId>,
+    AccountId: Codec,
+{
+    fn storage_capacity(
+        &self,
+        who: AccountId,
+        at: Option<<B as BlockT>::Hash>,
+    ) -> Result<Option<Permill>> {
+        let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+
+        self.client
+            .runtime_api()
+            .storage_capacity(&at, who)
+            .map_err(|e| Error::RuntimeApiError(e.to_string()))
+    }
+
+    fn all_capacities(&self, at: Option<<B as BlockT>::Hash>) -> Result<Vec<(AccountId, Permill)>> {
+        let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+
+        self.client
+            .runtime_api()
+            .all_capacities(&at)
+            .map_err(|e| Error::RuntimeApiError(e.to_string()))
+    }
+}
@@ -0,0 +1,123 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// This file is part of Canyon.
+//
+// Copyright (c) 2021 Canyon Labs.
+//
+// Canyon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// Canyon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Canyon. If not, see <http://www.gnu.org/licenses/>.
+
+use super::*;
+
+use std::collections::BTreeMap;
+
+use sp_api::{ApiRef, ProvideRuntimeApi};
+use sp_blockchain::{BlockStatus, Info};
+use sp_runtime::traits::NumberFor;
+use substrate_test_runtime_client::{
+    runtime::{Block, Header},
+    Backend, Client, DefaultTestClientBuilderExt, TestClientBuilderExt,
+};
+
+use cp_poa::PoaConfiguration;
+
+#[derive(Clone)]
+struct TestApi {
+    client: Arc<Client<Backend>>,
+    capacities: BTreeMap<u64, Permill>,
+}
+
+impl TestApi {
+    fn new(capacities: Vec<(u64, Permill)>) -> Self {
+        Self {
+            client: Arc::new(substrate_test_runtime_client::new()),
+            capacities: capacities.into_iter().collect(),
+        }
+    }
+}
+
+struct RuntimeApi {
+    inner: TestApi,
+}
+
+impl ProvideRuntimeApi<Block> for TestApi {
+    type Api = RuntimeApi;
+
+    fn runtime_api<'a>(&'a self) -> ApiRef<'a, Self::Api> {
+        RuntimeApi {
+            inner: self.clone(),
+        }
+        .into()
+    }
+}
+
+sp_api::mock_impl_runtime_apis! {
+    impl PoaRuntimeApi<Block, u64> for RuntimeApi {
+        fn poa_config() -> PoaConfiguration {
+            Default::default()
+        }
+
+        fn storage_capacity(&self, who: u64) -> Option<Permill> {
+            self.inner.capacities.get(&who).copied()
+        }
+
+        fn all_capacities(&self) -> Vec<(u64, Permill)> {
+            self.inner.capacities.clone().into_iter().collect()
+        }
+    }
+}
+
+impl HeaderBackend<Block> for TestApi {
+    fn header(&self, id: BlockId<Block>) -> sp_blockchain::Result<Option<Header>> {
+        self.client.header(id)
+    }
+
+    fn info(&self) -> Info<Block> {
+        self.client.info()
+    }
+
+    fn status(&self, id: BlockId<Block>) -> sp_blockchain::Result<BlockStatus> {
+        self.client.status(id)
+    }
+
+    fn number(
+        &self,
+        hash: <Block as BlockT>::Hash,
+    ) -> sp_blockchain::Result<Option<NumberFor<Block>>> {
+        self.client.number(hash)
+    }
+
+    fn hash(
+        &self,
+        number: NumberFor<Block>,
+    ) -> sp_blockchain::Result<Option<<Block as BlockT>::Hash>> {
+        self.client.hash(number)
+    }
+}
+
+#[test]
+fn storage_capacity_should_work() {
+    let poa = Poa::<_, Block>::new(Arc::new(TestApi::new(vec![
+        (1, Permill::from_percent(50)),
+        (2, Permill::one()),
+    ])));
+
+    assert_eq!(
+        poa.storage_capacity(1, None).unwrap(),
+        Some(Permill::from_percent(50))
+    );
+    assert_eq!(poa.storage_capacity(3, None).unwrap(), None);
+    assert_eq!(
+        poa.all_capacities(None).unwrap(),
+        vec![(1, Permill::from_percent(50)), (2, Permill::one())]
+    );
+}
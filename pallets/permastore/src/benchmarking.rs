@@ -5,7 +5,9 @@ use frame_support::traits::Currency;
 use frame_system::RawOrigin;
 use sp_runtime::traits::Hash;
 
-use crate::{Call, Config, Pallet};
+use sp_runtime::Perbill;
+
+use crate::{Call, Config, Pallet, StoragePricing};
 
 benchmarks! {
     store {
@@ -35,6 +37,17 @@ benchmarks! {
     verify {
         // TODO
     }
+
+    set_pricing {
+        let new = StoragePricing {
+            base_cost_per_byte: 100u32.into(),
+            cost_decline_rate: Perbill::from_percent(1),
+            discount_rate: Perbill::from_percent(2),
+        };
+    }: set_pricing (RawOrigin::Root, new)
+    verify {
+        assert_eq!(new, Pallet::<T>::pricing());
+    }
 }
 
 impl_benchmark_test_suite!(Pallet, crate::mock::new_test_ext(), crate::mock::Test);
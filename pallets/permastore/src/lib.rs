@@ -22,12 +22,17 @@
 //! onto the network. It also records some information necessary for
 //! the PoA consensus on chain.
 //!
+//! The perpetual storage fees are accumulated in an endowment account,
+//! a portion of which is released to the block author every block in
+//! proportion to the storage capacity it has proven via PoA.
+//!
 //! ## Interface
 //!
 //! ### Dispatchable Functions
 //!
 //! * `store`: Make an order of storing data.
-//! * `forget`: Unimplemented.
+//! * `forget`: Forget the stored data, a time-decaying portion of the storage fee is refunded.
+//! * `set_pricing`: Set new parameters of the perpetual storage pricing model.
 //!
 //! ### Public Functions
 //!
@@ -47,8 +52,16 @@ use codec::{Decode, Encode};
 use scale_info::TypeInfo;
 
 use sp_runtime::{
-    traits::{AccountIdConversion, DispatchInfoOf, SaturatedConversion, SignedExtension},
-    transaction_validity::{InvalidTransaction, TransactionValidity, TransactionValidityError},
+    helpers_128bit::multiply_by_rational,
+    traits::{
+        AccountIdConversion, AtLeast32BitUnsigned, DispatchInfoOf, SaturatedConversion, Saturating,
+        SignedExtension, Zero,
+    },
+    transaction_validity::{
+        InvalidTransaction, TransactionPriority, TransactionValidity, TransactionValidityError,
+        ValidTransaction,
+    },
+    Perbill, Permill, RuntimeDebug,
 };
 use sp_std::{marker::PhantomData, prelude::*};
 
@@ -61,6 +74,7 @@ use frame_system::ensure_signed;
 
 #[cfg(any(feature = "runtime-benchmarks", test))]
 mod benchmarking;
+pub mod migrations;
 #[cfg(all(feature = "std", test))]
 mod mock;
 #[cfg(all(feature = "std", test))]
@@ -77,6 +91,60 @@ pub type BalanceOf<T> =
 
 type ExtrinsicIndex = u32;
 
+/// Trait for providing the storage capacity of validators proven by PoA.
+pub trait StorageCapacity<AccountId> {
+    /// Returns the author of current block.
+    fn author() -> AccountId;
+
+    /// Returns the estimated storage capacity of `who`.
+    ///
+    /// `None` if `who` has never authored a block with a valid proof of access.
+    fn storage_capacity(who: &AccountId) -> Option<Permill>;
+}
+
+/// Parameters of the perpetual storage pricing model.
+///
+/// The data is paid to be stored forever upfront, the total storage cost
+/// is the sum of the discounted storage cost of each period, under the
+/// assumption that the storage cost keeps declining over time:
+///
+/// ```text
+///   storage_fee = sum(base_cost * data_size * ((1 - decline) / (1 + discount)) ^ t), t = 0..inf
+///               = base_cost * data_size * (1 + discount) / (decline + discount)
+/// ```
+#[derive(RuntimeDebug, Clone, Copy, Eq, PartialEq, Encode, Decode, TypeInfo)]
+pub struct StoragePricing<Balance> {
+    /// Cost of storing one byte for one period.
+    pub base_cost_per_byte: Balance,
+    /// Expected decline rate of the storage cost per period.
+    pub cost_decline_rate: Perbill,
+    /// Discount rate per period.
+    pub discount_rate: Perbill,
+}
+
+impl<Balance: AtLeast32BitUnsigned + Copy> StoragePricing<Balance> {
+    /// Returns `true` if the storage fee converges.
+    pub fn check_sanity(&self) -> bool {
+        !self.cost_decline_rate.is_zero() || !self.discount_rate.is_zero()
+    }
+
+    /// Returns the perpetual storage fee of `data_size` bytes.
+    pub fn storage_fee(&self, data_size: u32) -> Balance {
+        let one_period_cost = self
+            .base_cost_per_byte
+            .saturating_mul(data_size.into())
+            .saturated_into::<u128>();
+
+        let discount = self.discount_rate.deconstruct() as u128;
+        let decline = self.cost_decline_rate.deconstruct() as u128;
+        let one = Perbill::one().deconstruct() as u128;
+
+        multiply_by_rational(one_period_cost, one + discount, decline + discount)
+            .unwrap_or(u128::MAX)
+            .saturated_into()
+    }
+}
+
 #[frame_support::pallet]
 pub mod pallet {
     use super::*;
@@ -85,6 +153,7 @@ pub mod pallet {
         traits::{Currency, Get},
         PalletId,
     };
+    use frame_system::ensure_root;
     use frame_system::pallet_prelude::{BlockNumberFor, OriginFor};
 
     #[pallet::config]
@@ -95,18 +164,52 @@ pub mod pallet {
         /// The native currency.
         type Currency: Currency<Self::AccountId>;
 
-        /// The treasury pallet id.
-        type TreasuryPalletId: Get<PalletId>;
+        /// The endowment pallet id, used for deriving the account holding the storage fees.
+        #[pallet::constant]
+        type PalletId: Get<PalletId>;
+
+        /// Portion of the endowment released as the storage reward every block.
+        #[pallet::constant]
+        type EndowmentRelease: Get<Perbill>;
+
+        /// Storage capacity of the block authors.
+        type StorageCapacity: StorageCapacity<Self::AccountId>;
+
+        /// Number of blocks in which the storage fee is refundable once the data is forgotten.
+        ///
+        /// The refund decays linearly to zero over this period.
+        #[pallet::constant]
+        type RefundPeriod: Get<Self::BlockNumber>;
+
+        /// Maximum portion of the storage fee refunded once the data is forgotten.
+        #[pallet::constant]
+        type MaxRefund: Get<Perbill>;
 
         /// Maximum of a transaction data in bytes.
         type MaxDataSize: Get<u32>;
 
+        /// Default cost of storing one byte for one period.
+        #[pallet::constant]
+        type BaseStorageCost: Get<BalanceOf<Self>>;
+
+        /// Default decline rate of the storage cost per period.
+        #[pallet::constant]
+        type StorageCostDeclineRate: Get<Perbill>;
+
+        /// Default discount rate per period.
+        #[pallet::constant]
+        type DiscountRate: Get<Perbill>;
+
         /// Weight information for extrinsics in this pallet.
         type WeightInfo: WeightInfo;
     }
 
+    /// The current storage version.
+    const STORAGE_VERSION: StorageVersion = StorageVersion::new(3);
+
     #[pallet::pallet]
     #[pallet::generate_store(pub(super) trait Store)]
+    #[pallet::storage_version(STORAGE_VERSION)]
     pub struct Pallet<T>(_);
 
     #[pallet::hooks]
@@ -114,15 +217,23 @@ pub mod pallet {
         fn on_initialize(_n: BlockNumberFor<T>) -> Weight {
             // Clear the block size of last block.
             <BlockDataSize<T>>::kill();
-            1
+            // Rewarding the block author in `on_finalize`.
+            1 + T::DbWeight::get().reads_writes(4, 2)
         }
 
         fn on_finalize(n: BlockNumberFor<T>) {
             if <BlockDataSize<T>>::get() > 0 {
                 let latest_weave_size = <WeaveSize<T>>::get();
-                <GlobalWeaveSizeIndex<T>>::append(latest_weave_size);
-                <GlobalBlockNumberIndex<T>>::append(n);
+                let index = <WeaveIndexLen<T>>::get();
+                <WeaveIndex<T>>::insert(index, (latest_weave_size, n));
+                <WeaveIndexLen<T>>::put(index + 1);
             }
+
+            Self::reward_block_author();
+        }
+
+        fn on_runtime_upgrade() -> Weight {
+            migrations::migrate::<T>()
         }
     }
 
@@ -133,6 +244,10 @@ pub mod pallet {
         /// The minimum data size is 1 bytes, the maximum is `MAX_DATA_SIZE`.
         /// The digest of data will be recorded on chain, the actual data has
         /// to be stored off-chain before executing this extrinsic.
+        ///
+        /// NOTE: The existence of transaction data is checked by [`CheckStore`]
+        /// in the transaction pool, the dispatch itself never touches the local
+        /// storage of node.
         #[pallet::weight(T::WeightInfo::store())]
         pub fn store(origin: OriginFor<T>, data_size: u32, chunk_root: T::Hash) -> DispatchResult {
             let sender = ensure_signed(origin)?;
@@ -141,7 +256,6 @@ pub mod pallet {
                 data_size > 0 && data_size < T::MaxDataSize::get(),
                 Error::<T>::InvalidDataSize
             );
-            ensure!(Self::stored_locally(&chunk_root), Error::<T>::NotStored);
 
             // TODO: ensure the validity of stored data in the local DB?
 
@@ -155,6 +269,7 @@ pub mod pallet {
             // FIXME: store these info in db directly.
             ChunkRootIndex::<T>::insert((block_number, extrinsic_index), chunk_root);
             TransactionDataSize::<T>::insert((block_number, extrinsic_index), data_size);
+            <WeaveOffsets<T>>::append(block_number, (extrinsic_index, <WeaveSize<T>>::get()));
 
             <BlockDataSize<T>>::mutate(|s| *s += data_size as u64);
             <WeaveSize<T>>::mutate(|s| *s += data_size as u64);
@@ -179,16 +294,36 @@ pub mod pallet {
             let sender = ensure_signed(origin)?;
 
             // Remove the order.
-            let _fee = Orders::<T>::take(&sender, (block_number, extrinsic_index))
+            let fee = Orders::<T>::take(&sender, (block_number, extrinsic_index))
                 .ok_or(Error::<T>::OrderDoesNotExist)?;
 
+            // Exclude the data from the PoA recall selection.
+            Forgotten::<T>::insert((block_number, extrinsic_index), ());
+
             // refund the remaining fee.
-            Self::refund_storage_fee(&sender, block_number);
+            Self::refund_storage_fee(&sender, block_number, fee);
 
             Self::deposit_event(Event::Forgot(block_number, extrinsic_index));
 
             Ok(())
         }
+
+        /// Set new parameters of the perpetual storage pricing model.
+        #[pallet::weight(T::WeightInfo::set_pricing())]
+        pub fn set_pricing(
+            origin: OriginFor<T>,
+            new: StoragePricing<BalanceOf<T>>,
+        ) -> DispatchResult {
+            ensure_root(origin)?;
+
+            ensure!(new.check_sanity(), Error::<T>::InvalidPricing);
+
+            Pricing::<T>::put(new);
+
+            Self::deposit_event(Event::PricingUpdated(new));
+
+            Ok(())
+        }
     }
 
     /// Event for the Permastore pallet.
@@ -199,6 +334,12 @@ pub mod pallet {
         Stored(T::AccountId, T::Hash),
         /// The data has been forgotten. [block_number, extrinsic_index]
         Forgot(T::BlockNumber, ExtrinsicIndex),
+        /// New parameters of the storage pricing model. [pricing]
+        PricingUpdated(StoragePricing<BalanceOf<T>>),
+        /// The block author has been rewarded for the storage. [who, amount]
+        Rewarded(T::AccountId, BalanceOf<T>),
+        /// The storage fee has been partially refunded. [who, amount]
+        Refunded(T::AccountId, BalanceOf<T>),
     }
 
     /// Error for the Permastore pallet.
@@ -206,10 +347,10 @@ pub mod pallet {
     pub enum Error<T> {
         /// The valid range of data size is (0, MAX_DATA_SIZE).
         InvalidDataSize,
-        /// The transaction data has not been stored locally.
-        NotStored,
         /// The storage order does not exist.
         OrderDoesNotExist,
+        /// The storage pricing failed the sanity checks.
+        InvalidPricing,
     }
 
     /// Map of all the storage orders.
@@ -224,6 +365,39 @@ pub mod pallet {
         BalanceOf<T>,
     >;
 
+    #[pallet::genesis_config]
+    #[derive(Default)]
+    pub struct GenesisConfig;
+
+    #[pallet::genesis_build]
+    impl<T: Config> GenesisBuild<T> for GenesisConfig {
+        fn build(&self) {
+            <Pallet<T>>::ensure_endowment_account();
+        }
+    }
+
+    #[pallet::type_value]
+    pub(super) fn DefaultPricing<T: Config>() -> StoragePricing<BalanceOf<T>> {
+        StoragePricing {
+            base_cost_per_byte: T::BaseStorageCost::get(),
+            cost_decline_rate: T::StorageCostDeclineRate::get(),
+            discount_rate: T::DiscountRate::get(),
+        }
+    }
+
+    /// Parameters of the perpetual storage pricing model.
+    #[pallet::storage]
+    #[pallet::getter(fn pricing)]
+    pub type Pricing<T: Config> =
+        StorageValue<_, StoragePricing<BalanceOf<T>>, ValueQuery, DefaultPricing<T>>;
+
+    /// Set of the forgotten transaction data.
+    ///
+    /// (block_number, extrinsic_index) => ()
+    #[pallet::storage]
+    pub(super) type Forgotten<T: Config> =
+        StorageMap<_, Twox64Concat, (T::BlockNumber, ExtrinsicIndex), ()>;
+
     /// Total byte size of data stored onto the network so far.
     ///
     /// In another word, it equals to the sum of [`BlockDataSize`]
@@ -248,18 +422,28 @@ pub mod pallet {
     pub(super) type TransactionDataSize<T: Config> =
         StorageMap<_, Twox64Concat, (T::BlockNumber, ExtrinsicIndex), u32, ValueQuery>;
 
-    /// FIXME: find a proper way to store these info.
+    /// Weave size before each transaction stored in the block, in the order of
+    /// extrinsic index.
     ///
-    /// Temp solution for locating the recall block. An ever increasing array of global weave size.
+    /// block_number => [(extrinsic_index, tx_weave_base)]
     #[pallet::storage]
-    #[pallet::getter(fn global_block_size_index)]
-    pub(super) type GlobalWeaveSizeIndex<T: Config> = StorageValue<_, Vec<u64>, ValueQuery>;
+    pub(super) type WeaveOffsets<T: Config> =
+        StorageMap<_, Twox64Concat, T::BlockNumber, Vec<(ExtrinsicIndex, u64)>, ValueQuery>;
 
-    /// Temp solution for locating the recall block.
+    /// Index for locating the recall block.
+    ///
+    /// An ever increasing list of `(weave_size, block_number)` of the blocks
+    /// in which some data was stored, which is sorted by the weave size, each
+    /// entry is stored individually so that the recall block can be found by
+    /// a binary search with one storage read per step.
+    ///
+    /// index => (weave_size, block_number)
+    #[pallet::storage]
+    pub(super) type WeaveIndex<T: Config> = StorageMap<_, Twox64Concat, u32, (u64, T::BlockNumber)>;
+
+    /// Number of entries in [`WeaveIndex`].
     #[pallet::storage]
-    #[pallet::getter(fn global_block_number_index)]
-    pub(super) type GlobalBlockNumberIndex<T: Config> =
-        StorageValue<_, Vec<T::BlockNumber>, ValueQuery>;
+    pub(super) type WeaveIndexLen<T: Config> = StorageValue<_, u32, ValueQuery>;
 }
 
 impl<T: Config> Pallet<T> {
@@ -269,26 +453,50 @@ impl<T: Config> Pallet<T> {
     }
 
     /// Returns the block number in which the recall byte is included.
+    ///
+    /// The recall byte is in `[0, weave_size)`, the recall block is the first
+    /// block in [`WeaveIndex`] of which the weave size is greater than `recall_byte`.
     pub fn find_recall_block(recall_byte: u64) -> Option<T::BlockNumber> {
-        frame_support::log::debug!(
-            target: "runtime::permastore",
-            "Global weave size list: {:?}",
-            <GlobalBlockNumberIndex<T>>::get()
-                .iter()
-                .zip(<GlobalWeaveSizeIndex<T>>::get().iter())
-                .collect::<Vec<_>>()
-        );
-        let weave_size_list = <GlobalWeaveSizeIndex<T>>::get();
-
-        let recall_block_number_index =
-            match weave_size_list.binary_search_by_key(&recall_byte, |&weave_size| weave_size) {
-                Ok(i) => i,
-                Err(i) => i,
-            };
-
-        <GlobalBlockNumberIndex<T>>::get()
-            .get(recall_block_number_index)
-            .copied()
+        let weave_size_at = |index: u32| {
+            <WeaveIndex<T>>::get(index)
+                .map(|(weave_size, _)| weave_size)
+                .unwrap_or_else(|| {
+                    panic!(
+                        "WeaveIndex entry must exist as index {} < WeaveIndexLen; qed",
+                        index
+                    )
+                })
+        };
+
+        let (mut low, mut high) = (0u32, <WeaveIndexLen<T>>::get());
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if weave_size_at(mid) <= recall_byte {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+
+        <WeaveIndex<T>>::get(low).map(|(_, block_number)| block_number)
+    }
+
+    /// Returns `(block_number, extrinsic_index, tx_weave_base)` of the transaction
+    /// in which the recall byte is included.
+    ///
+    /// Within the recall block, it's the last transaction of which the weave base
+    /// is not greater than `recall_byte`, i.e., `tx_weave_base <= recall_byte < tx_weave_ceil`.
+    pub fn locate_recall_tx(recall_byte: u64) -> Option<(T::BlockNumber, u32, u64)> {
+        let block_number = Self::find_recall_block(recall_byte)?;
+
+        let offsets = <WeaveOffsets<T>>::get(block_number);
+        let index = offsets
+            .partition_point(|&(_, tx_weave_base)| tx_weave_base <= recall_byte)
+            .saturating_sub(1);
+
+        offsets
+            .get(index)
+            .map(|&(extrinsic_index, tx_weave_base)| (block_number, extrinsic_index, tx_weave_base))
     }
 
     /// Returns the data size of transaction given `block_number` and `extrinsic_index`.
@@ -296,6 +504,17 @@ impl<T: Config> Pallet<T> {
         <TransactionDataSize<T>>::get((block_number, extrinsic_index))
     }
 
+    /// Returns `[(extrinsic_index, tx_weave_base)]` of the transactions storing data in
+    /// block `block_number`.
+    pub fn weave_offsets(block_number: T::BlockNumber) -> Vec<(u32, u64)> {
+        <WeaveOffsets<T>>::get(block_number)
+    }
+
+    /// Returns `true` if the transaction data has been forgotten.
+    pub fn is_forgotten(block_number: T::BlockNumber, extrinsic_index: u32) -> bool {
+        <Forgotten<T>>::contains_key((block_number, extrinsic_index))
+    }
+
     /// Returns true if poa proof should be included and verified.
     pub fn require_proof_of_access() -> bool {
         <BlockDataSize<T>>::get() > 0 || <WeaveSize<T>>::get() > 0
@@ -311,40 +530,161 @@ impl<T: Config> Pallet<T> {
         <WeaveSize<T>>::get()
     }
 
-    // TODO: ensure the transaction data has been indeed stored in the local DB.
-    fn stored_locally(_chunk_root: &T::Hash) -> bool {
-        true
+    /// Returns `true` if the transaction data of `chunk_root` has been stored locally.
+    ///
+    /// This is only meaningful in the transaction pool validation, see
+    /// [`cp_permastore::permastore::stored_locally`].
+    fn stored_locally(chunk_root: &T::Hash) -> bool {
+        cp_permastore::permastore::stored_locally(chunk_root.as_ref())
     }
 
-    // TODO: calculate the perpetual storage cost based on the data size.
-    fn calculate_storage_fee(data_size: u32) -> BalanceOf<T> {
-        data_size.saturated_into()
+    /// Returns the perpetual storage fee of `data_size` bytes.
+    pub fn calculate_storage_fee(data_size: u32) -> BalanceOf<T> {
+        Self::pricing().storage_fee(data_size)
     }
 
-    /// Charges the perpetual storage fee.
+    /// Returns the account of endowment.
+    pub fn endowment_account() -> T::AccountId {
+        T::PalletId::get().into_account()
+    }
+
+    /// Creates the endowment account by funding it with the existential deposit if
+    /// it does not exist, so that the storage fees below the existential deposit
+    /// can be transferred to it.
     ///
-    /// TODO: Currently all the fee is simply transfered to the treasury,
-    /// we might want a new destination for that.
+    /// Returns `true` if the endowment account has been funded.
+    pub fn ensure_endowment_account() -> bool {
+        let account_id = Self::endowment_account();
+        let min = T::Currency::minimum_balance();
+        if T::Currency::free_balance(&account_id) < min {
+            let _ = T::Currency::make_free_balance_be(&account_id, min);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns the amount of endowment, excluding the existential deposit.
+    pub fn endowment() -> BalanceOf<T> {
+        T::Currency::free_balance(&Self::endowment_account())
+            .saturating_sub(T::Currency::minimum_balance())
+    }
+
+    /// Charges the perpetual storage fee, which is transferred to the endowment.
     fn charge_storage_fee(
         who: &T::AccountId,
         data_size: u32,
     ) -> Result<BalanceOf<T>, sp_runtime::DispatchError> {
         let fee = Self::calculate_storage_fee(data_size);
-        let treasury_account: T::AccountId = T::TreasuryPalletId::get().into_account();
-        T::Currency::transfer(who, &treasury_account, fee, ExistenceRequirement::KeepAlive)?;
+        T::Currency::transfer(
+            who,
+            &Self::endowment_account(),
+            fee,
+            ExistenceRequirement::KeepAlive,
+        )?;
         Ok(fee)
     }
 
-    fn refund_storage_fee(_who: &T::AccountId, _created_at: T::BlockNumber) {}
+    /// Rewards the block author from the endowment.
+    ///
+    /// The released endowment of each block is weighted by the storage capacity
+    /// of block author, the rest remains in the endowment.
+    fn reward_block_author() {
+        let author = T::StorageCapacity::author();
+
+        let storage_capacity = match T::StorageCapacity::storage_capacity(&author) {
+            Some(capacity) if !capacity.is_zero() => capacity,
+            _ => return,
+        };
+
+        let reward = storage_capacity * (T::EndowmentRelease::get() * Self::endowment());
+
+        if reward.is_zero() {
+            return;
+        }
+
+        match T::Currency::transfer(
+            &Self::endowment_account(),
+            &author,
+            reward,
+            ExistenceRequirement::KeepAlive,
+        ) {
+            Ok(()) => Self::deposit_event(Event::Rewarded(author, reward)),
+            Err(e) => frame_support::log::error!(
+                target: "runtime::permastore",
+                "Failed to reward the block author: {:?}",
+                e,
+            ),
+        }
+    }
+
+    /// Returns the refund of a storage order created at `created_at` with `fee` paid.
+    ///
+    /// ```text
+    ///   refund = fee * max_refund * (1 - elapsed / refund_period)
+    /// ```
+    pub fn calculate_refund(created_at: T::BlockNumber, fee: BalanceOf<T>) -> BalanceOf<T> {
+        let elapsed = frame_system::Pallet::<T>::block_number().saturating_sub(created_at);
+        let refund_period = T::RefundPeriod::get();
+
+        if elapsed >= refund_period {
+            return Zero::zero();
+        }
+
+        let remaining = Perbill::from_rational(refund_period - elapsed, refund_period);
+
+        remaining * (T::MaxRefund::get() * fee)
+    }
+
+    /// Refunds a time-decaying portion of the storage fee from the endowment.
+    fn refund_storage_fee(who: &T::AccountId, created_at: T::BlockNumber, fee: BalanceOf<T>) {
+        let refund = Self::calculate_refund(created_at, fee).min(Self::endowment());
+
+        if refund.is_zero() {
+            return;
+        }
+
+        match T::Currency::transfer(
+            &Self::endowment_account(),
+            who,
+            refund,
+            ExistenceRequirement::KeepAlive,
+        ) {
+            Ok(()) => Self::deposit_event(Event::Refunded(who.clone(), refund)),
+            Err(e) => frame_support::log::error!(
+                target: "runtime::permastore",
+                "Failed to refund the storage fee: {:?}",
+                e,
+            ),
+        }
+    }
 }
 
 /// A signed extension that checks for the `store` call.
 ///
-/// It ensures the transaction data has been stored locally.
+/// It ensures the transaction data has been stored locally and the sender
+/// is able to afford the perpetual storage fee.
+///
+/// The `(chunk_root, data_size)` of the `store` call is committed to by the
+/// signature over the call itself, thus neither the extension nor its
+/// `AdditionalSigned` carries any payload.
 #[derive(Encode, Decode, Clone, Eq, PartialEq, TypeInfo)]
 #[scale_info(skip_type_params(T))]
 pub struct CheckStore<T: Config + Send + Sync>(PhantomData<T>);
 
+impl<T: Config + Send + Sync> CheckStore<T> {
+    /// Creates a new instance of [`CheckStore`].
+    pub fn new() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<T: Config + Send + Sync> Default for CheckStore<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<T: Config + Send + Sync> sp_std::fmt::Debug for CheckStore<T> {
     fn fmt(&self, f: &mut sp_std::fmt::Formatter) -> sp_std::fmt::Result {
         write!(f, "CheckStore")
@@ -358,7 +698,6 @@ where
     const IDENTIFIER: &'static str = "CheckStore";
     type AccountId = T::AccountId;
     type Call = <T as frame_system::Config>::Call;
-    // TODO: Sign the chunk root
     type AdditionalSigned = ();
     type Pre = ();
 
@@ -376,27 +715,27 @@ where
         if let Some(Call::store {
             data_size,
             chunk_root,
-            ..
         }) = call.is_sub_type()
         {
-            // TODO:
-            //
-            // 1. Check the balance is enough to pay the storage fee according to the data size.
-            //
-            // 2. Check if the data has been stored locally.
-            //
-            // 3. Adjust the transaction priority according to the data size.
+            let (chunk_root, data_size) = (*chunk_root, *data_size);
 
             ensure!(
-                T::Currency::free_balance(who) >= Pallet::<T>::calculate_storage_fee(*data_size),
+                T::Currency::free_balance(who) >= Pallet::<T>::calculate_storage_fee(data_size),
                 InvalidTransaction::Payment
             );
 
             const DATA_NOT_STORED: u8 = 100;
             ensure!(
-                Pallet::<T>::stored_locally(chunk_root),
+                Pallet::<T>::stored_locally(&chunk_root),
                 InvalidTransaction::Custom(DATA_NOT_STORED)
             );
+
+            // The smaller the data is, the higher the priority is, as the
+            // larger data is more expensive to propagate and include.
+            return Ok(ValidTransaction {
+                priority: T::MaxDataSize::get().saturating_sub(data_size) as TransactionPriority,
+                ..Default::default()
+            });
         }
 
         Ok(Default::default())
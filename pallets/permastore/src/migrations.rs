@@ -0,0 +1,185 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// This file is part of Canyon.
+//
+// Copyright (c) 2021 Canyon Labs.
+//
+// Canyon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// Canyon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Canyon. If not, see <http://www.gnu.org/licenses/>.
+
+//! Storage migrations for the Permastore pallet.
+
+use frame_support::{
+    storage::migration::take_storage_value,
+    traits::{Get, PalletInfoAccess, StorageVersion},
+    weights::Weight,
+};
+use sp_std::{collections::btree_map::BTreeMap, prelude::*};
+
+use crate::{Config, Pallet, TransactionDataSize, WeaveIndex, WeaveIndexLen, WeaveOffsets};
+
+/// Applies all the pending migrations.
+///
+/// The migrations are applied in a single block, which is required as the recall
+/// index is read by the PoA consensus in every block. The migrations to v1 and v2
+/// are linear in the size of weave, see their worst-case weights, a warning is
+/// logged if the total weight exceeds the maximum block weight.
+pub fn migrate<T: Config>() -> Weight {
+    let mut weight = T::DbWeight::get().reads(1);
+
+    if StorageVersion::get::<Pallet<T>>() < 1 {
+        weight += v1::migrate::<T>();
+    }
+
+    if StorageVersion::get::<Pallet<T>>() < 2 {
+        weight += v2::migrate::<T>();
+    }
+
+    if StorageVersion::get::<Pallet<T>>() < 3 {
+        weight += v3::migrate::<T>();
+    }
+
+    let max_block = T::BlockWeights::get().max_block;
+    if weight > max_block {
+        frame_support::log::warn!(
+            target: "runtime::permastore",
+            "Migration weight {} exceeds the maximum block weight {}",
+            weight,
+            max_block,
+        );
+    }
+
+    weight
+}
+
+/// Migration to the storage version 1.
+pub mod v1 {
+    use super::*;
+
+    /// Moves the recall block index from the two ever growing vectors
+    /// `GlobalWeaveSizeIndex` and `GlobalBlockNumberIndex` to [`WeaveIndex`].
+    ///
+    /// The weight is `2` reads and `n + 4` writes, `n` being the number of blocks
+    /// storing data. With `RocksDbWeight` (25µs per read, 100µs per write), it stays
+    /// within the 2s block weight of the Canyon runtime for up to about 20,000 blocks.
+    pub fn migrate<T: Config>() -> Weight {
+        let pallet_name = <Pallet<T> as PalletInfoAccess>::name().as_bytes();
+
+        let weave_sizes = take_storage_value::<Vec<u64>>(pallet_name, b"GlobalWeaveSizeIndex", &[])
+            .unwrap_or_default();
+        let block_numbers =
+            take_storage_value::<Vec<T::BlockNumber>>(pallet_name, b"GlobalBlockNumberIndex", &[])
+                .unwrap_or_default();
+
+        let mut len = 0u32;
+        for entry in weave_sizes.into_iter().zip(block_numbers.into_iter()) {
+            <WeaveIndex<T>>::insert(len, entry);
+            len += 1;
+        }
+        <WeaveIndexLen<T>>::put(len);
+
+        StorageVersion::new(1).put::<Pallet<T>>();
+
+        frame_support::log::info!(
+            target: "runtime::permastore",
+            "Migrated {} entries to WeaveIndex",
+            len,
+        );
+
+        T::DbWeight::get().reads_writes(2, len as Weight + 4)
+    }
+}
+
+/// Migration to the storage version 2.
+pub mod v2 {
+    use super::*;
+
+    /// Backfills [`WeaveOffsets`] from [`TransactionDataSize`] and [`WeaveIndex`].
+    ///
+    /// The weight is `t + n + 1` reads and `n + 1` writes, `t` being the number of
+    /// transactions storing data and `n` the number of blocks storing data. With
+    /// `RocksDbWeight`, it stays within the 2s block weight of the Canyon runtime for
+    /// up to about 13,000 blocks of one transaction each.
+    pub fn migrate<T: Config>() -> Weight {
+        let mut reads = 1u64;
+        let mut writes = 1u64;
+
+        let mut block_transactions = BTreeMap::<T::BlockNumber, Vec<(u32, u32)>>::new();
+        for ((block_number, extrinsic_index), data_size) in <TransactionDataSize<T>>::iter() {
+            block_transactions
+                .entry(block_number)
+                .or_default()
+                .push((extrinsic_index, data_size));
+            reads += 1;
+        }
+
+        let mut block_weave_base = 0u64;
+        for index in 0..<WeaveIndexLen<T>>::get() {
+            reads += 1;
+
+            let (weave_size, block_number) = match <WeaveIndex<T>>::get(index) {
+                Some(entry) => entry,
+                None => break,
+            };
+
+            if let Some(mut transactions) = block_transactions.remove(&block_number) {
+                transactions.sort_unstable();
+
+                let mut tx_weave_base = block_weave_base;
+                let offsets = transactions
+                    .into_iter()
+                    .map(|(extrinsic_index, data_size)| {
+                        let offset = (extrinsic_index, tx_weave_base);
+                        tx_weave_base += data_size as u64;
+                        offset
+                    })
+                    .collect::<Vec<_>>();
+
+                <WeaveOffsets<T>>::insert(block_number, offsets);
+                writes += 1;
+            }
+
+            block_weave_base = weave_size;
+        }
+
+        StorageVersion::new(2).put::<Pallet<T>>();
+
+        frame_support::log::info!(
+            target: "runtime::permastore",
+            "Backfilled WeaveOffsets of {} blocks",
+            writes - 1,
+        );
+
+        T::DbWeight::get().reads_writes(reads as Weight, writes as Weight)
+    }
+}
+
+/// Migration to the storage version 3.
+pub mod v3 {
+    use super::*;
+
+    /// Funds the endowment account, which is only created in the genesis of the
+    /// chains launched after the endowment is introduced.
+    pub fn migrate<T: Config>() -> Weight {
+        if Pallet::<T>::ensure_endowment_account() {
+            frame_support::log::info!(
+                target: "runtime::permastore",
+                "Funded the endowment account {:?}",
+                Pallet::<T>::endowment_account(),
+            );
+        }
+
+        StorageVersion::new(3).put::<Pallet<T>>();
+
+        T::DbWeight::get().reads_writes(1, 2)
+    }
+}
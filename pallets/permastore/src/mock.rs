@@ -18,6 +18,8 @@
 
 use super::*;
 
+use std::{cell::RefCell, collections::HashMap};
+
 use frame_support::{parameter_types, traits::Everything, PalletId};
 use sp_core::H256;
 // The testing primitives are very useful for avoiding having to work with signatures
@@ -25,7 +27,7 @@ use sp_core::H256;
 use sp_runtime::{
     testing::Header,
     traits::{BlakeTwo256, IdentityLookup},
-    BuildStorage,
+    BuildStorage, Perbill,
 };
 // Reexport crate as its pallet name for construct_runtime.
 use crate as pallet_permastore;
@@ -42,7 +44,7 @@ frame_support::construct_runtime!(
     {
         System: frame_system::{Pallet, Call, Config, Storage, Event<T>},
         Balances: pallet_balances::{Pallet, Call, Storage, Config<T>, Event<T>},
-        Permastore: pallet_permastore::{Pallet, Call, Storage, Event<T>},
+        Permastore: pallet_permastore::{Pallet, Call, Storage, Config, Event<T>},
     }
 );
 
@@ -93,17 +95,58 @@ impl pallet_balances::Config for Test {
     type WeightInfo = ();
 }
 parameter_types! {
-    pub const TreasuryPalletId: PalletId = PalletId(*b"py/trsry");
+    pub const PermastorePalletId: PalletId = PalletId(*b"py/prmst");
+    pub const EndowmentRelease: Perbill = Perbill::from_percent(1);
+    pub const RefundPeriod: u64 = 100;
+    pub const MaxRefund: Perbill = Perbill::from_percent(50);
     pub const MaxDataSize: u32 = 1024 * 1024 * 1024;
+    pub const BaseStorageCost: u64 = 1;
+    pub const StorageCostDeclineRate: Perbill = Perbill::one();
+    pub const DiscountRate: Perbill = Perbill::zero();
 }
 impl Config for Test {
     type Event = Event;
     type Currency = Balances;
-    type TreasuryPalletId = TreasuryPalletId;
+    type PalletId = PermastorePalletId;
+    type EndowmentRelease = EndowmentRelease;
+    type StorageCapacity = TestStorageCapacity;
+    type RefundPeriod = RefundPeriod;
+    type MaxRefund = MaxRefund;
     type MaxDataSize = MaxDataSize;
+    type BaseStorageCost = BaseStorageCost;
+    type StorageCostDeclineRate = StorageCostDeclineRate;
+    type DiscountRate = DiscountRate;
     type WeightInfo = ();
 }
 
+thread_local! {
+    static AUTHOR: RefCell<u64> = RefCell::new(0);
+    static CAPACITIES: RefCell<HashMap<u64, Permill>> = RefCell::new(HashMap::new());
+}
+
+/// Storage capacity of validators for testing.
+pub struct TestStorageCapacity;
+
+impl TestStorageCapacity {
+    pub fn set_author(author: u64) {
+        AUTHOR.with(|a| *a.borrow_mut() = author);
+    }
+
+    pub fn set_capacity(who: u64, capacity: Permill) {
+        CAPACITIES.with(|c| c.borrow_mut().insert(who, capacity));
+    }
+}
+
+impl StorageCapacity<u64> for TestStorageCapacity {
+    fn author() -> u64 {
+        AUTHOR.with(|a| *a.borrow())
+    }
+
+    fn storage_capacity(who: &u64) -> Option<Permill> {
+        CAPACITIES.with(|c| c.borrow().get(who).copied())
+    }
+}
+
 // This function basically just builds a genesis storage key/value store according to
 // our desired mockup.
 pub fn new_test_ext() -> sp_io::TestExternalities {
@@ -111,6 +154,7 @@ pub fn new_test_ext() -> sp_io::TestExternalities {
         // We use default for brevity, but you can configure as desired if needed.
         system: Default::default(),
         balances: Default::default(),
+        permastore: Default::default(),
     }
     .build_storage()
     .unwrap();
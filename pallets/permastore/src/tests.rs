@@ -16,10 +16,24 @@
 // You should have received a copy of the GNU General Public License
 // along with Canyon. If not, see <http://www.gnu.org/licenses/>.
 
-use frame_support::traits::OnFinalize;
+use std::collections::HashMap;
+
+use frame_support::{
+    assert_noop, assert_ok,
+    storage::migration::{get_storage_value, put_storage_value},
+    traits::{Currency, OnFinalize, OnInitialize, OnRuntimeUpgrade, StorageVersion},
+    weights::DispatchInfo,
+};
+use sp_core::H256;
+use sp_runtime::{
+    traits::SignedExtension, transaction_validity::InvalidTransaction, DispatchError, Perbill,
+    Permill,
+};
+
+use cp_permastore::{PermaStorage, PermastoreExt};
 
 use crate::{
-    mock::{new_test_ext, Permastore, Test},
+    mock::{self, new_test_ext, Balances, Origin, Permastore, System, Test, TestStorageCapacity},
     *,
 };
 
@@ -48,8 +62,383 @@ fn find_recall_block_should_work() {
         <Permastore as OnFinalize<u64>>::on_finalize(10);
 
         assert_eq!(Pallet::<Test>::find_recall_block(3), Some(1));
-        assert_eq!(Pallet::<Test>::find_recall_block(12), Some(4));
+        assert_eq!(Pallet::<Test>::find_recall_block(5), Some(4));
+        assert_eq!(Pallet::<Test>::find_recall_block(11), Some(4));
+        assert_eq!(Pallet::<Test>::find_recall_block(12), Some(10));
+        assert_eq!(Pallet::<Test>::find_recall_block(21), Some(10));
+        assert_eq!(Pallet::<Test>::find_recall_block(22), None);
+    });
+}
+
+#[test]
+fn migrate_to_v1_should_work() {
+    new_test_ext().execute_with(|| {
+        StorageVersion::new(0).put::<Permastore>();
+
+        put_storage_value(
+            b"Permastore",
+            b"GlobalWeaveSizeIndex",
+            &[],
+            vec![5u64, 12, 22],
+        );
+        put_storage_value(
+            b"Permastore",
+            b"GlobalBlockNumberIndex",
+            &[],
+            vec![1u64, 4, 10],
+        );
+
+        migrations::v1::migrate::<Test>();
+
+        assert_eq!(StorageVersion::get::<Permastore>(), 1);
+        assert!(
+            get_storage_value::<Vec<u64>>(b"Permastore", b"GlobalWeaveSizeIndex", &[]).is_none()
+        );
+        assert!(
+            get_storage_value::<Vec<u64>>(b"Permastore", b"GlobalBlockNumberIndex", &[]).is_none()
+        );
+
+        assert_eq!(WeaveIndexLen::<Test>::get(), 3);
+        assert_eq!(WeaveIndex::<Test>::get(1), Some((12, 4)));
+        assert_eq!(Pallet::<Test>::find_recall_block(3), Some(1));
         assert_eq!(Pallet::<Test>::find_recall_block(13), Some(10));
-        assert_eq!(Pallet::<Test>::find_recall_block(15), Some(10));
+
+        // Nothing happens once migrated.
+        <Permastore as OnRuntimeUpgrade>::on_runtime_upgrade();
+        assert_eq!(WeaveIndexLen::<Test>::get(), 3);
+    });
+}
+
+fn store_transactions(transactions: Vec<(u64, Vec<(u32, u32)>)>) {
+    let _ = Balances::deposit_creating(&1, 1000);
+
+    for (block_number, extrinsics) in transactions {
+        System::set_block_number(block_number);
+        <Permastore as OnInitialize<u64>>::on_initialize(block_number);
+
+        for (extrinsic_index, data_size) in extrinsics {
+            System::set_extrinsic_index(extrinsic_index);
+            assert_ok!(Permastore::store(
+                Origin::signed(1),
+                data_size,
+                H256::repeat_byte(extrinsic_index as u8)
+            ));
+        }
+
+        <Permastore as OnFinalize<u64>>::on_finalize(block_number);
+    }
+}
+
+#[test]
+fn locate_recall_tx_should_work() {
+    new_test_ext().execute_with(|| {
+        // Weave: [0, 5) (1, 1), [5, 12) (1, 3), [12, 22) (2, 1)
+        store_transactions(vec![(1, vec![(1, 5), (3, 7)]), (2, vec![(1, 10)])]);
+
+        assert_eq!(Permastore::locate_recall_tx(0), Some((1, 1, 0)));
+        assert_eq!(Permastore::locate_recall_tx(4), Some((1, 1, 0)));
+        assert_eq!(Permastore::locate_recall_tx(5), Some((1, 3, 5)));
+        assert_eq!(Permastore::locate_recall_tx(11), Some((1, 3, 5)));
+        assert_eq!(Permastore::locate_recall_tx(12), Some((2, 1, 12)));
+        assert_eq!(Permastore::locate_recall_tx(21), Some((2, 1, 12)));
+        assert_eq!(Permastore::locate_recall_tx(22), None);
+    });
+}
+
+#[test]
+fn migrate_to_v2_should_work() {
+    new_test_ext().execute_with(|| {
+        store_transactions(vec![(1, vec![(1, 5), (3, 7)]), (2, vec![(1, 10)])]);
+
+        let offsets = (WeaveOffsets::<Test>::get(1), WeaveOffsets::<Test>::get(2));
+        assert_eq!(offsets, (vec![(1, 0), (3, 5)], vec![(1, 12)]));
+
+        WeaveOffsets::<Test>::remove(1);
+        WeaveOffsets::<Test>::remove(2);
+        StorageVersion::new(1).put::<Permastore>();
+
+        <Permastore as OnRuntimeUpgrade>::on_runtime_upgrade();
+
+        assert_eq!(StorageVersion::get::<Permastore>(), 3);
+        assert_eq!(
+            (WeaveOffsets::<Test>::get(1), WeaveOffsets::<Test>::get(2)),
+            offsets
+        );
+        assert_eq!(Permastore::locate_recall_tx(6), Some((1, 3, 5)));
+    });
+}
+
+#[test]
+fn migrate_to_v3_should_fund_endowment_account() {
+    new_test_ext().execute_with(|| {
+        let endowment_account = Permastore::endowment_account();
+        let _ = Balances::make_free_balance_be(&endowment_account, 0);
+        StorageVersion::new(2).put::<Permastore>();
+
+        <Permastore as OnRuntimeUpgrade>::on_runtime_upgrade();
+
+        assert_eq!(StorageVersion::get::<Permastore>(), 3);
+        assert_eq!(
+            Balances::free_balance(&endowment_account),
+            Balances::minimum_balance()
+        );
+        assert_eq!(Permastore::endowment(), 0);
+    });
+}
+
+#[derive(Default)]
+struct MockStorage(HashMap<Vec<u8>, Vec<u8>>);
+
+impl PermaStorage for MockStorage {
+    fn submit(&mut self, key: &[u8], value: &[u8]) {
+        self.0.insert(key.to_vec(), value.to_vec());
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        self.0.remove(key);
+    }
+
+    fn retrieve(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.0.get(key).cloned()
+    }
+}
+
+#[test]
+fn stored_locally_should_work() {
+    let stored = H256::repeat_byte(1);
+    let missing = H256::repeat_byte(2);
+
+    // Always `true` without the permastore extension, e.g., in block import.
+    new_test_ext().execute_with(|| {
+        assert!(Pallet::<Test>::stored_locally(&stored));
+        assert!(Pallet::<Test>::stored_locally(&missing));
+    });
+
+    let mut storage = MockStorage::default();
+    storage.submit(stored.as_ref(), b"transaction data");
+
+    let mut ext = new_test_ext();
+    ext.register_extension(PermastoreExt::new(storage));
+    ext.execute_with(|| {
+        assert!(Pallet::<Test>::stored_locally(&stored));
+        assert!(!Pallet::<Test>::stored_locally(&missing));
+    });
+}
+
+#[test]
+fn check_store_should_work() {
+    new_test_ext().execute_with(|| {
+        let chunk_root = H256::repeat_byte(1);
+        let store: mock::Call = Call::store {
+            data_size: 100,
+            chunk_root,
+        }
+        .into();
+        let remark: mock::Call = frame_system::Call::remark { remark: vec![] }.into();
+        let info = DispatchInfo::default();
+
+        // Unable to afford the storage fee.
+        assert_eq!(
+            CheckStore::<Test>::new().validate(&1, &store, &info, 0),
+            Err(InvalidTransaction::Payment.into())
+        );
+
+        let _ = Balances::deposit_creating(&1, 1000);
+
+        // Smaller data has a higher priority.
+        let valid = CheckStore::<Test>::new()
+            .validate(&1, &store, &info, 0)
+            .unwrap();
+        assert_eq!(valid.priority, (mock::MaxDataSize::get() - 100) as u64);
+
+        // Nothing to check for the other calls.
+        assert_eq!(
+            CheckStore::<Test>::new().validate(&1, &remark, &info, 0),
+            Ok(Default::default())
+        );
+
+        // No payload is added to the extrinsic.
+        assert!(CheckStore::<Test>::new().encode().is_empty());
+    });
+}
+
+#[test]
+fn storage_fee_should_work() {
+    let pricing = StoragePricing {
+        base_cost_per_byte: 10u64,
+        cost_decline_rate: Perbill::from_percent(10),
+        discount_rate: Perbill::from_percent(10),
+    };
+    // 10 * 100 * (1 + 0.1) / (0.1 + 0.1)
+    assert_eq!(pricing.storage_fee(100), 5500);
+
+    // No discount, the fee is only determined by the declining cost.
+    let pricing = StoragePricing {
+        base_cost_per_byte: 10u64,
+        cost_decline_rate: Perbill::from_percent(50),
+        discount_rate: Perbill::zero(),
+    };
+    assert_eq!(pricing.storage_fee(100), 2000);
+
+    // Saturated instead of overflow.
+    let pricing = StoragePricing {
+        base_cost_per_byte: u64::MAX,
+        cost_decline_rate: Perbill::from_percent(1),
+        discount_rate: Perbill::zero(),
+    };
+    assert_eq!(pricing.storage_fee(100), u64::MAX);
+
+    // The fee never converges.
+    let pricing = StoragePricing {
+        base_cost_per_byte: 10u64,
+        cost_decline_rate: Perbill::zero(),
+        discount_rate: Perbill::zero(),
+    };
+    assert!(!pricing.check_sanity());
+}
+
+#[test]
+fn set_pricing_should_work() {
+    new_test_ext().execute_with(|| {
+        // Defaults to the parameters in `Config`.
+        assert_eq!(Permastore::calculate_storage_fee(100), 100);
+
+        let new = StoragePricing {
+            base_cost_per_byte: 10u64,
+            cost_decline_rate: Perbill::from_percent(10),
+            discount_rate: Perbill::from_percent(10),
+        };
+
+        assert_noop!(
+            Permastore::set_pricing(Origin::signed(1), new),
+            DispatchError::BadOrigin
+        );
+        assert_noop!(
+            Permastore::set_pricing(
+                Origin::root(),
+                StoragePricing {
+                    cost_decline_rate: Perbill::zero(),
+                    discount_rate: Perbill::zero(),
+                    ..new
+                }
+            ),
+            Error::<Test>::InvalidPricing
+        );
+
+        assert_ok!(Permastore::set_pricing(Origin::root(), new));
+        assert_eq!(Permastore::pricing(), new);
+        assert_eq!(Permastore::calculate_storage_fee(100), 5500);
+    });
+}
+
+#[test]
+fn storage_fee_should_go_to_endowment() {
+    new_test_ext().execute_with(|| {
+        // The endowment account is created in genesis.
+        assert_eq!(Balances::free_balance(Permastore::endowment_account()), 1);
+        assert_eq!(Permastore::endowment(), 0);
+
+        let _ = Balances::deposit_creating(&1, 1000);
+        assert_ok!(Permastore::store(
+            Origin::signed(1),
+            100,
+            H256::repeat_byte(1)
+        ));
+
+        assert_eq!(Balances::free_balance(1), 900);
+        assert_eq!(Permastore::endowment(), 100);
+    });
+}
+
+#[test]
+fn reward_block_author_should_work() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+
+        let _ = Balances::deposit_creating(&Permastore::endowment_account(), 10_000);
+        TestStorageCapacity::set_author(10);
+
+        // No reward if the author has never proven its storage.
+        <Permastore as OnFinalize<u64>>::on_finalize(1);
+        assert_eq!(Balances::free_balance(10), 0);
+        assert_eq!(Permastore::endowment(), 10_000);
+
+        TestStorageCapacity::set_capacity(10, Permill::from_percent(50));
+
+        // 1% of the endowment is released per block, weighted by the storage capacity.
+        <Permastore as OnFinalize<u64>>::on_finalize(1);
+        assert_eq!(Balances::free_balance(10), 50);
+        assert_eq!(Permastore::endowment(), 10_000 - 50);
+        System::assert_last_event(mock::Event::Permastore(crate::Event::<Test>::Rewarded(
+            10, 50,
+        )));
+    });
+}
+
+#[test]
+fn calculate_refund_should_work() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(10);
+        // 50% of the fee is refunded at most.
+        assert_eq!(Permastore::calculate_refund(10, 1000), 500);
+
+        System::set_block_number(60);
+        assert_eq!(Permastore::calculate_refund(10, 1000), 250);
+
+        System::set_block_number(109);
+        assert_eq!(Permastore::calculate_refund(10, 1000), 5);
+
+        // Nothing is refundable once the refund period has elapsed.
+        System::set_block_number(110);
+        assert_eq!(Permastore::calculate_refund(10, 1000), 0);
+        System::set_block_number(200);
+        assert_eq!(Permastore::calculate_refund(10, 1000), 0);
+    });
+}
+
+#[test]
+fn forget_should_refund_storage_fee() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(1);
+
+        let _ = Balances::deposit_creating(&1, 1000);
+        assert_ok!(Permastore::store(
+            Origin::signed(1),
+            100,
+            H256::repeat_byte(1)
+        ));
+        System::set_block_number(2);
+        assert_ok!(Permastore::store(
+            Origin::signed(1),
+            100,
+            H256::repeat_byte(2)
+        ));
+        assert_eq!(Balances::free_balance(1), 800);
+
+        assert_noop!(
+            Permastore::forget(Origin::signed(2), 1, 0),
+            Error::<Test>::OrderDoesNotExist
+        );
+
+        System::set_block_number(51);
+        assert!(!Permastore::is_forgotten(1, 0));
+        assert_ok!(Permastore::forget(Origin::signed(1), 1, 0));
+        assert!(Permastore::is_forgotten(1, 0));
+        assert!(!Permastore::is_forgotten(2, 0));
+        assert_eq!(Balances::free_balance(1), 825);
+        System::assert_has_event(mock::Event::Permastore(crate::Event::<Test>::Refunded(
+            1, 25,
+        )));
+        assert!(Permastore::orders(1, (1, 0)).is_none());
+
+        assert_noop!(
+            Permastore::forget(Origin::signed(1), 1, 0),
+            Error::<Test>::OrderDoesNotExist
+        );
+
+        // No refund after the refund period.
+        System::set_block_number(102);
+        assert_ok!(Permastore::forget(Origin::signed(1), 2, 0));
+        assert_eq!(Balances::free_balance(1), 825);
     });
 }
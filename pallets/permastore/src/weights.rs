@@ -54,6 +54,7 @@ use sp_std::marker::PhantomData;
 pub trait WeightInfo {
     fn store() -> Weight;
     fn forget() -> Weight;
+    fn set_pricing() -> Weight;
 }
 
 /// Weights for pallet_permastore using the Substrate node and recommended hardware.
@@ -65,10 +66,11 @@ impl<T: frame_system::Config> WeightInfo for SubstrateWeight<T> {
     // Storage: Permastore BlockDataSize (r:1 w:1)
     // Storage: Permastore WeaveSize (r:1 w:1)
     // Storage: Permastore TransactionDataSize (r:0 w:1)
+    // Storage: Permastore WeaveOffsets (r:1 w:1)
     fn store() -> Weight {
         (64_430_000 as Weight)
-            .saturating_add(T::DbWeight::get().reads(3 as Weight))
-            .saturating_add(T::DbWeight::get().writes(5 as Weight))
+            .saturating_add(T::DbWeight::get().reads(4 as Weight))
+            .saturating_add(T::DbWeight::get().writes(6 as Weight))
     }
     // Storage: Permastore Orders (r:1 w:1)
     fn forget() -> Weight {
@@ -76,6 +78,10 @@ impl<T: frame_system::Config> WeightInfo for SubstrateWeight<T> {
             .saturating_add(T::DbWeight::get().reads(1 as Weight))
             .saturating_add(T::DbWeight::get().writes(1 as Weight))
     }
+    // Storage: Permastore Pricing (r:0 w:1)
+    fn set_pricing() -> Weight {
+        (16_902_000 as Weight).saturating_add(T::DbWeight::get().writes(1 as Weight))
+    }
 }
 
 // For backwards compatibility and tests
@@ -86,10 +92,11 @@ impl WeightInfo for () {
     // Storage: Permastore BlockDataSize (r:1 w:1)
     // Storage: Permastore WeaveSize (r:1 w:1)
     // Storage: Permastore TransactionDataSize (r:0 w:1)
+    // Storage: Permastore WeaveOffsets (r:1 w:1)
     fn store() -> Weight {
         (64_430_000 as Weight)
-            .saturating_add(RocksDbWeight::get().reads(3 as Weight))
-            .saturating_add(RocksDbWeight::get().writes(5 as Weight))
+            .saturating_add(RocksDbWeight::get().reads(4 as Weight))
+            .saturating_add(RocksDbWeight::get().writes(6 as Weight))
     }
     // Storage: Permastore Orders (r:1 w:1)
     fn forget() -> Weight {
@@ -97,4 +104,8 @@ impl WeightInfo for () {
             .saturating_add(RocksDbWeight::get().reads(1 as Weight))
             .saturating_add(RocksDbWeight::get().writes(1 as Weight))
     }
+    // Storage: Permastore Pricing (r:0 w:1)
+    fn set_pricing() -> Weight {
+        (16_902_000 as Weight).saturating_add(RocksDbWeight::get().writes(1 as Weight))
+    }
 }
@@ -55,7 +55,8 @@ benchmarks! {
         let new = PoaConfiguration {
             max_depth: 1u32,
             max_tx_path: 100u32,
-            max_chunk_path: 100u32
+            max_chunk_path: 100u32,
+            mining_mode: Default::default(),
         };
     }: set_config (RawOrigin::Root, new.clone())
     verify {
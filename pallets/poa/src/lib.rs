@@ -35,6 +35,9 @@
 //! The Poa pallet creates the inherent extrinsic [`Call::deposit`]
 //! when the inherent data contains a valid [`POA_INHERENT_IDENTIFIER`],
 //! in which a new digest item will probably be deposited.
+//!
+//! In the [`MiningMode::Spora`] mode, the miner of [`ProofOfAccess`] must
+//! be the author of current block.
 
 // Ensure we're `no_std` when compiling for Wasm.
 #![cfg_attr(not(feature = "std"), no_std)]
@@ -57,10 +60,14 @@ use frame_support::{
 };
 
 use canyon_primitives::Depth;
-use cp_consensus_poa::{PoaConfiguration, PoaOutcome, POA_ENGINE_ID, POA_INHERENT_IDENTIFIER};
+use cp_consensus_poa::{
+    MiningMode, PoaConfiguration, PoaOutcome, ProofOfAccess, POA_ENGINE_ID, POA_ENGINE_ID_V2,
+    POA_INHERENT_IDENTIFIER,
+};
 
 #[cfg(any(feature = "runtime-benchmarks", test))]
 mod benchmarking;
+pub mod migrations;
 #[cfg(all(feature = "std", test))]
 mod mock;
 #[cfg(all(feature = "std", test))]
@@ -155,14 +162,22 @@ pub mod pallet {
         type WeightInfo: WeightInfo;
     }
 
+    /// The current storage version.
+    const STORAGE_VERSION: StorageVersion = StorageVersion::new(1);
+
     #[pallet::pallet]
     #[pallet::generate_store(pub(super) trait Store)]
     #[pallet::generate_storage_info]
+    #[pallet::storage_version(STORAGE_VERSION)]
     pub struct Pallet<T>(_);
 
     #[pallet::hooks]
     impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T> {
         fn on_finalize(_n: BlockNumberFor<T>) {}
+
+        fn on_runtime_upgrade() -> Weight {
+            migrations::migrate::<T>()
+        }
     }
 
     #[pallet::call]
@@ -175,20 +190,17 @@ pub mod pallet {
             ensure_none(origin)?;
 
             match poa_outcome {
+                PoaOutcome::LegacyJustification(legacy_poa) => {
+                    let seal = legacy_poa.encode();
+                    Self::note_poa(legacy_poa.into())?;
+                    <frame_system::Pallet<T>>::deposit_log(DigestItem::Seal(POA_ENGINE_ID, seal));
+                }
                 PoaOutcome::Justification(poa) => {
-                    poa.check_validity(&Self::poa_config()).map_err(|e| {
-                        frame_support::log::error!(
-                            target: "runtime::poa",
-                            "Checking poa validity failed when creating the poa `deposit` inherent: {:?}",
-                            e,
-                        );
-                        Error::<T>::InvalidProofOfAccess
-                    })?;
-
-                    Self::note_depth(poa.depth);
+                    let seal = poa.encode();
+                    Self::note_poa(poa)?;
                     <frame_system::Pallet<T>>::deposit_log(DigestItem::Seal(
-                        POA_ENGINE_ID,
-                        poa.encode(),
+                        POA_ENGINE_ID_V2,
+                        seal,
                     ));
                 }
                 PoaOutcome::MaxDepthReached(_) => {
@@ -247,15 +259,12 @@ pub mod pallet {
 
         fn check_inherent(call: &Self::Call, _: &InherentData) -> Result<(), Self::Error> {
             match call {
+                Call::deposit {
+                    poa_outcome: PoaOutcome::LegacyJustification(legacy_poa),
+                } => Self::check_inherent_poa(&legacy_poa.clone().into()),
                 Call::deposit {
                     poa_outcome: PoaOutcome::Justification(poa),
-                } => poa.check_validity(&Self::poa_config()).map_err(|e| {
-                    frame_support::log::error!(
-                        target: "runtime::poa",
-                        "Check inherent failed due to poa is invalid: {:?}", e,
-                    );
-                    InherentError::InvalidProofOfAccess.into()
-                }),
+                } => Self::check_inherent_poa(poa),
                 _ => Ok(()),
             }
         }
@@ -289,6 +298,8 @@ pub mod pallet {
         InvalidProofOfAccess,
         /// The poa configuration failed the sanity checks.
         InvalidPoaConfiguration,
+        /// The miner of `[ProofOfAccess]` is not the block author.
+        InvalidMiner,
     }
 
     /// Poa Configuration.
@@ -314,6 +325,59 @@ pub mod pallet {
     pub(super) type TestAuthor<T: Config> = StorageValue<_, T::AccountId, ValueQuery>;
 
     impl<T: Config> Pallet<T> {
+        /// Checks and notes the [`ProofOfAccess`] included in the `deposit` inherent.
+        fn note_poa(poa: ProofOfAccess) -> DispatchResult {
+            poa.check_validity(&Self::poa_config()).map_err(|e| {
+                frame_support::log::error!(
+                    target: "runtime::poa",
+                    "Checking poa validity failed when creating the poa `deposit` inherent: {:?}",
+                    e,
+                );
+                Error::<T>::InvalidProofOfAccess
+            })?;
+
+            ensure!(Self::check_miner(&poa), Error::<T>::InvalidMiner);
+
+            Self::note_depth(poa.depth);
+
+            Ok(())
+        }
+
+        /// Checks the validity of the [`ProofOfAccess`] in the `deposit` inherent.
+        fn check_inherent_poa(poa: &ProofOfAccess) -> Result<(), MakeFatalError<InherentError>> {
+            poa.check_validity(&Self::poa_config()).map_err(|e| {
+                frame_support::log::error!(
+                    target: "runtime::poa",
+                    "Check inherent failed due to poa is invalid: {:?}", e,
+                );
+                InherentError::InvalidProofOfAccess.into()
+            })
+        }
+
+        /// Returns `true` if the miner of `poa` is the block author in the SPoRA mode.
+        ///
+        /// Always `true` in the classic mode.
+        pub(crate) fn check_miner(poa: &ProofOfAccess) -> bool {
+            match Self::poa_config().mining_mode {
+                MiningMode::Classic => true,
+                MiningMode::Spora(_) => {
+                    poa.miner.as_deref() == Some(&T::BlockAuthor::author().encode()[..])
+                }
+            }
+        }
+
+        /// Returns the estimated storage capacity of `who`.
+        pub fn storage_capacity(who: &T::AccountId) -> Option<Permill> {
+            HistoryDepth::<T>::get(who).map(|depth_info| depth_info.as_storage_capacity())
+        }
+
+        /// Returns the estimated storage capacities of all the validators in [`HistoryDepth`].
+        pub fn all_capacities() -> Vec<(T::AccountId, Permill)> {
+            HistoryDepth::<T>::iter()
+                .map(|(who, depth_info)| (who, depth_info.as_storage_capacity()))
+                .collect()
+        }
+
         /// Updates the historical depth info of block author.
         pub(crate) fn note_depth(depth: Depth) {
             let block_author = T::BlockAuthor::author();
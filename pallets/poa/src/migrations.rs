@@ -0,0 +1,83 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+// This file is part of Canyon.
+//
+// Copyright (c) 2021 Canyon Labs.
+//
+// Canyon is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published
+// by the Free Software Foundation, either version 3 of the License,
+// or (at your option) any later version.
+//
+// Canyon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Canyon. If not, see <http://www.gnu.org/licenses/>.
+
+//! Storage migrations for the Poa pallet.
+
+use codec::{Decode, Encode};
+use frame_support::{
+    traits::{Get, StorageVersion},
+    weights::Weight,
+};
+
+use cp_consensus_poa::{MiningMode, PoaConfiguration};
+
+use crate::{Config, Pallet, PoaConfig};
+
+/// Applies all the pending migrations.
+pub fn migrate<T: Config>() -> Weight {
+    let mut weight = T::DbWeight::get().reads(1);
+
+    if StorageVersion::get::<Pallet<T>>() < 1 {
+        weight += v1::migrate::<T>();
+    }
+
+    weight
+}
+
+/// Migration to the storage version 1.
+pub mod v1 {
+    use super::*;
+
+    /// [`PoaConfiguration`] before the mining mode was introduced.
+    #[derive(Encode, Decode)]
+    pub struct OldPoaConfiguration {
+        pub max_depth: u32,
+        pub max_tx_path: u32,
+        pub max_chunk_path: u32,
+    }
+
+    /// Translates the stored [`PoaConfiguration`] into the new one using the
+    /// [`MiningMode::Classic`].
+    pub fn migrate<T: Config>() -> Weight {
+        let translated = <PoaConfig<T>>::translate::<OldPoaConfiguration, _>(|old| {
+            old.map(
+                |OldPoaConfiguration {
+                     max_depth,
+                     max_tx_path,
+                     max_chunk_path,
+                 }| PoaConfiguration {
+                    max_depth,
+                    max_tx_path,
+                    max_chunk_path,
+                    mining_mode: MiningMode::Classic,
+                },
+            )
+        });
+
+        if let Err(()) = translated {
+            frame_support::log::error!(
+                target: "runtime::poa",
+                "Failed to decode the old PoaConfiguration, the default one is used",
+            );
+        }
+
+        StorageVersion::new(1).put::<Pallet<T>>();
+
+        T::DbWeight::get().reads_writes(1, 2)
+    }
+}
@@ -16,20 +16,28 @@
 // You should have received a copy of the GNU General Public License
 // along with Canyon. If not, see <http://www.gnu.org/licenses/>.
 
+use codec::Encode;
+use frame_support::{
+    storage::migration::put_storage_value,
+    traits::{OnRuntimeUpgrade, StorageVersion},
+};
 use sc_block_builder::{BlockBuilder, RecordProof};
 use sp_blockchain::HeaderBackend;
 use sp_keyring::AccountKeyring::{Alice, Bob};
-use sp_runtime::traits::Block as BlockT;
+use sp_runtime::{traits::Block as BlockT, DigestItem, Permill};
 use substrate_test_runtime::{Block, Transfer};
 use substrate_test_runtime_client::{
     BlockBuilderExt, DefaultTestClientBuilderExt, TestClientBuilderExt,
 };
 
-use cc_consensus_poa::{build_extrinsic_proof, ChunkProof, ChunkProofBuilder};
+use cc_consensus_poa::{
+    build_extrinsic_proof, ChunkProof, ChunkProofBuilder, LegacyProofOfAccess, MiningMode,
+    PoaConfiguration, PoaOutcome, ProofOfAccess, SporaParams, POA_ENGINE_ID, POA_ENGINE_ID_V2,
+};
 use cp_permastore::CHUNK_SIZE;
 
-use crate::mock::{new_test_ext, Poa, Test};
-use crate::{DepthInfo, HistoryDepth, TestAuthor};
+use crate::mock::{new_test_ext, Origin, Poa, System, Test};
+use crate::{DepthInfo, HistoryDepth, PoaConfig, TestAuthor};
 
 fn generate_chunk_proof(data: Vec<u8>, offset: u32) -> ChunkProof {
     ChunkProofBuilder::new(data, CHUNK_SIZE, offset)
@@ -127,3 +135,130 @@ fn note_depth_should_work() {
         );
     });
 }
+
+#[test]
+fn check_miner_should_work() {
+    new_test_ext().execute_with(|| {
+        TestAuthor::<Test>::put(6);
+
+        let poa = ProofOfAccess::new(1, vec![], ChunkProof::new(vec![], vec![], 0));
+
+        // Miner is not checked in the classic mode.
+        assert!(Poa::check_miner(&poa));
+
+        PoaConfig::<Test>::put(PoaConfiguration {
+            mining_mode: MiningMode::Spora(SporaParams {
+                recall_range_size: 1024,
+                difficulty: 2,
+            }),
+            ..Default::default()
+        });
+
+        assert!(!Poa::check_miner(&poa));
+        assert!(!Poa::check_miner(&poa.clone().with_miner(8u64.encode())));
+        assert!(Poa::check_miner(&poa.with_miner(6u64.encode())));
+    });
+}
+
+#[test]
+fn deposit_should_seal_with_the_engine_id_of_poa_version() {
+    new_test_ext().execute_with(|| {
+        TestAuthor::<Test>::put(6);
+
+        let chunk_proof = ChunkProof::new(vec![], vec![], 0);
+
+        let legacy_poa = LegacyProofOfAccess {
+            depth: 1,
+            tx_path: vec![],
+            chunk_proof: chunk_proof.clone(),
+        };
+        assert!(Poa::deposit(
+            Origin::none(),
+            PoaOutcome::LegacyJustification(legacy_poa.clone())
+        )
+        .is_ok());
+
+        let poa = ProofOfAccess::new(2, vec![], chunk_proof);
+        assert!(Poa::deposit(Origin::none(), PoaOutcome::Justification(poa.clone())).is_ok());
+
+        assert_eq!(
+            System::digest().logs,
+            vec![
+                DigestItem::Seal(POA_ENGINE_ID, legacy_poa.encode()),
+                DigestItem::Seal(POA_ENGINE_ID_V2, poa.encode()),
+            ]
+        );
+        assert_eq!(
+            HistoryDepth::<Test>::get(&6).unwrap(),
+            DepthInfo {
+                total_depth: 3,
+                blocks: 2
+            }
+        );
+    });
+}
+
+#[test]
+fn legacy_poa_outcome_should_keep_the_old_encoding() {
+    let chunk_proof = ChunkProof::new(vec![vec![1]], vec![2], 3);
+    let legacy_poa = LegacyProofOfAccess {
+        depth: 1,
+        tx_path: vec![vec![4]],
+        chunk_proof: chunk_proof.clone(),
+    };
+
+    // `PoaOutcome::Justification(ProofOfAccess)` before the miner field was introduced.
+    let mut old_encoding = vec![2u8];
+    old_encoding.extend((1u32, vec![vec![4u8]], chunk_proof).encode());
+
+    assert_eq!(
+        PoaOutcome::LegacyJustification(legacy_poa).encode(),
+        old_encoding
+    );
+}
+
+#[test]
+fn migrate_to_v1_should_work() {
+    new_test_ext().execute_with(|| {
+        StorageVersion::new(0).put::<Poa>();
+
+        // (max_depth, max_tx_path, max_chunk_path)
+        put_storage_value(b"Poa", b"PoaConfig", &[], (10u32, 20u32, 30u32));
+
+        <Poa as OnRuntimeUpgrade>::on_runtime_upgrade();
+
+        assert_eq!(StorageVersion::get::<Poa>(), 1);
+        assert_eq!(
+            PoaConfig::<Test>::get(),
+            PoaConfiguration {
+                max_depth: 10,
+                max_tx_path: 20,
+                max_chunk_path: 30,
+                mining_mode: MiningMode::Classic,
+            }
+        );
+    });
+}
+
+#[test]
+fn storage_capacity_should_work() {
+    new_test_ext().execute_with(|| {
+        assert_eq!(Poa::storage_capacity(&6), None);
+
+        TestAuthor::<Test>::put(6);
+        Poa::note_depth(1);
+        Poa::note_depth(3);
+
+        TestAuthor::<Test>::put(8);
+        Poa::note_depth(1);
+
+        assert_eq!(Poa::storage_capacity(&6), Some(Permill::from_percent(50)));
+
+        let mut capacities = Poa::all_capacities();
+        capacities.sort();
+        assert_eq!(
+            capacities,
+            vec![(6, Permill::from_percent(50)), (8, Permill::one())]
+        );
+    });
+}
@@ -22,15 +22,21 @@ use codec::{Decode, Encode, MaxEncodedLen};
 use scale_info::TypeInfo;
 
 use sp_inherents::InherentIdentifier;
-use sp_runtime::ConsensusEngineId;
+use sp_runtime::{ConsensusEngineId, RuntimeDebug};
 use sp_std::vec::Vec;
 
 /// The identifier for the inherent of poa pallet.
 pub const POA_INHERENT_IDENTIFIER: InherentIdentifier = *b"poaproof";
 
 /// The engine id for the Proof of Access consensus.
+///
+/// The seals using this engine id carry a [`LegacyProofOfAccess`], which are only
+/// decoded for the blocks authored before [`POA_ENGINE_ID_V2`] was introduced.
 pub const POA_ENGINE_ID: ConsensusEngineId = *b"POA:";
 
+/// The engine id for the Proof of Access consensus, the seal carries a [`ProofOfAccess`].
+pub const POA_ENGINE_ID_V2: ConsensusEngineId = *b"POA2";
+
 /// This struct includes the raw bytes of recall chunk as well as the chunk proof stuffs.
 #[derive(Clone, Eq, PartialEq, Encode, Decode, TypeInfo)]
 #[cfg_attr(feature = "std", derive(serde::Serialize, serde::Deserialize))]
@@ -101,6 +107,34 @@ pub struct ProofOfAccess {
     pub tx_path: Vec<Vec<u8>>,
     /// Proof of the recall chunk.
     pub chunk_proof: ChunkProof,
+    /// Encoded account of the miner, only used in [`MiningMode::Spora`].
+    pub miner: Option<Vec<u8>>,
+}
+
+/// The layout of [`ProofOfAccess`] before the miner field was introduced.
+///
+/// Kept for decoding the historical seals and inherents.
+#[derive(Debug, Clone, Eq, PartialEq, Encode, Decode, TypeInfo)]
+#[cfg_attr(feature = "std", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "std", serde(rename_all = "camelCase"))]
+pub struct LegacyProofOfAccess {
+    /// Number of trials when a valid `ProofOfAccess` created.
+    pub depth: u32,
+    /// Merkle path/proof of the recall tx.
+    pub tx_path: Vec<Vec<u8>>,
+    /// Proof of the recall chunk.
+    pub chunk_proof: ChunkProof,
+}
+
+impl From<LegacyProofOfAccess> for ProofOfAccess {
+    fn from(legacy: LegacyProofOfAccess) -> Self {
+        let LegacyProofOfAccess {
+            depth,
+            tx_path,
+            chunk_proof,
+        } = legacy;
+        Self::new(depth, tx_path, chunk_proof)
+    }
 }
 
 /// Errors that can occur while checking the validity of [`ProofOfAccess`].
@@ -116,6 +150,8 @@ pub enum PoaValidityError {
     TooLargeTxPath(u32, u32),
     /// Chunk path exceeds the maximum size specified in the config.
     TooLargeChunkPath(u32, u32),
+    /// Miner is required in the SPoRA mining mode.
+    MissingMiner,
 }
 
 #[cfg(not(feature = "std"))]
@@ -126,6 +162,7 @@ impl sp_std::fmt::Debug for PoaValidityError {
             Self::TooLargeDepth(_, _) => f.write_str("PoaValidityError::TooLargeDepth"),
             Self::TooLargeTxPath(_, _) => f.write_str("PoaValidityError::TooLargeTxPath"),
             Self::TooLargeChunkPath(_, _) => f.write_str("PoaValidityError::TooLargeChunkPath"),
+            Self::MissingMiner => f.write_str("PoaValidityError::MissingMiner"),
         }
     }
 }
@@ -137,9 +174,16 @@ impl ProofOfAccess {
             depth,
             tx_path,
             chunk_proof,
+            miner: None,
         }
     }
 
+    /// Sets the miner of [`ProofOfAccess`], required in [`MiningMode::Spora`].
+    pub fn with_miner(mut self, miner: Vec<u8>) -> Self {
+        self.miner = Some(miner);
+        self
+    }
+
     /// Returns the size of tx proof.
     pub fn tx_path_len(&self) -> usize {
         self.tx_path.iter().map(|x| x.len()).sum()
@@ -156,6 +200,7 @@ impl ProofOfAccess {
             max_depth,
             max_tx_path,
             max_chunk_path,
+            mining_mode,
         } = poa_config;
 
         if self.depth == 0 {
@@ -182,6 +227,10 @@ impl ProofOfAccess {
             ));
         }
 
+        if matches!(mining_mode, MiningMode::Spora(_)) && self.miner.is_none() {
+            return Err(PoaValidityError::MissingMiner);
+        }
+
         Ok(())
     }
 }
@@ -195,6 +244,11 @@ pub enum PoaOutcome {
     Skipped,
     /// Failed to create a valid [`ProofOfAccess`] due to the maximum depth limit has been reached.
     MaxDepthReached(u32),
+    /// Generate a [`LegacyProofOfAccess`] successfully.
+    ///
+    /// Only kept for decoding the inherents of historical blocks, new blocks
+    /// always use [`PoaOutcome::Justification`].
+    LegacyJustification(LegacyProofOfAccess),
     /// Generate a [`ProofOfAccess`] successfully.
     ///
     /// Each block contains a justification of poa as long as the weave
@@ -205,7 +259,10 @@ pub enum PoaOutcome {
 impl PoaOutcome {
     /// Returns true if the poa inherent must be included in the block.
     pub fn require_inherent(&self) -> bool {
-        matches!(self, Self::Justification(..))
+        matches!(
+            self,
+            Self::LegacyJustification(..) | Self::Justification(..)
+        )
     }
 }
 
@@ -216,6 +273,36 @@ const MAX_TX_PATH: u32 = 256 * 1024;
 /// Maximu byte size of chunk path 256 KiB.
 const MAX_CHUNK_PATH: u32 = 256 * 1024;
 
+/// Parameters of the SPoRA mining.
+#[derive(RuntimeDebug, Clone, Copy, Eq, PartialEq, Encode, Decode, MaxEncodedLen, TypeInfo)]
+pub struct SporaParams {
+    /// Byte size of the recall range from which the recall bytes of a miner are picked.
+    pub recall_range_size: u64,
+    /// Expected number of the attempts to find a solution of which the hash meets the
+    /// difficulty target, given all the data in the recall range is stored locally.
+    pub difficulty: u64,
+}
+
+/// Mining mode of the PoA consensus engine.
+#[derive(RuntimeDebug, Clone, Copy, Eq, PartialEq, Encode, Decode, MaxEncodedLen, TypeInfo)]
+pub enum MiningMode {
+    /// The recall byte is derived from the parent hash and depth only.
+    Classic,
+    /// Succinct Proofs of Random Access.
+    ///
+    /// The recall range is derived from the parent hash and the miner's account,
+    /// the depth is used as the nonce for picking a recall byte in the range, the
+    /// proof is valid only if the hash of the recall chunk with the nonce meets the
+    /// difficulty target.
+    Spora(SporaParams),
+}
+
+impl Default for MiningMode {
+    fn default() -> Self {
+        Self::Classic
+    }
+}
+
 /// Configuration of the PoA consensus engine.
 #[derive(Clone, Eq, PartialEq, Encode, Decode, MaxEncodedLen, TypeInfo)]
 pub struct PoaConfiguration {
@@ -225,6 +312,8 @@ pub struct PoaConfiguration {
     pub max_tx_path: u32,
     /// Maximum byte size of chunk merkle path.
     pub max_chunk_path: u32,
+    /// Mining mode of the PoA consensus.
+    pub mining_mode: MiningMode,
 }
 
 impl Default for PoaConfiguration {
@@ -233,6 +322,7 @@ impl Default for PoaConfiguration {
             max_depth: MAX_DEPTH,
             max_tx_path: MAX_TX_PATH,
             max_chunk_path: MAX_CHUNK_PATH,
+            mining_mode: MiningMode::Classic,
         }
     }
 }
@@ -243,7 +333,15 @@ impl PoaConfiguration {
         // TODO:
         // 1. upper limit check?
         // 2. more accurate check for the proof since the size of merkle proof has a lower bound?
-        self.max_depth > 0 && self.max_tx_path > 0 && self.max_chunk_path > 0
+        let mining_mode_ok = match self.mining_mode {
+            MiningMode::Classic => true,
+            MiningMode::Spora(SporaParams {
+                recall_range_size,
+                difficulty,
+            }) => recall_range_size > 0 && difficulty > 0,
+        };
+
+        self.max_depth > 0 && self.max_tx_path > 0 && self.max_chunk_path > 0 && mining_mode_ok
     }
 }
 
@@ -254,6 +352,7 @@ impl sp_std::fmt::Debug for PoaConfiguration {
             .field("max_depth", &self.max_depth)
             .field("max_tx_path", &self.max_tx_path)
             .field("max_chunk_path", &self.max_chunk_path)
+            .field("mining_mode", &self.mining_mode)
             .finish()
     }
 
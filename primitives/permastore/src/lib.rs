@@ -19,6 +19,7 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 #![allow(clippy::too_many_arguments)]
 
+use sp_core::H256;
 use sp_std::vec::Vec;
 
 /// 256B per chunk.
@@ -33,7 +34,161 @@ pub type Hasher = sp_core::Blake2Hasher;
 pub type TrieLayout = sp_trie::Layout<Hasher>;
 
 /// Error type of chunk proof verification.
-pub type VerifyError = sp_trie::VerifyError<sp_core::H256, sp_trie::Error>;
+pub type VerifyError = sp_trie::VerifyError<H256, sp_trie::Error>;
+
+/// Returns the hash of a data chunk, which is the leaf value in the chunk trie.
+pub fn chunk_hash(chunk: &[u8]) -> H256 {
+    H256(sp_core::hashing::blake2_256(chunk))
+}
+
+/// Returns the chunk root given the hashes of all data chunks in order.
+///
+/// The chunk root is the root of the trie in which the hash of each data chunk
+/// is keyed by the compact encoded chunk index.
+#[cfg(feature = "std")]
+pub fn chunk_root_of_hashes<I: IntoIterator<Item = H256>>(chunk_hashes: I) -> H256 {
+    use sp_trie::TrieConfiguration;
+
+    TrieLayout::ordered_trie_root(chunk_hashes)
+}
+
+/// Returns the chunk root of the entire transaction data.
+///
+/// Use [`ChunkRootBuilder`] if the data can not be held in memory at once.
+#[cfg(feature = "std")]
+pub fn chunk_root(data: &[u8]) -> H256 {
+    chunk_root_of_hashes(data.chunks(CHUNK_SIZE as usize).map(chunk_hash))
+}
+
+/// Verifies that `chunk` is the chunk `chunk_index` of the transaction data of
+/// `chunk_root` given the trie `proof`.
+#[cfg(feature = "std")]
+pub fn verify_chunk_proof(
+    chunk_root: &H256,
+    chunk: &[u8],
+    chunk_index: u32,
+    proof: &[Vec<u8>],
+) -> Result<(), VerifyError> {
+    use codec::Encode;
+
+    sp_trie::verify_trie_proof::<TrieLayout, _, _, _>(
+        chunk_root,
+        proof,
+        &[(
+            codec::Compact(chunk_index).encode(),
+            Some(chunk_hash(chunk)),
+        )],
+    )
+}
+
+/// Generates the trie proof of chunk `chunk_index` given the hashes of all data
+/// chunks in order.
+///
+/// Use [`ChunkTrie`] to generate the proofs of many chunks of the same data.
+#[cfg(feature = "std")]
+pub fn generate_chunk_proof<I: IntoIterator<Item = H256>>(
+    chunk_hashes: I,
+    chunk_index: u32,
+) -> Result<Vec<Vec<u8>>, Box<sp_trie::TrieError<TrieLayout>>> {
+    ChunkTrie::new(chunk_hashes)?.generate_proof(chunk_index)
+}
+
+/// The chunk trie of transaction data held in memory, from which the proof of any
+/// chunk can be generated without rebuilding the trie.
+#[cfg(feature = "std")]
+pub struct ChunkTrie {
+    db: sp_trie::MemoryDB<Hasher>,
+    chunk_root: H256,
+}
+
+#[cfg(feature = "std")]
+impl ChunkTrie {
+    /// Builds the chunk trie given the hashes of all data chunks in order.
+    pub fn new<I: IntoIterator<Item = H256>>(
+        chunk_hashes: I,
+    ) -> Result<Self, Box<sp_trie::TrieError<TrieLayout>>> {
+        use codec::Encode;
+        use sp_trie::TrieMut;
+
+        let mut db = sp_trie::MemoryDB::<Hasher>::default();
+        let mut chunk_root = sp_trie::empty_trie_root::<TrieLayout>();
+
+        {
+            let mut trie = sp_trie::TrieDBMut::<TrieLayout>::new(&mut db, &mut chunk_root);
+            for (index, chunk_hash) in chunk_hashes.into_iter().enumerate() {
+                trie.insert(
+                    &codec::Compact(index as u32).encode(),
+                    chunk_hash.as_bytes(),
+                )?;
+            }
+            trie.commit();
+        }
+
+        Ok(Self { db, chunk_root })
+    }
+
+    /// Returns the chunk root.
+    pub fn chunk_root(&self) -> H256 {
+        self.chunk_root
+    }
+
+    /// Generates the trie proof of chunk `chunk_index`.
+    pub fn generate_proof(
+        &self,
+        chunk_index: u32,
+    ) -> Result<Vec<Vec<u8>>, Box<sp_trie::TrieError<TrieLayout>>> {
+        use codec::Encode;
+
+        sp_trie::generate_trie_proof::<TrieLayout, _, _, _>(
+            &self.db,
+            self.chunk_root,
+            &[codec::Compact(chunk_index).encode()],
+        )
+    }
+}
+
+/// A builder for computing the chunk root of transaction data fed piece by piece.
+///
+/// At most one chunk of data is buffered in the builder, the result is the same
+/// as [`chunk_root`] of the concatenation of all the pieces.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Default)]
+pub struct ChunkRootBuilder {
+    /// Hashes of the complete chunks so far.
+    chunk_hashes: Vec<H256>,
+    /// Data of the incomplete chunk.
+    buffer: Vec<u8>,
+}
+
+#[cfg(feature = "std")]
+impl ChunkRootBuilder {
+    /// Creates a new instance of [`ChunkRootBuilder`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds the next piece of transaction data.
+    pub fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            let len = (CHUNK_SIZE as usize - self.buffer.len()).min(data.len());
+            self.buffer.extend_from_slice(&data[..len]);
+            data = &data[len..];
+            if self.buffer.len() == CHUNK_SIZE as usize {
+                self.chunk_hashes.push(chunk_hash(&self.buffer));
+                self.buffer.clear();
+            }
+        }
+    }
+
+    /// Returns the hashes of all data chunks fed so far and the chunk root.
+    pub fn finish(mut self) -> (Vec<H256>, H256) {
+        if !self.buffer.is_empty() {
+            self.chunk_hashes.push(chunk_hash(&self.buffer));
+        }
+        let chunk_root = chunk_root_of_hashes(self.chunk_hashes.iter().copied());
+        (self.chunk_hashes, chunk_root)
+    }
+}
 
 /// Low level APIs for manipulating the persistent transaction data storage.
 /// No data validation performed.
@@ -53,8 +208,43 @@ pub trait PermaStorage: Send + Sync {
     }
 }
 
+#[cfg(feature = "std")]
+sp_externalities::decl_extension! {
+    /// The permastore extension to retrieve the transaction data stored locally.
+    pub struct PermastoreExt(Box<dyn PermaStorage>);
+}
+
+#[cfg(feature = "std")]
+impl PermastoreExt {
+    /// Creates a new instance of [`PermastoreExt`].
+    pub fn new<S: PermaStorage + 'static>(storage: S) -> Self {
+        Self(Box::new(storage))
+    }
+}
+
+/// Interfaces for accessing the permanent storage of node from the runtime.
+#[sp_runtime_interface::runtime_interface]
+pub trait Permastore {
+    /// Returns `true` if the transaction data of `chunk_root` has been stored locally.
+    ///
+    /// The local storage is only accessible when the [`PermastoreExt`] is registered,
+    /// which is the case for the transaction pool validation. Always returns `true`
+    /// otherwise, e.g., in block import, so that the block execution does not depend
+    /// on the local state of node.
+    fn stored_locally(&mut self, chunk_root: &[u8]) -> bool {
+        use sp_externalities::ExternalitiesExt;
+
+        self.extension::<PermastoreExt>()
+            .map(|ext| ext.exists(chunk_root))
+            .unwrap_or(true)
+    }
+}
+
 sp_api::decl_runtime_apis! {
     /// The permastore API.
+    ///
+    /// Version 2 adds `locate_recall_tx`, `weave_offsets` and `is_forgotten`.
+    #[api_version(2)]
     pub trait PermastoreApi<BlockNumber, ExtrinsicIndex, Hash> where
         BlockNumber: codec::Codec,
         ExtrinsicIndex: codec::Codec,
@@ -66,9 +256,21 @@ sp_api::decl_runtime_apis! {
         /// Returns the number of block in which the recall byte is included.
         fn find_recall_block(recall_byte: u64) -> Option<BlockNumber>;
 
+        /// Returns `(block_number, extrinsic_index, tx_weave_base)` of the transaction
+        /// in which the recall byte is included.
+        fn locate_recall_tx(recall_byte: u64) -> Option<(BlockNumber, ExtrinsicIndex, u64)>;
+
         /// Returns the size of transaction data given `block_number` and `extrinsic_index`.
         fn data_size(block_number: BlockNumber, extrinsic_index: ExtrinsicIndex) -> u32;
 
+        /// Returns `[(extrinsic_index, tx_weave_base)]` of the transactions storing data
+        /// in block `block_number`, in the order of extrinsic index.
+        fn weave_offsets(block_number: BlockNumber) -> Vec<(ExtrinsicIndex, u64)>;
+
+        /// Returns `true` if the transaction data given `block_number` and `extrinsic_index`
+        /// has been forgotten.
+        fn is_forgotten(block_number: BlockNumber, extrinsic_index: ExtrinsicIndex) -> bool;
+
         /// Returns `true` if the proof of access is required for the block.
         fn require_proof_of_access() -> bool;
 
@@ -78,4 +280,66 @@ sp_api::decl_runtime_apis! {
         /// Returns the size of entire weave.
         fn weave_size() -> u64;
     }
+
+    /// The permastore fee API.
+    pub trait PermastoreFeeApi<Balance> where
+        Balance: codec::Codec,
+    {
+        /// Returns the perpetual storage fee of storing `data_size` bytes.
+        fn estimate_storage_fee(data_size: u32) -> Balance;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunk_root_builder_should_agree_with_chunk_root() {
+        let data = (0..CHUNK_SIZE * 3 + 10)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        let expected = chunk_root(&data);
+
+        for &piece_size in &[1000, CHUNK_SIZE as usize, CHUNK_SIZE as usize + 1] {
+            let mut builder = ChunkRootBuilder::new();
+            for piece in data.chunks(piece_size) {
+                builder.update(piece);
+            }
+            let (chunk_hashes, chunk_root) = builder.finish();
+            assert_eq!(chunk_hashes.len(), 4);
+            assert_eq!(chunk_root, expected);
+            assert_eq!(chunk_root_of_hashes(chunk_hashes), expected);
+        }
+
+        assert_eq!(ChunkRootBuilder::new().finish().1, chunk_root(&[]));
+    }
+
+    #[test]
+    fn generated_chunk_proof_should_verify() {
+        let data = (0..CHUNK_SIZE * 2 + 10)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        let chunk_root = chunk_root(&data);
+        let chunks = data.chunks(CHUNK_SIZE as usize).collect::<Vec<_>>();
+        let chunk_hashes = chunks
+            .iter()
+            .map(|chunk| chunk_hash(chunk))
+            .collect::<Vec<_>>();
+
+        for (chunk_index, chunk) in chunks.iter().enumerate() {
+            let chunk_index = chunk_index as u32;
+            let proof = generate_chunk_proof(chunk_hashes.clone(), chunk_index).unwrap();
+            assert!(verify_chunk_proof(&chunk_root, chunk, chunk_index, &proof).is_ok());
+            assert!(verify_chunk_proof(&chunk_root, chunk, chunk_index + 1, &proof).is_err());
+        }
+
+        let chunk_trie = ChunkTrie::new(chunk_hashes).unwrap();
+        assert_eq!(chunk_trie.chunk_root(), chunk_root);
+        for (chunk_index, chunk) in chunks.iter().enumerate() {
+            let chunk_index = chunk_index as u32;
+            let proof = chunk_trie.generate_proof(chunk_index).unwrap();
+            assert!(verify_chunk_proof(&chunk_root, chunk, chunk_index, &proof).is_ok());
+        }
+    }
 }
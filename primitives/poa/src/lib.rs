@@ -19,12 +19,25 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 #![allow(clippy::too_many_arguments)]
 
+use sp_runtime::Permill;
+use sp_std::vec::Vec;
+
 pub use cp_consensus_poa::PoaConfiguration;
 
 sp_api::decl_runtime_apis! {
     /// The poa API.
-    pub trait PoaApi {
+    pub trait PoaApi<AccountId> where
+        AccountId: codec::Codec,
+    {
         /// Returns the configuration of PoA consensus.
         fn poa_config() -> PoaConfiguration;
+
+        /// Returns the estimated storage capacity of `who`.
+        ///
+        /// `None` if `who` has never authored a block with a valid proof of access.
+        fn storage_capacity(who: AccountId) -> Option<Permill>;
+
+        /// Returns the estimated storage capacities of all the block authors so far.
+        fn all_capacities() -> Vec<(AccountId, Permill)>;
     }
 }
@@ -33,7 +33,7 @@
 
 use std::sync::Arc;
 
-use sc_client_api::AuxStore;
+use sc_client_api::{AuxStore, BlockchainEvents, StorageProvider};
 use sc_consensus_babe::{Config, Epoch};
 use sc_consensus_babe_rpc::BabeRpcHandler;
 use sc_consensus_epochs::SharedEpochChanges;
@@ -107,6 +107,8 @@ pub struct FullDeps<C, P, SC, B, S> {
     pub grandpa: GrandpaDeps<B>,
     /// permanent storage
     pub perma_storage: S,
+    /// Progress of backfilling the historical transaction data.
+    pub backfill: cc_network::SharedBackfillProgress,
 }
 
 /// A IO handler that uses all Full RPC extensions.
@@ -120,6 +122,8 @@ pub fn create_full<C, P, SC, B, S, A>(
 where
     C: ProvideRuntimeApi<Block>
         + HeaderBackend<Block>
+        + BlockchainEvents<Block>
+        + StorageProvider<Block, B>
         + AuxStore
         + HeaderMetadata<Block, Error = BlockChainError>
         + Sync
@@ -129,12 +133,14 @@ where
     C::Api: pallet_transaction_payment_rpc::TransactionPaymentRuntimeApi<Block, Balance>,
     C::Api: BabeApi<Block>,
     C::Api: BlockBuilder<Block>,
+    C::Api: cp_permastore::PermastoreApi<Block, BlockNumber, u32, Hash>,
+    C::Api: cp_poa::PoaApi<Block, AccountId>,
     P: TransactionPool + 'static,
     <P as TransactionPool>::Hash: serde::de::DeserializeOwned,
     SC: SelectChain<Block> + 'static,
     B: sc_client_api::Backend<Block> + Send + Sync + 'static,
     B::State: sc_client_api::backend::StateBackend<sp_runtime::traits::HashFor<Block>>,
-    S: cp_permastore::PermaStorage + 'static,
+    S: cc_datastore::ChunkStorage + cc_datastore::TransactionDataBackend<Block> + Clone + 'static,
     A: sc_rpc_api::author::AuthorApi<
         sc_transaction_pool_api::TxHash<P>,
         <Block as sp_runtime::traits::Block>::Hash,
@@ -154,6 +160,7 @@ where
         babe,
         grandpa,
         perma_storage,
+        backfill,
     } = deps;
 
     let BabeDeps {
@@ -192,7 +199,7 @@ where
             shared_authority_set.clone(),
             shared_voter_state,
             justification_stream,
-            subscription_executor,
+            subscription_executor.clone(),
             finality_provider,
         ),
     ));
@@ -200,20 +207,37 @@ where
     io.extend_with(sc_sync_state_rpc::SyncStateRpcApi::to_delegate(
         sc_sync_state_rpc::SyncStateRpcHandler::new(
             chain_spec,
-            client,
+            client.clone(),
             shared_authority_set,
             shared_epoch_changes,
             deny_unsafe,
         )?,
     ));
 
+    io.extend_with(
+        cc_rpc_api::permastore::PermastoreSubscriptionApi::to_delegate(
+            cc_rpc::permastore::PermastoreSubscription::<
+                Block,
+                _,
+                B,
+                _,
+                canyon_runtime::Runtime,
+            >::new(client.clone(), perma_storage.clone(), subscription_executor),
+        ),
+    );
+
     io.extend_with(cc_rpc_api::permastore::PermastoreApi::to_delegate(
         cc_rpc::permastore::Permastore::<_, _, _, Block>::new(
             perma_storage,
             pool,
             author,
             deny_unsafe,
-        ),
+        )
+        .with_backfill(backfill),
+    ));
+
+    io.extend_with(cc_rpc_api::poa::PoaApi::<Hash, AccountId>::to_delegate(
+        cc_rpc::poa::Poa::<_, Block>::new(client),
     ));
 
     Ok(io)
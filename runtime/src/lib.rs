@@ -117,10 +117,10 @@ pub const VERSION: RuntimeVersion = RuntimeVersion {
     spec_name: create_runtime_str!("canyon"),
     impl_name: create_runtime_str!("canyon-node"),
     authoring_version: 0,
-    spec_version: 0,
+    spec_version: 1,
     impl_version: 0,
     apis: RUNTIME_API_VERSIONS,
-    transaction_version: 0,
+    transaction_version: 1,
 };
 
 /// The BABE epoch configuration at genesis.
@@ -852,13 +852,33 @@ impl pallet_sudo::Config for Runtime {
 parameter_types! {
     /// 1GiB
     pub const MaxDataSize: u32 = 1024 * 1024 * 1024;
+    /// About $0.005 per GiB per year.
+    pub const BaseStorageCost: Balance = MILLICENTS / 2_000_000;
+    /// 0.5% per year.
+    pub const StorageCostDeclineRate: Perbill = Perbill::from_parts(5_000_000);
+    pub const DiscountRate: Perbill = Perbill::from_percent(1);
+    pub const PermastorePalletId: PalletId = PalletId(*b"py/prmst");
+    /// 0.0000002% per block at most, i.e., about 1.05% per year with
+    /// 365 * DAYS = 5_256_000 blocks (1 - (1 - 2e-9)^5_256_000 ~= 0.0105),
+    /// which is in line with the `DiscountRate` the storage fee is priced at.
+    /// The endowment is halved in about 66 years (ln(2) / 0.0105).
+    pub const EndowmentRelease: Perbill = Perbill::from_parts(2);
+    pub const RefundPeriod: BlockNumber = 30 * DAYS;
+    pub const MaxRefund: Perbill = Perbill::from_percent(50);
 }
 
 impl pallet_permastore::Config for Runtime {
     type Event = Event;
     type Currency = Balances;
-    type TreasuryPalletId = TreasuryModuleId;
+    type PalletId = PermastorePalletId;
+    type EndowmentRelease = EndowmentRelease;
+    type StorageCapacity = Self;
+    type RefundPeriod = RefundPeriod;
+    type MaxRefund = MaxRefund;
     type MaxDataSize = MaxDataSize;
+    type BaseStorageCost = BaseStorageCost;
+    type StorageCostDeclineRate = StorageCostDeclineRate;
+    type DiscountRate = DiscountRate;
     type WeightInfo = pallet_permastore::weights::SubstrateWeight<Runtime>;
 }
 
@@ -868,6 +888,16 @@ impl pallet_poa::BlockAuthor<AccountId> for Runtime {
     }
 }
 
+impl pallet_permastore::StorageCapacity<AccountId> for Runtime {
+    fn author() -> AccountId {
+        Authorship::author()
+    }
+
+    fn storage_capacity(who: &AccountId) -> Option<Permill> {
+        Poa::storage_capacity(who)
+    }
+}
+
 impl pallet_poa::Config for Runtime {
     type Event = Event;
     type BlockAuthor = Self;
@@ -917,6 +947,7 @@ where
             frame_system::CheckNonce::<Runtime>::from(nonce),
             frame_system::CheckWeight::<Runtime>::new(),
             pallet_transaction_payment::ChargeTransactionPayment::<Runtime>::from(tip),
+            pallet_permastore::CheckStore::<Runtime>::new(),
         );
         let raw_payload = SignedPayload::new(call, extra)
             .map_err(|e| {
@@ -1167,7 +1198,7 @@ construct_runtime!(
         Lottery: pallet_lottery::{Pallet, Call, Storage, Event<T>} = 31,
         Gilt: pallet_gilt::{Pallet, Call, Storage, Event<T>, Config} = 32,
 
-        Permastore: pallet_permastore::{Pallet, Call, Storage, Event<T>} = 33,
+        Permastore: pallet_permastore::{Pallet, Call, Storage, Config, Event<T>} = 33,
         Poa: pallet_poa::{Pallet, Call, Storage, Inherent, Event<T>} = 34,
 
         Sudo: pallet_sudo::{Pallet, Call, Config<T>, Storage, Event<T>} = 35,
@@ -1199,6 +1230,7 @@ pub type SignedExtra = (
     frame_system::CheckNonce<Runtime>,
     frame_system::CheckWeight<Runtime>,
     pallet_transaction_payment::ChargeTransactionPayment<Runtime>,
+    pallet_permastore::CheckStore<Runtime>,
 );
 /// Unchecked extrinsic type as expected by this runtime.
 pub type UncheckedExtrinsic = generic::UncheckedExtrinsic<Address, Call, Signature, SignedExtra>;
@@ -1403,9 +1435,18 @@ impl_runtime_apis! {
         fn find_recall_block(recall_byte: u64) -> Option<BlockNumber> {
             Permastore::find_recall_block(recall_byte)
         }
+        fn locate_recall_tx(recall_byte: u64) -> Option<(BlockNumber, u32, u64)> {
+            Permastore::locate_recall_tx(recall_byte)
+        }
         fn data_size(block_number: BlockNumber, extrinsic_index: u32) -> u32 {
             Permastore::data_size(block_number, extrinsic_index)
         }
+        fn weave_offsets(block_number: BlockNumber) -> Vec<(u32, u64)> {
+            Permastore::weave_offsets(block_number)
+        }
+        fn is_forgotten(block_number: BlockNumber, extrinsic_index: u32) -> bool {
+            Permastore::is_forgotten(block_number, extrinsic_index)
+        }
         fn require_proof_of_access() -> bool {
             Permastore::require_proof_of_access()
         }
@@ -1417,10 +1458,22 @@ impl_runtime_apis! {
         }
     }
 
-    impl cp_poa::PoaApi<Block> for Runtime {
+    impl cp_permastore::PermastoreFeeApi<Block, Balance> for Runtime {
+        fn estimate_storage_fee(data_size: u32) -> Balance {
+            Permastore::calculate_storage_fee(data_size)
+        }
+    }
+
+    impl cp_poa::PoaApi<Block, AccountId> for Runtime {
         fn poa_config() -> cp_poa::PoaConfiguration {
             Poa::poa_config()
         }
+        fn storage_capacity(who: AccountId) -> Option<Permill> {
+            Poa::storage_capacity(&who)
+        }
+        fn all_capacities() -> Vec<(AccountId, Permill)> {
+            Poa::all_capacities()
+        }
     }
 
     #[cfg(feature = "try-runtime")]